@@ -0,0 +1,57 @@
+//! Convert a PCM WAV file to IMA ADPCM for the firmware,
+//! mixed down to mono at the same sample rate. Runs on the
+//! build host:
+//!
+//!     cargo adpcm in.wav out.wav [block-align]
+
+use std::process::exit;
+
+use mb2_audio::{adpcm, wav::Wav, Sample};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !(2..=3).contains(&args.len()) {
+        eprintln!("usage: adpcm <in.wav> <out.wav> [block-align]");
+        exit(2);
+    }
+    let block_align = match args.get(2).map(|s| s.parse()) {
+        None => adpcm::DEFAULT_BLOCK_ALIGN,
+        Some(Ok(n)) => n,
+        Some(Err(e)) => {
+            eprintln!("{}: {}", args[2], e);
+            exit(2);
+        }
+    };
+    let input = std::fs::read(&args[0]).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[0], e);
+        exit(1);
+    });
+    let wav = Wav::parse(&input).unwrap_or_else(|e| {
+        eprintln!("{}: byte {}: {:?}", args[0], e.pos, e.kind);
+        exit(1);
+    });
+    let samples: Vec<Sample> = wav.samples().collect();
+    let mut output = vec![];
+    adpcm::encode_wav(&samples, wav.sample_rate(), block_align, |bytes| {
+        output.extend(bytes)
+    })
+    .unwrap_or_else(|e| {
+        eprintln!("{:?}", e);
+        exit(2);
+    });
+    if let Err(e) = std::fs::write(&args[1], &output) {
+        eprintln!("{}: {}", args[1], e);
+        exit(1);
+    }
+    println!(
+        "{}: {:?}, {} channel(s), {}Hz, {}ms, {} bytes -> {}: {} bytes",
+        args[0],
+        wav.encoding(),
+        wav.channels(),
+        wav.sample_rate(),
+        wav.duration_ms(),
+        input.len(),
+        args[1],
+        output.len(),
+    );
+}
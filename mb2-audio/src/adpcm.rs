@@ -0,0 +1,514 @@
+//! IMA ADPCM.
+//!
+//! IMA (or DVI) ADPCM stores each sample as a 4-bit code: the
+//! difference from the previous sample, in units of a step
+//! size that grows when the differences are large and
+//! shrinks when they are small. That is a quarter of the
+//! space of 16-bit PCM, and decoding needs only shifts and
+//! adds.
+//!
+//! In a WAV file (format tag [FORMAT_IMA_ADPCM]) the codes
+//! come in blocks of a fixed number of bytes, each of which
+//! can be decoded on its own. A block starts with a
+//! four-byte header for each channel: the first sample in
+//! full (16-bit little-endian), the step index, and a zero
+//! byte. The codes of the other samples follow, two to a
+//! byte with the earlier one in the low nibble, the channels
+//! taking turns four bytes (eight samples) at a time.
+//!
+//! [encode_wav] writes mono WAV files in this format; the
+//! `adpcm` example uses it to convert clips on the build
+//! host.
+
+use crate::Sample;
+
+/// WAV `fmt ` format tag for IMA ADPCM.
+pub const FORMAT_IMA_ADPCM: u16 = 0x11;
+
+/// Block length used by common encoders for mono at 8kHz to
+/// 11kHz: 505 samples.
+pub const DEFAULT_BLOCK_ALIGN: usize = 256;
+
+/// Step sizes, by step index.
+static STEPS: [i16; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408,
+    449, 494, 544, 598, 658, 724, 796, 876, 963, 1_060, 1_166, 1_282, 1_411, 1_552, 1_707, 1_878,
+    2_066, 2_272, 2_499, 2_749, 3_024, 3_327, 3_660, 4_026, 4_428, 4_871, 5_358, 5_894, 6_484,
+    7_132, 7_845, 8_630, 9_493, 10_442, 11_487, 12_635, 13_899, 15_289, 16_818, 18_500, 20_350,
+    22_385, 24_623, 27_086, 29_794, 32_767,
+];
+
+/// Largest step index.
+pub const MAX_INDEX: u8 = STEPS.len() as u8 - 1;
+
+/// Change in step index after each code, by its magnitude.
+const INDEX_CHANGES: [i8; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+/// Decodes a stream of codes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Decoder {
+    predictor: Sample,
+    index: u8,
+}
+
+impl Decoder {
+    /// Start from sample `predictor` with step index
+    /// `index`, which is limited to [MAX_INDEX].
+    pub fn new(predictor: Sample, index: u8) -> Self {
+        Self {
+            predictor,
+            index: index.min(MAX_INDEX),
+        }
+    }
+
+    /// The last sample decoded.
+    pub fn predictor(&self) -> Sample {
+        self.predictor
+    }
+
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Decode the low four bits of `code`.
+    pub fn decode(&mut self, code: u8) -> Sample {
+        let step = STEPS[self.index as usize] as i32;
+        let mut diff = step >> 3;
+        if code & 4 != 0 {
+            diff += step;
+        }
+        if code & 2 != 0 {
+            diff += step >> 1;
+        }
+        if code & 1 != 0 {
+            diff += step >> 2;
+        }
+        let predictor = if code & 8 != 0 {
+            self.predictor as i32 - diff
+        } else {
+            self.predictor as i32 + diff
+        };
+        self.predictor = predictor.clamp(Sample::MIN as i32, Sample::MAX as i32) as Sample;
+        let index = self.index as i32 + INDEX_CHANGES[(code & 7) as usize] as i32;
+        self.index = index.clamp(0, MAX_INDEX as i32) as u8;
+        self.predictor
+    }
+}
+
+/// Encodes samples as codes. Its state follows a [Decoder]
+/// exactly, so decoding gives back what it predicts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Encoder {
+    decoder: Decoder,
+}
+
+impl Encoder {
+    /// Start from sample `predictor` with step index
+    /// `index`, as a decoder of the codes will.
+    pub fn new(predictor: Sample, index: u8) -> Self {
+        Self {
+            decoder: Decoder::new(predictor, index),
+        }
+    }
+
+    /// The state a decoder will have after the codes so far.
+    pub fn decoder(&self) -> Decoder {
+        self.decoder
+    }
+
+    /// The code for `sample`, in the low four bits.
+    pub fn encode(&mut self, sample: Sample) -> u8 {
+        let mut step = STEPS[self.decoder.index as usize] as i32;
+        let mut diff = sample as i32 - self.decoder.predictor as i32;
+        let mut code = 0;
+        if diff < 0 {
+            code = 8;
+            diff = -diff;
+        }
+        for bit in [4, 2, 1] {
+            if diff >= step {
+                code |= bit;
+                diff -= step;
+            }
+            step >>= 1;
+        }
+        self.decoder.decode(code);
+        code
+    }
+}
+
+/// Samples of each channel in a WAV block of `block_len`
+/// bytes, which may be a short last block. Zero if it is
+/// too short for its headers.
+pub fn block_samples(block_len: usize, channels: usize) -> usize {
+    let Some(codes) = block_len.checked_sub(4 * channels) else {
+        return 0;
+    };
+    if channels == 1 {
+        1 + 2 * codes
+    } else {
+        // Only whole rounds of the channels' turns.
+        1 + codes / (4 * channels) * 8
+    }
+}
+
+/// Samples of each channel in `data_len` bytes of blocks of
+/// `block_align` bytes holding at most `samples_per_block`.
+pub fn frames(
+    data_len: usize,
+    block_align: usize,
+    channels: usize,
+    samples_per_block: usize,
+) -> usize {
+    let whole = data_len / block_align * samples_per_block;
+    let rest = block_samples(data_len % block_align, channels).min(samples_per_block);
+    whole + rest
+}
+
+/// Iterator over the samples of the blocks of a WAV `data`
+/// chunk, mixed down to mono.
+#[derive(Debug, Clone)]
+pub struct WavSamples<'a> {
+    blocks: core::slice::Chunks<'a, u8>,
+    channels: usize,
+    samples_per_block: usize,
+    block: &'a [u8],
+    decoders: [Decoder; 2],
+    /// Next sample of the block, and the number it holds.
+    next: usize,
+    len: usize,
+    /// Samples left, including this block's.
+    left: usize,
+}
+
+impl<'a> WavSamples<'a> {
+    /// Read the first `frames` samples of `data`, in blocks
+    /// of `block_align` bytes with `channels` channels. The
+    /// format must have been checked.
+    pub fn new(
+        data: &'a [u8],
+        block_align: usize,
+        channels: usize,
+        samples_per_block: usize,
+        frames: usize,
+    ) -> Self {
+        Self {
+            blocks: data.chunks(block_align),
+            channels,
+            samples_per_block,
+            block: &[],
+            decoders: [Decoder::default(); 2],
+            next: 0,
+            len: 0,
+            left: frames,
+        }
+    }
+
+    /// The code for sample `n` of the block on `channel`.
+    fn code(&self, channel: usize, n: usize) -> u8 {
+        let n = n - 1;
+        let round = n / 8 * 4 * self.channels;
+        let byte = self.block[4 * self.channels + round + 4 * channel + n % 8 / 2];
+        if n.is_multiple_of(2) {
+            byte & 0xf
+        } else {
+            byte >> 4
+        }
+    }
+}
+
+impl Iterator for WavSamples<'_> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.left == 0 {
+            return None;
+        }
+        if self.next == self.len {
+            self.block = self.blocks.next()?;
+            self.len = block_samples(self.block.len(), self.channels).min(self.samples_per_block);
+            self.next = 0;
+            if self.len == 0 {
+                self.left = 0;
+                return None;
+            }
+        }
+        let mut sum = 0;
+        for channel in 0..self.channels {
+            let sample = if self.next == 0 {
+                let header = &self.block[4 * channel..];
+                let predictor = i16::from_le_bytes([header[0], header[1]]);
+                self.decoders[channel] = Decoder::new(predictor, header[2]);
+                predictor
+            } else {
+                let code = self.code(channel, self.next);
+                self.decoders[channel].decode(code)
+            };
+            sum += sample as i32;
+        }
+        self.next += 1;
+        self.left -= 1;
+        Some((sum / self.channels as i32) as Sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+
+impl ExactSizeIterator for WavSamples<'_> {}
+
+/// What went wrong encoding a WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError {
+    /// Blocks must be a whole number of four-byte words, and
+    /// longer than their header.
+    UnsupportedBlockAlign(usize),
+}
+
+/// Length of the header [encode_wav] writes before the
+/// blocks.
+const HEADER_LEN: usize = 60;
+
+/// Length in bytes of `frames` mono samples as written by
+/// [encode_wav] in blocks of `block_align` bytes.
+pub fn wav_len(frames: usize, block_align: usize) -> usize {
+    let per_block = block_samples(block_align, 1);
+    let last = frames % per_block;
+    let last_len = if last == 0 { 0 } else { 4 + last / 2 };
+    HEADER_LEN + frames / per_block * block_align + last_len
+}
+
+/// Encode `samples`, mono at `sample_rate`, as an IMA ADPCM
+/// WAV file in blocks of `block_align` bytes, handing it to
+/// `write` a piece at a time. The last block is cut short
+/// after its last sample, and a `fact` chunk gives the exact
+/// number of samples.
+pub fn encode_wav(
+    samples: &[Sample],
+    sample_rate: u32,
+    block_align: usize,
+    mut write: impl FnMut(&[u8]),
+) -> Result<(), EncodeError> {
+    if block_align < 8 || !block_align.is_multiple_of(4) || block_align > u16::MAX as usize {
+        return Err(EncodeError::UnsupportedBlockAlign(block_align));
+    }
+    let per_block = block_samples(block_align, 1);
+    let data_len = wav_len(samples.len(), block_align) - HEADER_LEN;
+    let byte_rate = (sample_rate as u64 * block_align as u64 / per_block as u64) as u32;
+    let mut header = [0; HEADER_LEN];
+    let fields: [&[u8]; 18] = [
+        b"RIFF",
+        &((HEADER_LEN - 8 + data_len + data_len % 2) as u32).to_le_bytes(),
+        b"WAVE",
+        b"fmt ",
+        &20u32.to_le_bytes(),
+        &FORMAT_IMA_ADPCM.to_le_bytes(),
+        &1u16.to_le_bytes(),
+        &sample_rate.to_le_bytes(),
+        &byte_rate.to_le_bytes(),
+        &(block_align as u16).to_le_bytes(),
+        &4u16.to_le_bytes(),
+        // Extra format bytes: the samples per block.
+        &2u16.to_le_bytes(),
+        &(per_block as u16).to_le_bytes(),
+        b"fact",
+        &4u32.to_le_bytes(),
+        &(samples.len() as u32).to_le_bytes(),
+        b"data",
+        &(data_len as u32).to_le_bytes(),
+    ];
+    let mut pos = 0;
+    for field in fields {
+        header[pos..pos + field.len()].copy_from_slice(field);
+        pos += field.len();
+    }
+    write(&header);
+
+    let mut index = 0;
+    let mut block = [0; 4];
+    for chunk in samples.chunks(per_block) {
+        let first = chunk[0];
+        let mut encoder = Encoder::new(first, index);
+        block[..2].copy_from_slice(&first.to_le_bytes());
+        block[2] = index;
+        block[3] = 0;
+        write(&block);
+        for pair in chunk[1..].chunks(2) {
+            let low = encoder.encode(pair[0]);
+            let high = pair.get(1).map_or(0, |&s| encoder.encode(s));
+            write(&[low | high << 4]);
+        }
+        index = encoder.decoder().index();
+    }
+    if data_len % 2 == 1 {
+        write(&[0]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::{Encoding, Wav};
+
+    /// A chirp with some noise, 16-bit full scale.
+    fn test_signal(len: usize) -> Vec<Sample> {
+        let mut x = 1u32;
+        (0..len)
+            .map(|i| {
+                let t = i as f64 / 8_000.0;
+                let tone = (2.0 * std::f64::consts::PI * (200.0 + 1_500.0 * t) * t).sin();
+                x = x.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let noise = (x >> 16) as i16 as f64 / 32_768.0;
+                (30_000.0 * tone + 2_000.0 * noise).clamp(-32_768.0, 32_767.0) as Sample
+            })
+            .collect()
+    }
+
+    fn encode(samples: &[Sample], block_align: usize) -> Vec<u8> {
+        let mut file = vec![];
+        encode_wav(samples, 8_000, block_align, |bytes| file.extend(bytes)).unwrap();
+        file
+    }
+
+    /// What the encoder reconstructs, block by block.
+    fn reconstruct(samples: &[Sample], block_align: usize) -> Vec<Sample> {
+        let mut index = 0;
+        let mut out = vec![];
+        for chunk in samples.chunks(block_samples(block_align, 1)) {
+            let mut encoder = Encoder::new(chunk[0], index);
+            out.push(chunk[0]);
+            for &s in &chunk[1..] {
+                encoder.encode(s);
+                out.push(encoder.decoder().predictor());
+            }
+            index = encoder.decoder().index();
+        }
+        out
+    }
+
+    // From Python's audioop.lin2adpcm, an independent
+    // implementation, starting from silence at index 0.
+    const REFERENCE_INPUT: [Sample; 16] = [
+        0, 1_000, 3_000, 6_000, 10_000, 15_000, 20_000, 25_000, 32_767, 20_000, 0, -20_000,
+        -32_768, -32_768, -5_000, 100,
+    ];
+    const REFERENCE_CODES: [u8; 16] = [0, 7, 7, 7, 7, 7, 7, 7, 7, 7, 14, 14, 10, 0, 5, 0];
+    const REFERENCE_OUTPUT: [Sample; 16] = [
+        0, 11, 41, 104, 240, 533, 1_164, 2_521, 5_431, 11_667, 78, -20_453, -32_768, -30_225,
+        -4_788, -1_403,
+    ];
+
+    #[test]
+    fn matches_reference() {
+        let mut encoder = Encoder::default();
+        let codes: Vec<u8> = REFERENCE_INPUT.iter().map(|&s| encoder.encode(s)).collect();
+        assert_eq!(codes, REFERENCE_CODES);
+        let mut decoder = Decoder::default();
+        let output: Vec<Sample> = codes.iter().map(|&c| decoder.decode(c)).collect();
+        assert_eq!(output, REFERENCE_OUTPUT);
+        assert_eq!(decoder, encoder.decoder());
+    }
+
+    #[test]
+    fn steps_saturate() {
+        let mut decoder = Decoder::new(0, 200);
+        assert_eq!(decoder.index(), MAX_INDEX);
+        assert_eq!(decoder.decode(7), Sample::MAX);
+        decoder.decode(15);
+        assert_eq!(decoder.decode(15), Sample::MIN);
+        assert_eq!(decoder.index(), MAX_INDEX);
+        for _ in 0..100 {
+            decoder.decode(0);
+        }
+        assert_eq!(decoder.index(), 0);
+    }
+
+    #[test]
+    fn block_sizes() {
+        assert_eq!(block_samples(DEFAULT_BLOCK_ALIGN, 1), 505);
+        assert_eq!(block_samples(512, 1), 1_017);
+        assert_eq!(block_samples(1_024, 2), 1_017);
+        assert_eq!(block_samples(4, 1), 1);
+        assert_eq!(block_samples(3, 1), 0);
+        assert_eq!(block_samples(7, 2), 0);
+        // A short stereo block only counts whole rounds.
+        assert_eq!(block_samples(8 + 4, 2), 1);
+        assert_eq!(block_samples(8 + 12, 2), 9);
+        assert_eq!(block_samples(8 + 16, 2), 17);
+        assert_eq!(frames(256 * 3 + 10, 256, 1, 505), 3 * 505 + 13);
+    }
+
+    #[test]
+    fn round_trip_is_bit_exact() {
+        let samples = test_signal(8_000);
+        let cases = [(8_000, 256), (1, 256), (505, 256), (506, 256), (7_999, 512), (30, 8)];
+        for (len, block_align) in cases {
+            let samples = &samples[..len];
+            let file = encode(samples, block_align);
+            assert_eq!(file.len(), wav_len(len, block_align) + wav_len(len, block_align) % 2);
+            let wav = Wav::parse(&file).unwrap();
+            assert_eq!(wav.encoding(), Encoding::ImaAdpcm);
+            assert_eq!(wav.frames(), len);
+            let decoded: Vec<Sample> = wav.samples().collect();
+            assert_eq!(decoded, reconstruct(samples, block_align), "{} in {}", len, block_align);
+        }
+    }
+
+    #[test]
+    fn round_trip_is_close() {
+        let samples = test_signal(8_000);
+        let file = encode(&samples, DEFAULT_BLOCK_ALIGN);
+        let decoded: Vec<Sample> = Wav::parse(&file).unwrap().samples().collect();
+        let signal: f64 = samples.iter().map(|&s| (s as f64).powi(2)).sum();
+        let noise: f64 = samples
+            .iter()
+            .zip(&decoded)
+            .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+            .sum();
+        let snr_db = 10.0 * (signal / noise).log10();
+        assert!(snr_db > 20.0, "{}dB", snr_db);
+        // A quarter the size of 16-bit PCM.
+        let ratio = file.len() as f64 / (samples.len() * 2) as f64;
+        assert!(ratio < 0.26, "{}", ratio);
+    }
+
+    #[test]
+    fn stereo_blocks() {
+        // Left rises, right falls, in one block of 17
+        // samples.
+        let mut left = Encoder::new(100, 10);
+        let mut right = Encoder::new(-300, 20);
+        let mut block = vec![100, 0, 10, 0, 0xd4, 0xfe, 20, 0];
+        let (mut l_codes, mut r_codes) = (vec![], vec![]);
+        let mut expected = vec![-100];
+        for i in 1..17 {
+            l_codes.push(left.encode(100 + 300 * i));
+            r_codes.push(right.encode(-300 - 200 * i));
+            let sum = left.decoder().predictor() as i32 + right.decoder().predictor() as i32;
+            expected.push((sum / 2) as Sample);
+        }
+        for round in 0..2 {
+            for codes in [&l_codes, &r_codes] {
+                for pair in codes[8 * round..8 * round + 8].chunks(2) {
+                    block.push(pair[0] | pair[1] << 4);
+                }
+            }
+        }
+        let samples = WavSamples::new(&block, block.len(), 2, 17, 17);
+        assert_eq!(samples.len(), 17);
+        assert_eq!(samples.collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn bad_block_align() {
+        for block_align in [0, 4, 6, 258, 1 << 16] {
+            assert_eq!(
+                encode_wav(&[0], 8_000, block_align, |_| ()),
+                Err(EncodeError::UnsupportedBlockAlign(block_align))
+            );
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Band-limited oscillator using PolyBLEP.
+//!
+//! A naive waveform with jumps in it (square, saw, pulse)
+//! has harmonics all the way up, and everything above
+//! Nyquist folds back down as inharmonic aliases. PolyBLEP
+//! smooths each jump with a two-sample polynomial
+//! approximation of a band-limited step, which removes most
+//! of that alias energy for very little computation.
+
+use crate::{osc, Sample, ToneGenerator};
+
+/// Band-limited oscillator waveform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlepWaveform {
+    /// Rising sawtooth, jumping from high to low at the start
+    /// of each period.
+    Saw,
+    /// Square wave, high for the first half of each period.
+    Square,
+    /// Pulse wave high for the given fraction (`0.0..=1.0`)
+    /// of each period.
+    Pulse(f32),
+}
+
+/// Correction to add near an upward jump of 2, from -1 to
+/// +1, at phase 0, for phase `t` (in periods) advancing `dt`
+/// per sample. Scale it by half the size of other jumps.
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt;
+        x + x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + x + x + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Value in `-1.0..=1.0` of the naive (aliasing) `waveform`
+/// at phase `t`.
+fn naive(waveform: BlepWaveform, t: f32) -> f32 {
+    match waveform {
+        BlepWaveform::Saw => 2.0 * t - 1.0,
+        BlepWaveform::Square => naive(BlepWaveform::Pulse(0.5), t),
+        BlepWaveform::Pulse(duty) => {
+            if t < duty {
+                1.0
+            } else {
+                -1.0
+            }
+        }
+    }
+}
+
+/// Value in `-1.0..=1.0` of band-limited `waveform` at
+/// phase `t`, advancing `dt` per sample.
+fn band_limited(waveform: BlepWaveform, t: f32, dt: f32) -> f32 {
+    let y = naive(waveform, t);
+    match waveform {
+        BlepWaveform::Saw => y - poly_blep(t, dt),
+        BlepWaveform::Square => band_limited(BlepWaveform::Pulse(0.5), t, dt),
+        BlepWaveform::Pulse(duty) => {
+            // Up by 2 at phase 0, down by 2 at phase `duty`.
+            let mut fall = t - duty;
+            if fall < 0.0 {
+                fall += 1.0;
+            }
+            y + poly_blep(t, dt) - poly_blep(fall, dt)
+        }
+    }
+}
+
+/// Phase accumulator oscillator producing band-limited
+/// square, saw and pulse waves.
+pub struct BlepOscillator {
+    sample_rate: u32,
+    waveform: BlepWaveform,
+    tuning_word: u32,
+    phase: u32,
+}
+
+impl BlepOscillator {
+    pub fn new(sample_rate: u32, waveform: BlepWaveform, freq_hz: f32) -> Self {
+        Self {
+            sample_rate,
+            waveform,
+            tuning_word: osc::tuning_word(sample_rate, freq_hz),
+            phase: 0,
+        }
+    }
+
+    /// Change frequency without a phase discontinuity.
+    pub fn set_freq(&mut self, freq_hz: f32) {
+        self.tuning_word = osc::tuning_word(self.sample_rate, freq_hz);
+    }
+
+    pub fn waveform(&self) -> BlepWaveform {
+        self.waveform
+    }
+
+    pub fn set_waveform(&mut self, waveform: BlepWaveform) {
+        self.waveform = waveform;
+    }
+}
+
+/// Convert a 32-bit phase to a fraction of a period.
+fn turns(phase: u32) -> f32 {
+    phase as f32 / 4_294_967_296.0
+}
+
+/// Band-limited `waveform` at 32-bit `phase`, advancing
+/// `tuning_word` per sample.
+pub(crate) fn sample(waveform: BlepWaveform, phase: u32, tuning_word: u32) -> Sample {
+    let y = band_limited(waveform, turns(phase), turns(tuning_word));
+    (y.clamp(-1.0, 1.0) * Sample::MAX as f32) as Sample
+}
+
+impl ToneGenerator for BlepOscillator {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_sample(&mut self) -> Sample {
+        let sample = self::sample(self.waveform, self.phase, self.tuning_word);
+        self.phase = self.phase.wrapping_add(self.tuning_word);
+        sample
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const RATE: u32 = 16_000;
+    const N: usize = 1 << 14;
+
+    /// In-place radix-2 complex FFT.
+    fn fft(re: &mut [f64], im: &mut [f64]) {
+        let n = re.len();
+        let mut j = 0;
+        for i in 1..n {
+            let mut bit = n >> 1;
+            while j & bit != 0 {
+                j ^= bit;
+                bit >>= 1;
+            }
+            j |= bit;
+            if i < j {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+        let mut len = 2;
+        while len <= n {
+            let angle = -2.0 * PI / len as f64;
+            for start in (0..n).step_by(len) {
+                for k in 0..len / 2 {
+                    let (wr, wi) = ((angle * k as f64).cos(), (angle * k as f64).sin());
+                    let (a, b) = (start + k, start + k + len / 2);
+                    let tr = re[b] * wr - im[b] * wi;
+                    let ti = re[b] * wi + im[b] * wr;
+                    re[b] = re[a] - tr;
+                    im[b] = im[a] - ti;
+                    re[a] += tr;
+                    im[a] += ti;
+                }
+            }
+            len <<= 1;
+        }
+    }
+
+    /// Hann-windowed power spectrum of `samples`, bins
+    /// `0..=N/2`.
+    fn power_spectrum(samples: &[f64]) -> Vec<f64> {
+        let mut re: Vec<f64> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f64 / N as f64).cos()))
+            .collect();
+        let mut im = vec![0.0; N];
+        fft(&mut re, &mut im);
+        (0..=N / 2).map(|k| re[k] * re[k] + im[k] * im[k]).collect()
+    }
+
+    /// Energy in bins that are not near a true harmonic of
+    /// `freq`: that is, aliases.
+    fn alias_energy(spectrum: &[f64], freq: f64) -> f64 {
+        let bin_hz = RATE as f64 / N as f64;
+        spectrum
+            .iter()
+            .enumerate()
+            .filter(|&(k, _)| {
+                let f = k as f64 * bin_hz;
+                let nearest = (f / freq).round() * freq;
+                (f - nearest).abs() > 4.0 * bin_hz
+            })
+            .map(|(_, p)| p)
+            .sum()
+    }
+
+    fn render(waveform: BlepWaveform, freq: f32, band_limit: bool) -> Vec<f64> {
+        let tuning_word = osc::tuning_word(RATE, freq);
+        let dt = turns(tuning_word);
+        (0..N as u32)
+            .map(|i| {
+                let t = turns(tuning_word.wrapping_mul(i));
+                if band_limit {
+                    band_limited(waveform, t, dt) as f64
+                } else {
+                    naive(waveform, t) as f64
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn alias_energy_reduced() {
+        for waveform in [
+            BlepWaveform::Saw,
+            BlepWaveform::Square,
+            BlepWaveform::Pulse(0.25),
+        ] {
+            for freq in [440.0, 1_234.5, 2_637.0] {
+                let naive = alias_energy(&power_spectrum(&render(waveform, freq, false)), freq as f64);
+                let blep = alias_energy(&power_spectrum(&render(waveform, freq, true)), freq as f64);
+                // At least 10dB less alias energy.
+                assert!(
+                    blep * 10.0 < naive,
+                    "{:?} {}Hz: naive {:e}, blep {:e}",
+                    waveform,
+                    freq,
+                    naive,
+                    blep,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fundamental_preserved() {
+        let freq = 1_234.5;
+        let bin = (freq as f64 * N as f64 / RATE as f64).round() as usize;
+        let naive = power_spectrum(&render(BlepWaveform::Saw, freq, false));
+        let blep = power_spectrum(&render(BlepWaveform::Saw, freq, true));
+        let ratio = blep[bin - 2..=bin + 2].iter().sum::<f64>()
+            / naive[bin - 2..=bin + 2].iter().sum::<f64>();
+        assert!((ratio - 1.0).abs() < 0.05, "{}", ratio);
+    }
+
+    #[test]
+    fn oscillator_output_in_range() {
+        for waveform in [
+            BlepWaveform::Saw,
+            BlepWaveform::Square,
+            BlepWaveform::Pulse(0.1),
+        ] {
+            let mut osc = BlepOscillator::new(RATE, waveform, 301.0);
+            let samples: Vec<Sample> = osc.samples().take(RATE as usize).collect();
+            let max = samples.iter().copied().max().unwrap();
+            let min = samples.iter().copied().min().unwrap();
+            assert!(max > 30_000 && min < -30_000, "{:?}: {}..{}", waveform, min, max);
+        }
+    }
+
+    #[test]
+    fn matches_naive_away_from_edges() {
+        let mut osc = BlepOscillator::new(RATE, BlepWaveform::Square, 100.0);
+        let samples: Vec<Sample> = osc.samples().take(160).collect();
+        // 160 samples per period: edges at 0 and 80.
+        assert_eq!(samples[40], Sample::MAX);
+        assert_eq!(samples[120], -Sample::MAX);
+        assert!(samples[0].abs() < 1_000);
+        assert!(samples[80].abs() < 1_000);
+    }
+}
@@ -0,0 +1,269 @@
+//! Continuous sample capture into double DMA buffers.
+//!
+//! The ADC fills its two buffers alternately, restarting
+//! into the next one by itself each time one fills. As each
+//! transfer starts, [Capture::poll] points the DMA at the
+//! other buffer for the transfer after; as each one ends,
+//! its buffer is handed out sample by sample. If a buffer
+//! ends before the last one has been read, the rest of that
+//! one is lost and an overrun is counted.
+
+use crate::{stream::SampleSource, Sample};
+
+/// An ADC storing samples into two DMA buffers, 0 and 1.
+pub trait Sampler {
+    /// Point the DMA at buffer `index` for the next transfer.
+    fn set_buffer(&mut self, index: usize);
+
+    /// The samples in buffer `index`.
+    fn buffer(&self, index: usize) -> &[Sample];
+
+    /// Start sampling into the buffer last set.
+    fn start(&mut self);
+
+    /// Stop sampling.
+    fn stop(&mut self);
+
+    /// True, clearing the event, if a transfer has started
+    /// since the last call.
+    fn take_started(&mut self) -> bool;
+
+    /// True, clearing the event, if a transfer has ended
+    /// since the last call.
+    fn take_end(&mut self) -> bool;
+}
+
+/// Captures samples continuously from a [Sampler].
+pub struct Capture<S> {
+    sampler: S,
+    /// Buffer being filled.
+    dma: usize,
+    /// Buffer the DMA will fill next.
+    queued: usize,
+    /// Buffer being read, if any.
+    ready: Option<usize>,
+    pos: usize,
+    overruns: u32,
+}
+
+impl<S: Sampler> Capture<S> {
+    pub fn new(sampler: S) -> Self {
+        Self {
+            sampler,
+            dma: 0,
+            queued: 0,
+            ready: None,
+            pos: 0,
+            overruns: 0,
+        }
+    }
+
+    /// Start capturing, discarding anything not yet read.
+    pub fn start(&mut self) {
+        self.dma = 0;
+        self.queued = 0;
+        self.ready = None;
+        self.sampler.set_buffer(0);
+        self.sampler.take_started();
+        self.sampler.take_end();
+        self.sampler.start();
+    }
+
+    pub fn stop(&mut self) {
+        self.sampler.stop();
+    }
+
+    /// Keep up with the hardware. This must be called at
+    /// least once per buffer to capture every sample; it is
+    /// called by each [SampleSource::next_sample].
+    pub fn poll(&mut self) {
+        if self.sampler.take_end() {
+            if let Some(ready) = self.ready {
+                if self.pos < self.sampler.buffer(ready).len() {
+                    self.overruns += 1;
+                }
+            }
+            self.ready = Some(self.dma);
+            self.pos = 0;
+        }
+        if self.sampler.take_started() {
+            self.dma = self.queued;
+            self.queued = 1 - self.dma;
+            self.sampler.set_buffer(self.queued);
+        }
+    }
+
+    /// Number of buffers cut short so far.
+    pub fn overruns(&self) -> u32 {
+        self.overruns
+    }
+
+    pub fn sampler(&self) -> &S {
+        &self.sampler
+    }
+
+    pub fn sampler_mut(&mut self) -> &mut S {
+        &mut self.sampler
+    }
+
+    /// Give back the sampler.
+    pub fn free(self) -> S {
+        self.sampler
+    }
+}
+
+impl<S: Sampler> SampleSource for Capture<S> {
+    fn next_sample(&mut self) -> Option<Sample> {
+        self.poll();
+        let buffer = self.sampler.buffer(self.ready?);
+        let sample = *buffer.get(self.pos)?;
+        self.pos += 1;
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEN: usize = 4;
+
+    /// Stand-in for the SAADC. The test makes it sample with
+    /// [MockAdc::sample], which stores counting values and
+    /// restarts into the pointed-to buffer when one fills,
+    /// as the hardware does.
+    struct MockAdc {
+        bufs: [[Sample; LEN]; 2],
+        ptr: usize,
+        dma: Option<usize>,
+        pos: usize,
+        started: bool,
+        ended: bool,
+        next: Sample,
+    }
+
+    impl MockAdc {
+        fn new() -> Self {
+            Self {
+                bufs: [[0; LEN]; 2],
+                ptr: 0,
+                dma: None,
+                pos: 0,
+                started: false,
+                ended: false,
+                next: 0,
+            }
+        }
+
+        fn sample(&mut self, n: usize) {
+            for _ in 0..n {
+                let dma = self.dma.unwrap();
+                self.bufs[dma][self.pos] = self.next;
+                self.next += 1;
+                self.pos += 1;
+                if self.pos == LEN {
+                    self.ended = true;
+                    self.start();
+                }
+            }
+        }
+    }
+
+    impl Sampler for MockAdc {
+        fn set_buffer(&mut self, index: usize) {
+            self.ptr = index;
+        }
+
+        fn buffer(&self, index: usize) -> &[Sample] {
+            &self.bufs[index]
+        }
+
+        fn start(&mut self) {
+            self.dma = Some(self.ptr);
+            self.pos = 0;
+            self.started = true;
+        }
+
+        fn stop(&mut self) {
+            self.dma = None;
+        }
+
+        fn take_started(&mut self) -> bool {
+            core::mem::take(&mut self.started)
+        }
+
+        fn take_end(&mut self) -> bool {
+            core::mem::take(&mut self.ended)
+        }
+    }
+
+    fn drain(capture: &mut Capture<MockAdc>) -> Vec<Sample> {
+        core::iter::from_fn(|| capture.next_sample()).collect()
+    }
+
+    #[test]
+    fn nothing_before_first_buffer() {
+        let mut capture = Capture::new(MockAdc::new());
+        assert_eq!(capture.next_sample(), None);
+        capture.start();
+        capture.sampler_mut().sample(LEN - 1);
+        assert_eq!(capture.next_sample(), None);
+    }
+
+    #[test]
+    fn buffers_alternate() {
+        let mut capture = Capture::new(MockAdc::new());
+        capture.start();
+        let mut got = Vec::new();
+        for _ in 0..6 {
+            capture.poll();
+            capture.sampler_mut().sample(LEN);
+            got.extend(drain(&mut capture));
+        }
+        assert_eq!(got, (0..6 * LEN as Sample).collect::<Vec<_>>());
+        assert_eq!(capture.overruns(), 0);
+        // Each buffer was used in turn.
+        assert_eq!(capture.sampler().bufs[0], [16, 17, 18, 19]);
+        assert_eq!(capture.sampler().bufs[1], [20, 21, 22, 23]);
+    }
+
+    #[test]
+    fn partial_reads_continue() {
+        let mut capture = Capture::new(MockAdc::new());
+        capture.start();
+        capture.poll();
+        capture.sampler_mut().sample(LEN + 1);
+        assert_eq!(capture.next_sample(), Some(0));
+        assert_eq!(capture.next_sample(), Some(1));
+        capture.sampler_mut().sample(1);
+        assert_eq!(drain(&mut capture), [2, 3]);
+        capture.sampler_mut().sample(LEN - 2);
+        assert_eq!(drain(&mut capture), [4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn slow_reader_overruns() {
+        let mut capture = Capture::new(MockAdc::new());
+        capture.start();
+        capture.poll();
+        capture.sampler_mut().sample(LEN);
+        assert_eq!(capture.next_sample(), Some(0));
+        capture.sampler_mut().sample(LEN);
+        // The rest of the first buffer is dropped.
+        assert_eq!(drain(&mut capture), [4, 5, 6, 7]);
+        assert_eq!(capture.overruns(), 1);
+    }
+
+    #[test]
+    fn restart_discards_old_samples() {
+        let mut capture = Capture::new(MockAdc::new());
+        capture.start();
+        capture.poll();
+        capture.sampler_mut().sample(LEN);
+        capture.stop();
+        capture.start();
+        assert_eq!(capture.next_sample(), None);
+        capture.sampler_mut().sample(LEN);
+        assert_eq!(drain(&mut capture), [4, 5, 6, 7]);
+    }
+}
@@ -0,0 +1,449 @@
+//! Line-based command console.
+//!
+//! Bytes from a serial port are collected into lines by a
+//! [LineBuffer], and each line is parsed into a [Command]:
+//!
+//! ```text
+//! tone 440 500    play 440Hz for 500ms
+//! vol 60          set the volume to 60%
+//! wave saw        set the waveform
+//! play tetris     play a built-in song
+//! stop            stop playing
+//! mic             show the microphone level
+//! gain 300        set the microphone loopback gain
+//! codec mulaw     pass the loopback through G.711
+//! meter spectrum  show the spectrum on the display
+//! logo sustain    make the touch logo a sustain pedal
+//! theremin major  play by tilting, keeping to C major
+//! help            list the commands
+//! ```
+//!
+//! Command words and their arguments are not case
+//! sensitive. Errors report the byte offset in the line
+//! where parsing went wrong.
+
+use core::fmt;
+
+use crate::{
+    controls::LogoMode,
+    g711::Law,
+    loopback::MAX_GAIN_PERCENT,
+    meter::View,
+    osc::Waveform,
+    songs::{self, Song},
+    square::{MAX_FREQ_HZ, MIN_FREQ_HZ},
+    theremin,
+};
+
+/// Longest line a console needs to hold.
+pub const LINE_LEN: usize = 64;
+
+/// Length of a tone when none is given.
+pub const DEFAULT_TONE_MS: u32 = 500;
+
+/// Longest tone.
+pub const MAX_TONE_MS: u32 = 60_000;
+
+/// Text for the `help` command.
+pub const HELP: &str = "\
+tone <hz> [ms]  play a tone (default 500ms)
+vol <0-100>     set the volume in percent
+wave <name>     sine, square, triangle or saw
+play <song>     play a built-in song
+stop            stop playing
+mic             show the microphone level
+gain <0-800>    set the loopback gain in percent
+codec <law>     loopback through mulaw, alaw or off
+meter <view>    level, spectrum, tuner or off
+logo <mode>     touch logo as a gate or sustain pedal
+theremin <mode> off, free, chromatic, major, minor or pentatonic
+help            show this list";
+
+/// What went wrong reading a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandErrorKind {
+    /// A blank line.
+    Empty,
+    /// The line was longer than the buffer holding it.
+    LineTooLong,
+    UnknownCommand,
+    MissingArgument,
+    ExtraArgument,
+    ExpectedNumber,
+    OutOfRange,
+    UnknownWaveform,
+    UnknownSong,
+    UnknownView,
+    UnknownLogoMode,
+    UnknownThereminMode,
+    UnknownCodec,
+}
+
+impl fmt::Display for CommandErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            CommandErrorKind::Empty => "empty line",
+            CommandErrorKind::LineTooLong => "line too long",
+            CommandErrorKind::UnknownCommand => "unknown command",
+            CommandErrorKind::MissingArgument => "missing argument",
+            CommandErrorKind::ExtraArgument => "too many arguments",
+            CommandErrorKind::ExpectedNumber => "expected a number",
+            CommandErrorKind::OutOfRange => "out of range",
+            CommandErrorKind::UnknownWaveform => "unknown waveform",
+            CommandErrorKind::UnknownSong => "unknown song",
+            CommandErrorKind::UnknownView => "unknown view",
+            CommandErrorKind::UnknownLogoMode => "unknown logo mode",
+            CommandErrorKind::UnknownThereminMode => "unknown theremin mode",
+            CommandErrorKind::UnknownCodec => "unknown codec",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// A command error at byte offset `pos` of the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandError {
+    pub pos: usize,
+    pub kind: CommandErrorKind,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.kind, self.pos + 1)
+    }
+}
+
+/// A console command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Tone { freq_hz: f32, duration_ms: u32 },
+    /// Volume in percent.
+    Volume(u8),
+    Wave(Waveform),
+    Play(Song),
+    Stop,
+    Mic,
+    /// Microphone loopback gain in percent.
+    Gain(u16),
+    /// Companding of the microphone loopback, if any.
+    Codec(Option<Law>),
+    Meter(View),
+    Logo(LogoMode),
+    Theremin(theremin::Mode),
+    Help,
+}
+
+/// The whitespace-separated words of a line, with their
+/// offsets.
+struct Words<'a> {
+    line: &'a str,
+    pos: usize,
+}
+
+impl<'a> Words<'a> {
+    fn next_word(&mut self) -> Option<(usize, &'a str)> {
+        let rest = &self.line[self.pos..];
+        let start = self.pos + rest.len() - rest.trim_start().len();
+        let len = self.line[start..]
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(self.line.len() - start);
+        self.pos = start + len;
+        (len > 0).then(|| (start, &self.line[start..start + len]))
+    }
+
+    fn error(&self, kind: CommandErrorKind) -> CommandError {
+        CommandError {
+            pos: self.pos,
+            kind,
+        }
+    }
+
+    /// The next word, which must be there.
+    fn arg(&mut self) -> Result<(usize, &'a str), CommandError> {
+        self.next_word()
+            .ok_or_else(|| self.error(CommandErrorKind::MissingArgument))
+    }
+
+    /// The next word as a number in `range`, if there is one.
+    fn number<T>(&mut self, range: core::ops::RangeInclusive<T>) -> Result<Option<T>, CommandError>
+    where
+        T: core::str::FromStr + PartialOrd,
+    {
+        let Some((pos, word)) = self.next_word() else {
+            return Ok(None);
+        };
+        let error = |kind| CommandError { pos, kind };
+        let n: T = word
+            .parse()
+            .map_err(|_| error(CommandErrorKind::ExpectedNumber))?;
+        if !range.contains(&n) {
+            return Err(error(CommandErrorKind::OutOfRange));
+        }
+        Ok(Some(n))
+    }
+
+    fn required<T>(&mut self, range: core::ops::RangeInclusive<T>) -> Result<T, CommandError>
+    where
+        T: core::str::FromStr + PartialOrd,
+    {
+        self.number(range)?
+            .ok_or_else(|| self.error(CommandErrorKind::MissingArgument))
+    }
+}
+
+impl Command {
+    /// Parse a line.
+    pub fn parse(line: &str) -> Result<Self, CommandError> {
+        let mut words = Words { line, pos: 0 };
+        let Some((pos, name)) = words.next_word() else {
+            return Err(words.error(CommandErrorKind::Empty));
+        };
+        let is = |s: &str| name.eq_ignore_ascii_case(s);
+        let command = if is("tone") {
+            let freq_hz = words.required(MIN_FREQ_HZ..=MAX_FREQ_HZ)?;
+            let duration_ms = words.number(1..=MAX_TONE_MS)?.unwrap_or(DEFAULT_TONE_MS);
+            Command::Tone {
+                freq_hz,
+                duration_ms,
+            }
+        } else if is("vol") {
+            Command::Volume(words.required(0..=100)?)
+        } else if is("wave") {
+            let (pos, name) = words.arg()?;
+            let waveform = Waveform::from_name(name).ok_or(CommandError {
+                pos,
+                kind: CommandErrorKind::UnknownWaveform,
+            })?;
+            Command::Wave(waveform)
+        } else if is("play") {
+            let (pos, name) = words.arg()?;
+            let song = songs::find(name).ok_or(CommandError {
+                pos,
+                kind: CommandErrorKind::UnknownSong,
+            })?;
+            Command::Play(song)
+        } else if is("stop") {
+            Command::Stop
+        } else if is("mic") {
+            Command::Mic
+        } else if is("gain") {
+            Command::Gain(words.required(0..=MAX_GAIN_PERCENT)?)
+        } else if is("codec") {
+            let (pos, name) = words.arg()?;
+            if name.eq_ignore_ascii_case("off") {
+                Command::Codec(None)
+            } else {
+                let law = Law::from_name(name).ok_or(CommandError {
+                    pos,
+                    kind: CommandErrorKind::UnknownCodec,
+                })?;
+                Command::Codec(Some(law))
+            }
+        } else if is("meter") {
+            let (pos, name) = words.arg()?;
+            let view = View::from_name(name).ok_or(CommandError {
+                pos,
+                kind: CommandErrorKind::UnknownView,
+            })?;
+            Command::Meter(view)
+        } else if is("logo") {
+            let (pos, name) = words.arg()?;
+            let mode = LogoMode::from_name(name).ok_or(CommandError {
+                pos,
+                kind: CommandErrorKind::UnknownLogoMode,
+            })?;
+            Command::Logo(mode)
+        } else if is("theremin") {
+            let (pos, name) = words.arg()?;
+            let mode = theremin::Mode::from_name(name).ok_or(CommandError {
+                pos,
+                kind: CommandErrorKind::UnknownThereminMode,
+            })?;
+            Command::Theremin(mode)
+        } else if is("help") {
+            Command::Help
+        } else {
+            return Err(CommandError {
+                pos,
+                kind: CommandErrorKind::UnknownCommand,
+            });
+        };
+        if let Some((pos, _)) = words.next_word() {
+            return Err(CommandError {
+                pos,
+                kind: CommandErrorKind::ExtraArgument,
+            });
+        }
+        Ok(command)
+    }
+}
+
+/// Collects serial input into lines. Backspace and delete
+/// remove the last character, and other control characters
+/// and non-ASCII bytes are dropped.
+pub struct LineBuffer<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    overflowed: bool,
+    /// The last byte ended a line: start a new one.
+    done: bool,
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            overflowed: false,
+            done: false,
+        }
+    }
+
+    /// Take the next byte. Returns the line when `byte` is a
+    /// carriage return or newline, or an error if the line
+    /// overflowed.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, CommandError>> {
+        if self.done {
+            self.len = 0;
+            self.overflowed = false;
+            self.done = false;
+        }
+        match byte {
+            b'\r' | b'\n' => {
+                self.done = true;
+                if self.overflowed {
+                    return Some(Err(CommandError {
+                        pos: N,
+                        kind: CommandErrorKind::LineTooLong,
+                    }));
+                }
+                // Only printable ASCII gets in, so this is UTF-8.
+                return Some(Ok(core::str::from_utf8(&self.buf[..self.len]).unwrap()));
+            }
+            0x08 | 0x7f => self.len = self.len.saturating_sub(1),
+            b' '..=b'~' if self.len < N => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            }
+            b' '..=b'~' => self.overflowed = true,
+            _ => (),
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuning::Scale;
+    use CommandErrorKind::*;
+
+    fn error(line: &str) -> (usize, CommandErrorKind) {
+        let e = Command::parse(line).unwrap_err();
+        (e.pos, e.kind)
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            Command::parse("tone 440 500"),
+            Ok(Command::Tone {
+                freq_hz: 440.0,
+                duration_ms: 500
+            })
+        );
+        assert_eq!(
+            Command::parse("  TONE   261.63 "),
+            Ok(Command::Tone {
+                freq_hz: 261.63,
+                duration_ms: DEFAULT_TONE_MS
+            })
+        );
+        assert_eq!(Command::parse("vol 60"), Ok(Command::Volume(60)));
+        assert_eq!(Command::parse("vol 0"), Ok(Command::Volume(0)));
+        assert_eq!(Command::parse("wave saw"), Ok(Command::Wave(Waveform::Saw)));
+        assert_eq!(Command::parse("Wave Triangle"), Ok(Command::Wave(Waveform::Triangle)));
+        assert_eq!(
+            Command::parse("play tetris"),
+            Ok(Command::Play(songs::find("Tetris").unwrap()))
+        );
+        assert_eq!(Command::parse("stop"), Ok(Command::Stop));
+        assert_eq!(Command::parse("mic"), Ok(Command::Mic));
+        assert_eq!(Command::parse("gain 350"), Ok(Command::Gain(350)));
+        assert_eq!(Command::parse("codec ALaw"), Ok(Command::Codec(Some(Law::ALaw))));
+        assert_eq!(Command::parse("codec off"), Ok(Command::Codec(None)));
+        assert_eq!(Command::parse("meter Off"), Ok(Command::Meter(View::Off)));
+        assert_eq!(Command::parse("logo Sustain"), Ok(Command::Logo(LogoMode::Sustain)));
+        assert_eq!(
+            Command::parse("theremin Pentatonic"),
+            Ok(Command::Theremin(theremin::Mode::Scale(Scale::Pentatonic)))
+        );
+        assert_eq!(Command::parse("theremin off"), Ok(Command::Theremin(theremin::Mode::Off)));
+        assert_eq!(Command::parse("help"), Ok(Command::Help));
+    }
+
+    #[test]
+    fn errors_with_position() {
+        assert_eq!(error(""), (0, Empty));
+        assert_eq!(error("   "), (3, Empty));
+        assert_eq!(error("  beep"), (2, UnknownCommand));
+        assert_eq!(error("tone"), (4, MissingArgument));
+        assert_eq!(error("tone x"), (5, ExpectedNumber));
+        assert_eq!(error("tone 5"), (5, OutOfRange));
+        assert_eq!(error("tone 440 0"), (9, OutOfRange));
+        assert_eq!(error("tone 440 -5"), (9, ExpectedNumber));
+        assert_eq!(error("tone 440 500 600"), (13, ExtraArgument));
+        assert_eq!(error("vol 101"), (4, OutOfRange));
+        assert_eq!(error("vol 6.5"), (4, ExpectedNumber));
+        assert_eq!(error("wave"), (4, MissingArgument));
+        assert_eq!(error("wave noise"), (5, UnknownWaveform));
+        assert_eq!(error("play macarena"), (5, UnknownSong));
+        assert_eq!(error("stop now"), (5, ExtraArgument));
+        assert_eq!(error("gain 801"), (5, OutOfRange));
+        assert_eq!(error("codec gsm"), (6, UnknownCodec));
+        assert_eq!(error("meter vu"), (6, UnknownView));
+        assert_eq!(error("logo pedal"), (5, UnknownLogoMode));
+        assert_eq!(error("theremin on"), (9, UnknownThereminMode));
+    }
+
+    #[test]
+    fn error_messages() {
+        let e = Command::parse("wave noise").unwrap_err();
+        assert_eq!(e.to_string(), "unknown waveform at column 6");
+    }
+
+    fn lines<const N: usize>(input: &[u8]) -> Vec<Result<String, CommandError>> {
+        let mut buffer: LineBuffer<N> = LineBuffer::new();
+        input
+            .iter()
+            .filter_map(|&b| buffer.push(b).map(|line| line.map(String::from)))
+            .collect()
+    }
+
+    #[test]
+    fn line_editing() {
+        assert_eq!(
+            lines::<16>(b"vol 6\r\n\x1bwave  sax\x7f\x08w\x08aw\nst"),
+            [Ok("vol 6".into()), Ok("".into()), Ok("wave  saw".into())]
+        );
+        assert_eq!(lines::<16>(b"\x08\x08ok\r"), [Ok("ok".into())]);
+    }
+
+    #[test]
+    fn long_lines_rejected() {
+        let error = CommandError {
+            pos: 4,
+            kind: LineTooLong,
+        };
+        assert_eq!(
+            lines::<4>(b"tone 440\rstop\rtone\x08\x08\x08\x08play\r"),
+            [Err(error), Ok("stop".into()), Ok("play".into())]
+        );
+    }
+}
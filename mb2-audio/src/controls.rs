@@ -0,0 +1,477 @@
+//! Button and touch controls.
+//!
+//! [Controls::update] is called every few milliseconds with
+//! the raw levels of button A, button B and the touch logo,
+//! and turns them into [Event]s:
+//!
+//! * Button A is the gate: a note sounds while it is held.
+//! * A short press of button B selects the next waveform,
+//!   and holding it for [LONG_PRESS_MS] switches microphone
+//!   loopback on or off.
+//! * Pressing A and B together steps the octave up, wrapping
+//!   round from [MAX_OCTAVE] to [MIN_OCTAVE]; holding them
+//!   together puts it back to 0.
+//! * The touch logo is either a second gate or a sustain
+//!   pedal, as set by its [LogoMode]. Held as a pedal, it
+//!   keeps the gate open after the keys are let go.
+//!
+//! Every input is debounced by a [Button], so contact bounce
+//! and a flickering touch reading do not make extra presses.
+
+/// How long an input must stay at a new level before the
+/// change counts.
+pub const DEBOUNCE_MS: u32 = 20;
+
+/// How long a press must last to be a long press.
+pub const LONG_PRESS_MS: u32 = 600;
+
+/// Lowest octave shift.
+pub const MIN_OCTAVE: i8 = -2;
+
+/// Highest octave shift.
+pub const MAX_OCTAVE: i8 = 2;
+
+/// Something that happened to a [Button].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    Press,
+    /// Held for [LONG_PRESS_MS]. Comes once per press,
+    /// while the button is still down.
+    LongPress,
+    /// Let go, after a long press or a short one.
+    Release { long: bool },
+}
+
+/// A debounced input that tells long presses from short.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Button {
+    pressed: bool,
+    /// How long the raw level has differed from `pressed`.
+    changing_ms: u32,
+    held_ms: u32,
+    long: bool,
+}
+
+impl Button {
+    pub const fn new() -> Self {
+        Self {
+            pressed: false,
+            changing_ms: 0,
+            held_ms: 0,
+            long: false,
+        }
+    }
+
+    /// Take the raw level, `elapsed_ms` after the last call.
+    pub fn update(&mut self, pressed: bool, elapsed_ms: u32) -> Option<ButtonEvent> {
+        if pressed == self.pressed {
+            self.changing_ms = 0;
+        } else {
+            self.changing_ms += elapsed_ms;
+            if self.changing_ms >= DEBOUNCE_MS {
+                self.pressed = pressed;
+                self.changing_ms = 0;
+                if !pressed {
+                    return Some(ButtonEvent::Release { long: self.long });
+                }
+                self.held_ms = 0;
+                self.long = false;
+                return Some(ButtonEvent::Press);
+            }
+        }
+        if self.pressed && !self.long {
+            self.held_ms += elapsed_ms;
+            if self.held_ms >= LONG_PRESS_MS {
+                self.long = true;
+                return Some(ButtonEvent::LongPress);
+            }
+        }
+        None
+    }
+
+    /// Whether the button is down, after debouncing.
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+/// What the touch logo does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogoMode {
+    /// Sound a note while touched, like button A.
+    Gate,
+    /// Keep notes sounding while touched.
+    Sustain,
+}
+
+impl LogoMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            LogoMode::Gate => "gate",
+            LogoMode::Sustain => "sustain",
+        }
+    }
+
+    /// The mode called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [LogoMode::Gate, LogoMode::Sustain]
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Something for the instrument to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Start or stop the note. A start while the gate is
+    /// already open, held by the pedal, should strike it
+    /// again, with [Mixer::retrigger] after
+    /// [Mixer::note_on].
+    ///
+    /// [Mixer::retrigger]: crate::mixer::Mixer::retrigger
+    /// [Mixer::note_on]: crate::mixer::Mixer::note_on
+    Gate(bool),
+    NextWaveform,
+    /// Switch microphone loopback on or off.
+    ToggleLoopback,
+    /// The new octave shift.
+    Octave(i8),
+}
+
+/// Most events one update can make.
+const MAX_EVENTS: usize = 3;
+
+/// The events from one [Controls::update], in order.
+#[derive(Debug, Clone)]
+pub struct Events {
+    events: [Option<Event>; MAX_EVENTS],
+    next: usize,
+}
+
+impl Events {
+    fn new() -> Self {
+        Self {
+            events: [None; MAX_EVENTS],
+            next: 0,
+        }
+    }
+
+    fn push(&mut self, event: Event) {
+        // Each update makes at most one gate event, one
+        // button B event and one octave event.
+        let slot = self.events.iter_mut().find(|e| e.is_none()).unwrap();
+        *slot = Some(event);
+    }
+}
+
+impl Iterator for Events {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        let event = *self.events.get(self.next)?;
+        self.next += 1;
+        event
+    }
+}
+
+/// The instrument's controls.
+#[derive(Debug, Clone)]
+pub struct Controls {
+    a: Button,
+    b: Button,
+    logo: Button,
+    logo_mode: LogoMode,
+    octave: i8,
+    /// A and B were pressed together, and have not both been
+    /// let go since.
+    chord: bool,
+    /// How long A and B have been held together.
+    chord_ms: u32,
+    /// The keys (A, and the logo as a gate) were down at the
+    /// last update.
+    keys: bool,
+    gate: bool,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Controls {
+    pub const fn new() -> Self {
+        Self {
+            a: Button::new(),
+            b: Button::new(),
+            logo: Button::new(),
+            logo_mode: LogoMode::Gate,
+            octave: 0,
+            chord: false,
+            chord_ms: 0,
+            keys: false,
+            gate: false,
+        }
+    }
+
+    pub fn logo_mode(&self) -> LogoMode {
+        self.logo_mode
+    }
+
+    pub fn set_logo_mode(&mut self, mode: LogoMode) {
+        self.logo_mode = mode;
+    }
+
+    pub fn octave(&self) -> i8 {
+        self.octave
+    }
+
+    /// Take the raw levels of the inputs, `elapsed_ms` after
+    /// the last call.
+    pub fn update(&mut self, elapsed_ms: u32, a: bool, b: bool, logo: bool) -> Events {
+        let mut events = Events::new();
+        let was_chord = self.chord;
+        self.a.update(a, elapsed_ms);
+        let b_event = self.b.update(b, elapsed_ms);
+        self.logo.update(logo, elapsed_ms);
+
+        let together = self.a.is_pressed() && self.b.is_pressed();
+        let mut octave = None;
+        if together {
+            if !self.chord {
+                self.chord = true;
+                self.chord_ms = 0;
+            } else if self.chord_ms < LONG_PRESS_MS {
+                self.chord_ms += elapsed_ms;
+                if self.chord_ms >= LONG_PRESS_MS {
+                    octave = Some(0);
+                }
+            }
+        } else if self.chord {
+            if self.chord_ms < LONG_PRESS_MS {
+                self.chord_ms = LONG_PRESS_MS;
+                octave = Some(if self.octave == MAX_OCTAVE {
+                    MIN_OCTAVE
+                } else {
+                    self.octave + 1
+                });
+            }
+            // Whichever is still down does nothing until it
+            // is let go too.
+            self.chord = self.a.is_pressed() || self.b.is_pressed();
+        }
+
+        let keys = (self.a.is_pressed() && !self.chord)
+            || (self.logo_mode == LogoMode::Gate && self.logo.is_pressed());
+        let pedal = self.logo_mode == LogoMode::Sustain && self.logo.is_pressed();
+        if keys && !self.keys {
+            self.gate = true;
+            events.push(Event::Gate(true));
+        } else if self.gate && !keys && !pedal {
+            self.gate = false;
+            events.push(Event::Gate(false));
+        }
+        self.keys = keys;
+
+        // B on its own, but not B let go after a chord.
+        let b_chord = was_chord || self.chord;
+        match b_event {
+            Some(ButtonEvent::LongPress) if !b_chord => events.push(Event::ToggleLoopback),
+            Some(ButtonEvent::Release { long: false }) if !b_chord => {
+                events.push(Event::NextWaveform)
+            }
+            _ => (),
+        }
+        if let Some(octave) = octave {
+            self.octave = octave;
+            events.push(Event::Octave(octave));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLL_MS: u32 = 10;
+
+    /// Events from holding the inputs at the given levels
+    /// for `ms`.
+    fn hold(controls: &mut Controls, ms: u32, a: bool, b: bool, logo: bool) -> Vec<Event> {
+        (0..ms / POLL_MS)
+            .flat_map(|_| controls.update(POLL_MS, a, b, logo))
+            .collect()
+    }
+
+    #[test]
+    fn button_debounces() {
+        let mut button = Button::new();
+        // Bounces shorter than DEBOUNCE_MS are ignored.
+        for pressed in [true, false, true, false] {
+            assert_eq!(button.update(pressed, 10), None);
+        }
+        assert_eq!(button.update(true, 10), None);
+        assert_eq!(button.update(true, 10), Some(ButtonEvent::Press));
+        assert!(button.is_pressed());
+        assert_eq!(button.update(false, 10), None);
+        assert_eq!(button.update(true, 10), None);
+        assert_eq!(button.update(false, 10), None);
+        assert_eq!(button.update(false, 10), Some(ButtonEvent::Release { long: false }));
+        assert!(!button.is_pressed());
+    }
+
+    #[test]
+    fn button_long_press() {
+        let mut button = Button::new();
+        let mut events = vec![];
+        let mut ms = 0;
+        while ms < 2 * LONG_PRESS_MS {
+            events.extend(button.update(true, 10));
+            ms += 10;
+            if events.len() == 2 {
+                break;
+            }
+        }
+        assert_eq!(events, [ButtonEvent::Press, ButtonEvent::LongPress]);
+        assert_eq!(ms, DEBOUNCE_MS + LONG_PRESS_MS);
+        for _ in 0..100 {
+            assert_eq!(button.update(true, 10), None);
+        }
+        button.update(false, 10);
+        assert_eq!(button.update(false, 10), Some(ButtonEvent::Release { long: true }));
+    }
+
+    #[test]
+    fn a_is_the_gate() {
+        let mut controls = Controls::new();
+        assert_eq!(hold(&mut controls, 100, true, false, false), [Event::Gate(true)]);
+        // Long presses of A just keep the note going.
+        assert_eq!(hold(&mut controls, 1_000, true, false, false), []);
+        assert_eq!(hold(&mut controls, 100, false, false, false), [Event::Gate(false)]);
+    }
+
+    #[test]
+    fn b_cycles_waveforms_and_loopback() {
+        let mut controls = Controls::new();
+        assert_eq!(hold(&mut controls, 100, false, true, false), []);
+        assert_eq!(hold(&mut controls, 100, false, false, false), [Event::NextWaveform]);
+        assert_eq!(hold(&mut controls, 1_000, false, true, false), [Event::ToggleLoopback]);
+        assert_eq!(hold(&mut controls, 100, false, false, false), []);
+    }
+
+    #[test]
+    fn a_and_b_change_octave() {
+        let mut controls = Controls::new();
+        let mut octaves = vec![];
+        for _ in 0..5 {
+            hold(&mut controls, 100, true, true, false);
+            octaves.extend(hold(&mut controls, 100, false, false, false));
+        }
+        let expected = [1, 2, -2, -1, 0].map(Event::Octave);
+        assert_eq!(octaves, expected);
+        // Holding them puts the octave back.
+        hold(&mut controls, 100, true, true, false);
+        hold(&mut controls, 100, false, false, false);
+        assert_eq!(controls.octave(), 1);
+        assert_eq!(hold(&mut controls, 1_000, true, true, false), [Event::Octave(0)]);
+        assert_eq!(hold(&mut controls, 100, false, false, false), []);
+        assert_eq!(controls.octave(), 0);
+    }
+
+    #[test]
+    fn chords_do_not_trigger_their_buttons() {
+        let mut controls = Controls::new();
+        // A first sounds the note, until B joins it.
+        assert_eq!(hold(&mut controls, 50, true, false, false), [Event::Gate(true)]);
+        assert_eq!(hold(&mut controls, 50, true, true, false), [Event::Gate(false)]);
+        // Letting go of B first leaves A doing nothing.
+        assert_eq!(hold(&mut controls, 50, true, false, false), [Event::Octave(1)]);
+        assert_eq!(hold(&mut controls, 50, false, false, false), []);
+        // B first, then A.
+        assert_eq!(hold(&mut controls, 50, false, true, false), []);
+        assert_eq!(hold(&mut controls, 50, true, true, false), []);
+        assert_eq!(hold(&mut controls, 50, false, true, false), [Event::Octave(2)]);
+        assert_eq!(hold(&mut controls, 50, false, false, false), []);
+    }
+
+    #[test]
+    fn logo_as_gate() {
+        let mut controls = Controls::new();
+        assert_eq!(hold(&mut controls, 100, false, false, true), [Event::Gate(true)]);
+        // A as well changes nothing.
+        assert_eq!(hold(&mut controls, 100, true, false, true), []);
+        assert_eq!(hold(&mut controls, 100, true, false, false), []);
+        assert_eq!(hold(&mut controls, 100, false, false, false), [Event::Gate(false)]);
+    }
+
+    #[test]
+    fn logo_as_pedal() {
+        let mut controls = Controls::new();
+        controls.set_logo_mode(LogoMode::Sustain);
+        // The pedal on its own sounds nothing.
+        assert_eq!(hold(&mut controls, 100, false, false, true), []);
+        assert_eq!(hold(&mut controls, 100, false, false, false), []);
+        // Held, it keeps the note going after A is let go,
+        // and A opens the gate again for the note to be
+        // struck again.
+        assert_eq!(hold(&mut controls, 100, true, false, true), [Event::Gate(true)]);
+        assert_eq!(hold(&mut controls, 100, false, false, true), []);
+        assert_eq!(hold(&mut controls, 100, true, false, true), [Event::Gate(true)]);
+        assert_eq!(hold(&mut controls, 100, false, false, true), []);
+        assert_eq!(hold(&mut controls, 100, false, false, false), [Event::Gate(false)]);
+    }
+
+    #[test]
+    fn pedal_held_note_struck_again() {
+        use crate::{
+            envelope::{Adsr, Stage, UNITY},
+            mixer::Mixer,
+            osc::Waveform,
+            ToneGenerator,
+        };
+        let adsr = Adsr {
+            attack_ms: 100,
+            decay_ms: 50,
+            sustain: UNITY / 2,
+            release_ms: 100,
+        };
+        let mut mixer: Mixer<2> = Mixer::new(8_000, Waveform::Sine, adsr);
+        let mut controls = Controls::new();
+        controls.set_logo_mode(LogoMode::Sustain);
+        // As the firmware plays the gate, with the mixer
+        // running alongside the controls.
+        let mut play = |mixer: &mut Mixer<2>, ms: u32, a: bool, logo: bool| {
+            for _ in 0..ms / POLL_MS {
+                for event in controls.update(POLL_MS, a, false, logo) {
+                    match event {
+                        Event::Gate(true) => {
+                            mixer.note_on(0, 440.0, UNITY);
+                            mixer.retrigger(0);
+                        }
+                        Event::Gate(false) => mixer.note_off(0),
+                        _ => (),
+                    }
+                }
+                mixer.samples().take(80).for_each(drop);
+            }
+        };
+        play(&mut mixer, 200, true, true);
+        play(&mut mixer, 200, false, true);
+        assert_eq!(mixer.voice(0).env.stage(), Stage::Sustain);
+        // A again, with the pedal down: the attack restarts.
+        play(&mut mixer, 30, true, true);
+        assert_eq!(mixer.voice(0).env.stage(), Stage::Attack);
+        assert!(mixer.voice(0).env.level() > UNITY / 2);
+        // Both let go: released.
+        play(&mut mixer, 100, false, false);
+        assert_eq!(mixer.voice(0).env.stage(), Stage::Release);
+    }
+
+    #[test]
+    fn logo_modes_by_name() {
+        assert_eq!(LogoMode::from_name("Sustain"), Some(LogoMode::Sustain));
+        assert_eq!(LogoMode::from_name("GATE"), Some(LogoMode::Gate));
+        assert_eq!(LogoMode::from_name("pedal"), None);
+    }
+}
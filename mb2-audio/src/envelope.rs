@@ -0,0 +1,348 @@
+//! ADSR envelope generator.
+//!
+//! All segments are linear ramps in fixed point. The
+//! release ramp is computed from wherever the level is when
+//! the gate closes, so a release always reaches zero in at
+//! most the release time, whatever stage it interrupts.
+
+use crate::{Sample, ToneGenerator};
+
+/// Envelope level representing unity gain (Q15).
+pub const UNITY: u16 = 0x8000;
+
+/// Internal levels have 16 more fraction bits than output
+/// levels, so that slow ramps still move every sample.
+const FRAC_BITS: u32 = 16;
+const FULL: u32 = (UNITY as u32) << FRAC_BITS;
+
+/// Envelope shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adsr {
+    /// Time to ramp from silence to full level.
+    pub attack_ms: u32,
+    /// Time to ramp from full level to the sustain level.
+    pub decay_ms: u32,
+    /// Level held while the gate stays open: `0..=UNITY`.
+    pub sustain: u16,
+    /// Time to ramp from the current level to silence once
+    /// the gate closes.
+    pub release_ms: u32,
+}
+
+/// Current envelope segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Gate-driven ADSR envelope.
+pub struct Envelope {
+    sample_rate: u32,
+    adsr: Adsr,
+    attack_step: u32,
+    decay_step: u32,
+    sustain: u32,
+    release_step: u32,
+    stage: Stage,
+    level: u32,
+}
+
+impl Envelope {
+    pub fn new(sample_rate: u32, adsr: Adsr) -> Self {
+        let mut env = Self {
+            sample_rate,
+            adsr,
+            attack_step: 0,
+            decay_step: 0,
+            sustain: 0,
+            release_step: 0,
+            stage: Stage::Idle,
+            level: 0,
+        };
+        env.set_adsr(adsr);
+        env
+    }
+
+    pub fn adsr(&self) -> Adsr {
+        self.adsr
+    }
+
+    /// Change shape. Takes effect from the next sample; a
+    /// release in progress keeps its current ramp.
+    pub fn set_adsr(&mut self, adsr: Adsr) {
+        self.adsr = adsr;
+        self.sustain = (adsr.sustain.min(UNITY) as u32) << FRAC_BITS;
+        self.attack_step = self.step(FULL, adsr.attack_ms);
+        self.decay_step = self.step(FULL - self.sustain, adsr.decay_ms);
+    }
+
+    /// Per-sample step to ramp through `distance` in `ms`,
+    /// rounded up so the ramp never takes longer.
+    fn step(&self, distance: u32, ms: u32) -> u32 {
+        let samples = (self.sample_rate as u64 * ms as u64 / 1_000).max(1);
+        (distance as u64).div_ceil(samples).max(1) as u32
+    }
+
+    /// Open the gate: attack from the current level.
+    pub fn gate_on(&mut self) {
+        if matches!(self.stage, Stage::Idle | Stage::Release) {
+            self.stage = Stage::Attack;
+        }
+    }
+
+    /// Attack again from the current level, whatever the
+    /// stage, as when a held note is struck again.
+    pub fn retrigger(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    /// Close the gate: release from the current level.
+    pub fn gate_off(&mut self) {
+        if !matches!(self.stage, Stage::Idle | Stage::Release) {
+            self.stage = Stage::Release;
+            self.release_step = self.step(self.level, self.adsr.release_ms);
+        }
+    }
+
+    /// Open or close the gate.
+    pub fn set_gate(&mut self, on: bool) {
+        if on {
+            self.gate_on();
+        } else {
+            self.gate_off();
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// True unless the envelope has finished its release
+    /// (or never started).
+    pub fn is_active(&self) -> bool {
+        self.stage != Stage::Idle
+    }
+
+    /// Current level, `0..=UNITY`.
+    pub fn level(&self) -> u16 {
+        (self.level >> FRAC_BITS) as u16
+    }
+
+    /// Advance one sample, returning the new level.
+    pub fn next_level(&mut self) -> u16 {
+        match self.stage {
+            Stage::Idle | Stage::Sustain => (),
+            Stage::Attack => {
+                self.level = (self.level + self.attack_step).min(FULL);
+                if self.level == FULL {
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level = self.level.saturating_sub(self.decay_step).max(self.sustain);
+                if self.level == self.sustain {
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Release => {
+                self.level = self.level.saturating_sub(self.release_step);
+                if self.level == 0 {
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.level()
+    }
+
+    /// Advance one sample and scale `sample` by the level.
+    pub fn apply(&mut self, sample: Sample) -> Sample {
+        let level = self.next_level() as i32;
+        ((sample as i32 * level) >> 15) as Sample
+    }
+}
+
+/// A tone generator shaped by an envelope.
+pub struct Enveloped<G> {
+    pub tone: G,
+    pub env: Envelope,
+}
+
+impl<G: ToneGenerator> Enveloped<G> {
+    pub fn new(tone: G, adsr: Adsr) -> Self {
+        let env = Envelope::new(tone.sample_rate(), adsr);
+        Self { tone, env }
+    }
+}
+
+impl<G: ToneGenerator> ToneGenerator for Enveloped<G> {
+    fn sample_rate(&self) -> u32 {
+        self.tone.sample_rate()
+    }
+
+    fn next_sample(&mut self) -> Sample {
+        let sample = self.tone.next_sample();
+        self.env.apply(sample)
+    }
+
+    fn reset(&mut self) {
+        self.tone.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1_000;
+
+    const ADSR: Adsr = Adsr {
+        attack_ms: 100,
+        decay_ms: 200,
+        sustain: UNITY / 2,
+        release_ms: 400,
+    };
+
+    fn levels(env: &mut Envelope, n: usize) -> Vec<u16> {
+        (0..n).map(|_| env.next_level()).collect()
+    }
+
+    #[test]
+    fn idle_until_gated() {
+        let mut env = Envelope::new(RATE, ADSR);
+        assert!(!env.is_active());
+        assert!(levels(&mut env, 10).iter().all(|&l| l == 0));
+    }
+
+    #[test]
+    fn curve_shape() {
+        let mut env = Envelope::new(RATE, ADSR);
+        env.gate_on();
+        // Attack: linear to full in 100 samples.
+        let attack = levels(&mut env, 100);
+        for (i, &l) in attack.iter().enumerate() {
+            let expected = (i + 1) as f64 / 100.0 * UNITY as f64;
+            assert!((l as f64 - expected).abs() <= 1.0, "attack {}: {}", i, l);
+        }
+        assert_eq!(env.level(), UNITY);
+        assert_eq!(env.stage(), Stage::Decay);
+        // Decay: linear down to sustain in 200 samples.
+        let decay = levels(&mut env, 200);
+        for (i, &l) in decay.iter().enumerate() {
+            let expected = UNITY as f64 - (i + 1) as f64 / 200.0 * (UNITY / 2) as f64;
+            assert!((l as f64 - expected).abs() <= 1.0, "decay {}: {}", i, l);
+        }
+        assert_eq!(env.stage(), Stage::Sustain);
+        // Sustain: hold.
+        assert!(levels(&mut env, 1_000).iter().all(|&l| l == UNITY / 2));
+        // Release: linear to zero in 400 samples.
+        env.gate_off();
+        let release = levels(&mut env, 400);
+        for (i, &l) in release.iter().enumerate() {
+            let expected = (UNITY / 2) as f64 * (1.0 - (i + 1) as f64 / 400.0);
+            assert!((l as f64 - expected).abs() <= 1.0, "release {}: {}", i, l);
+        }
+        assert_eq!(env.level(), 0);
+        assert!(!env.is_active());
+    }
+
+    #[test]
+    fn release_always_completes() {
+        // Let go at every point through attack and decay, and
+        // in sustain.
+        for hold in 0..400 {
+            let mut env = Envelope::new(RATE, ADSR);
+            env.gate_on();
+            levels(&mut env, hold);
+            env.gate_off();
+            let release = levels(&mut env, 400);
+            assert_eq!(*release.last().unwrap(), 0, "held {}", hold);
+            assert!(!env.is_active(), "held {}", hold);
+            assert!(release.windows(2).all(|w| w[1] <= w[0]));
+        }
+    }
+
+    #[test]
+    fn release_completes_with_odd_timing() {
+        let adsr = Adsr {
+            attack_ms: 7,
+            decay_ms: 13,
+            sustain: 12_345,
+            release_ms: 3,
+        };
+        for rate in [8_000, 11_025, 16_000, 32_000] {
+            let mut env = Envelope::new(rate, adsr);
+            env.gate_on();
+            levels(&mut env, 1_000);
+            env.gate_off();
+            let samples = (rate * 3 / 1_000) as usize;
+            levels(&mut env, samples);
+            assert!(!env.is_active(), "rate {}", rate);
+        }
+    }
+
+    #[test]
+    fn zero_times_jump() {
+        let adsr = Adsr {
+            attack_ms: 0,
+            decay_ms: 0,
+            sustain: UNITY,
+            release_ms: 0,
+        };
+        let mut env = Envelope::new(RATE, adsr);
+        env.gate_on();
+        assert_eq!(env.next_level(), UNITY);
+        env.gate_off();
+        assert_eq!(env.next_level(), 0);
+        assert!(!env.is_active());
+    }
+
+    #[test]
+    fn retrigger_during_release_continues_from_level() {
+        let mut env = Envelope::new(RATE, ADSR);
+        env.gate_on();
+        levels(&mut env, 500);
+        env.gate_off();
+        levels(&mut env, 100);
+        let before = env.level();
+        env.gate_on();
+        assert_eq!(env.stage(), Stage::Attack);
+        let after = env.next_level();
+        assert!(after > before && after - before < 400);
+    }
+
+    #[test]
+    fn retrigger_restarts_attack_from_level() {
+        let mut env = Envelope::new(RATE, ADSR);
+        env.gate_on();
+        levels(&mut env, 5_000);
+        assert_eq!(env.stage(), Stage::Sustain);
+        let before = env.level();
+        env.gate_on();
+        assert_eq!(env.stage(), Stage::Sustain);
+        env.retrigger();
+        assert_eq!(env.stage(), Stage::Attack);
+        assert!(env.next_level() > before);
+    }
+
+    #[test]
+    fn apply_scales_samples() {
+        let mut env = Envelope::new(RATE, ADSR);
+        env.gate_on();
+        levels(&mut env, 500);
+        assert_eq!(env.apply(Sample::MAX), Sample::MAX / 2);
+        assert_eq!(env.apply(Sample::MIN), Sample::MIN / 2);
+    }
+
+    #[test]
+    fn enveloped_tone_silent_when_idle() {
+        let square = crate::square::Square::new(RATE, 100);
+        let mut tone = Enveloped::new(square, ADSR);
+        assert!(tone.samples().take(50).all(|s| s == 0));
+        tone.env.gate_on();
+        assert!(tone.samples().take(50).any(|s| s != 0));
+    }
+}
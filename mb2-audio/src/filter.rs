@@ -0,0 +1,306 @@
+//! Filters for captured audio.
+//!
+//! Besides removing the microphone's DC offset, these keep
+//! a live microphone from howling through the speaker: an
+//! [AdaptiveNotch] homes in on the strongest tone, which is
+//! what feedback sounds like, and a [Limiter] catches
+//! whatever gets past it.
+
+use crate::Sample;
+
+/// One-pole DC-blocking high-pass filter:
+/// `y[n] = x[n] - x[n-1] + r * y[n-1]`.
+///
+/// The output is kept with 16 extra fraction bits, so the
+/// feedback does not leave a residual offset from rounding.
+pub struct DcBlocker {
+    /// Pole in Q15.
+    pole: i64,
+    x1: i64,
+    y1: i64,
+}
+
+impl DcBlocker {
+    /// A filter at `sample_rate` with its -3dB point at
+    /// about `cutoff_hz`.
+    pub fn new(sample_rate: u32, cutoff_hz: f32) -> Self {
+        let pole = 1.0 - 2.0 * core::f32::consts::PI * cutoff_hz / sample_rate as f32;
+        Self {
+            pole: (pole.clamp(0.0, 1.0) * 32_768.0) as i64,
+            x1: 0,
+            y1: 0,
+        }
+    }
+
+    pub fn process(&mut self, x: Sample) -> Sample {
+        let x = x as i64;
+        let y = ((x - self.x1) << 16) + ((self.y1 * self.pole) >> 15);
+        self.x1 = x;
+        self.y1 = y;
+        (y >> 16).clamp(Sample::MIN as i64, Sample::MAX as i64) as Sample
+    }
+
+    pub fn reset(&mut self) {
+        self.x1 = 0;
+        self.y1 = 0;
+    }
+}
+
+/// Default pole radius of an [AdaptiveNotch]: at 16kHz the
+/// notch is about 250Hz wide.
+pub const NOTCH_RADIUS: f32 = 0.95;
+
+/// Default adaptation rate of an [AdaptiveNotch].
+pub const NOTCH_RATE: f32 = 0.05;
+
+/// Second-order IIR notch that tracks the strongest tone in
+/// its input:
+/// `y[n] = x[n] - a x[n-1] + x[n-2] + r a y[n-1] - r² y[n-2]`.
+///
+/// The zeros sit on the unit circle at the angle given by
+/// `a = 2 cos(w)`, and the poles just inside them at radius
+/// `r`. Each sample nudges `a` down the gradient of the
+/// output power, normalized by the input power so that the
+/// rate does not depend on level.
+pub struct AdaptiveNotch {
+    r: f32,
+    rate: f32,
+    a: f32,
+    power: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Default for AdaptiveNotch {
+    fn default() -> Self {
+        Self::new(NOTCH_RADIUS, NOTCH_RATE)
+    }
+}
+
+impl AdaptiveNotch {
+    /// A notch with poles at radius `r`, adapting at `rate`.
+    /// It starts at a quarter of the sample rate.
+    pub fn new(r: f32, rate: f32) -> Self {
+        Self {
+            r,
+            rate,
+            a: 0.0,
+            power: 0.0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    /// Frequency of the notch as a fraction of the sample
+    /// rate, from 0 to 0.5.
+    pub fn frequency(&self) -> f32 {
+        acos(self.a / 2.0) / (2.0 * core::f32::consts::PI)
+    }
+
+    pub fn process(&mut self, x: Sample) -> Sample {
+        let x = x as f32 / 32_768.0;
+        let r = self.r;
+        let y = x - self.a * self.x1 + self.x2 + r * self.a * self.y1 - r * r * self.y2;
+        // Derivative of y with respect to a, near enough.
+        let slope = r * self.y1 - self.x1;
+        self.power += (self.x1 * self.x1 - self.power) / 100.0;
+        self.a -= self.rate * y * slope / (self.power + 1e-6);
+        self.a = self.a.clamp(-1.99, 1.99);
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        (y * 32_768.0).clamp(Sample::MIN as f32, Sample::MAX as f32) as Sample
+    }
+
+    /// Clear the filter state, keeping the notch frequency.
+    pub fn reset(&mut self) {
+        self.power = 0.0;
+        self.x1 = 0.0;
+        self.x2 = 0.0;
+        self.y1 = 0.0;
+        self.y2 = 0.0;
+    }
+}
+
+/// Arccosine from its polynomial approximation (Abramowitz
+/// and Stegun 4.4.45), good to about 1e-4 radians.
+fn acos(x: f32) -> f32 {
+    let ax = x.abs().min(1.0);
+    let poly = 1.570_728_8 + ax * (-0.212_114_4 + ax * (0.074_261 - ax * 0.018_729_3));
+    let mut root = 1.0 - ax;
+    // Newton's method: core has no sqrt.
+    let mut s = 1.0;
+    for _ in 0..8 {
+        s = 0.5 * (s + root / s);
+    }
+    root = if ax < 1.0 { s } else { 0.0 };
+    let y = root * poly;
+    if x < 0.0 {
+        core::f32::consts::PI - y
+    } else {
+        y
+    }
+}
+
+/// Peak limiter: the gain drops at once to keep the output
+/// within the threshold, and recovers slowly.
+pub struct Limiter {
+    threshold: f32,
+    /// Per-sample decay of the envelope.
+    release: f32,
+    envelope: f32,
+}
+
+impl Limiter {
+    /// A limiter at `sample_rate` holding peaks to
+    /// `threshold`, whose gain recovers by a factor of e
+    /// every `release_ms`.
+    pub fn new(sample_rate: u32, threshold: Sample, release_ms: u32) -> Self {
+        let samples = (sample_rate as f32 * release_ms as f32 / 1_000.0).max(1.0);
+        Self {
+            threshold: threshold.max(1) as f32,
+            release: 1.0 - 1.0 / samples,
+            envelope: 0.0,
+        }
+    }
+
+    /// Current gain, from 0 to 1.
+    pub fn gain(&self) -> f32 {
+        if self.envelope > self.threshold {
+            self.threshold / self.envelope
+        } else {
+            1.0
+        }
+    }
+
+    pub fn process(&mut self, x: Sample) -> Sample {
+        let x = x as f32;
+        self.envelope = (self.envelope * self.release).max(x.abs());
+        (x * self.gain()) as Sample
+    }
+
+    pub fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{osc::Oscillator, osc::Waveform, ToneGenerator};
+
+    fn peak(samples: &[Sample]) -> i32 {
+        samples.iter().map(|&s| (s as i32).abs()).max().unwrap()
+    }
+
+    #[test]
+    fn offset_removed() {
+        let mut dc = DcBlocker::new(16_000, 20.0);
+        let out: Vec<_> = (0..16_000).map(|_| dc.process(16_000)).collect();
+        // A step, decaying to nothing.
+        assert!(out[0] > 15_000);
+        assert!(out[8_000..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn audio_passed() {
+        let mut dc = DcBlocker::new(16_000, 20.0);
+        let mut osc = Oscillator::new(16_000, Waveform::Sine, 440.0);
+        let out: Vec<_> = osc
+            .samples()
+            .take(16_000)
+            .map(|s| dc.process(s / 2 + 12_000))
+            .collect();
+        // Centred on zero, at close to full amplitude.
+        let tail = &out[8_000..];
+        assert!((16_000..=16_600).contains(&peak(tail)));
+        let mean = tail.iter().map(|&s| s as i64).sum::<i64>() / tail.len() as i64;
+        assert!(mean.abs() < 50);
+    }
+
+    #[test]
+    fn low_frequencies_cut() {
+        let mut dc = DcBlocker::new(16_000, 100.0);
+        let mut osc = Oscillator::new(16_000, Waveform::Sine, 10.0);
+        let out: Vec<_> = osc.samples().take(32_000).map(|s| dc.process(s)).collect();
+        assert!(peak(&out[16_000..]) < 32_767 / 8);
+    }
+
+    #[test]
+    fn extreme_input_saturates() {
+        let mut dc = DcBlocker::new(16_000, 20.0);
+        assert_eq!(dc.process(Sample::MAX), Sample::MAX);
+        assert_eq!(dc.process(Sample::MIN), Sample::MIN);
+        dc.reset();
+        assert_eq!(dc.process(0), 0);
+    }
+
+    fn rms(samples: &[Sample]) -> f32 {
+        let sum: f64 = samples.iter().map(|&s| (s as f64).powi(2)).sum();
+        (sum / samples.len() as f64).sqrt() as f32
+    }
+
+    #[test]
+    fn notch_finds_tone() {
+        for freq in [1_000.0, 2_500.0, 5_000.0] {
+            let mut notch = AdaptiveNotch::default();
+            let mut osc = Oscillator::new(16_000, Waveform::Sine, freq);
+            let input: Vec<_> = osc.samples().take(16_000).map(|s| s / 2).collect();
+            let out: Vec<_> = input.iter().map(|&s| notch.process(s)).collect();
+            // Down by more than 20dB after half a second.
+            assert!(rms(&out[8_000..]) < rms(&input[8_000..]) / 10.0, "{freq}Hz");
+            let found = notch.frequency() * 16_000.0;
+            assert!((found - freq).abs() < 20.0, "{freq}Hz: {found}Hz");
+        }
+    }
+
+    #[test]
+    fn notch_passes_other_frequencies() {
+        // Slow enough to take a while to move off the howl.
+        let mut notch = AdaptiveNotch::new(NOTCH_RADIUS, 0.005);
+        let mut howl = Oscillator::new(16_000, Waveform::Sine, 2_500.0);
+        for s in howl.samples().take(16_000) {
+            notch.process(s / 2);
+        }
+        let mut voice = Oscillator::new(16_000, Waveform::Sine, 600.0);
+        let input: Vec<_> = voice.samples().take(800).map(|s| s / 2).collect();
+        let out: Vec<_> = input.iter().map(|&s| notch.process(s)).collect();
+        assert!(rms(&out[400..]) > rms(&input[400..]) * 0.9);
+    }
+
+    #[test]
+    fn acos_accuracy() {
+        for i in -100..=100 {
+            let x = i as f32 / 100.0;
+            assert!((acos(x) - x.acos()).abs() < 1e-3, "{x}");
+        }
+    }
+
+    #[test]
+    fn limiter_caps_peaks() {
+        let mut limiter = Limiter::new(16_000, 8_000, 100);
+        let mut osc = Oscillator::new(16_000, Waveform::Sine, 440.0);
+        let out: Vec<_> = osc.samples().take(1_600).map(|s| limiter.process(s)).collect();
+        assert!(peak(&out) <= 8_000);
+        assert!(peak(&out[800..]) > 7_500);
+    }
+
+    #[test]
+    fn limiter_recovers() {
+        let mut limiter = Limiter::new(16_000, 8_000, 10);
+        limiter.process(Sample::MAX);
+        assert!(limiter.gain() < 0.25);
+        // Quiet signals pass untouched once released.
+        let out: Vec<_> = (0..1_600).map(|_| limiter.process(4_000)).collect();
+        assert_eq!(out[1_599], 4_000);
+        assert_eq!(limiter.gain(), 1.0);
+        limiter.process(Sample::MAX);
+        limiter.reset();
+        assert_eq!(limiter.process(1_000), 1_000);
+    }
+}
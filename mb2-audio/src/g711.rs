@@ -0,0 +1,324 @@
+//! G.711 companding: mu-law and A-law.
+//!
+//! G.711 squeezes each sample into a byte by spacing the
+//! levels it can take logarithmically: fine steps near
+//! silence, coarse ones when loud, where the ear cannot
+//! tell. A byte holds a sign, a segment number (three bits)
+//! and a step within the segment (four bits), with some of
+//! the bits inverted so that silence on the line is not a
+//! run of zeros. North American and Japanese telephony uses
+//! mu-law; the rest of the world uses A-law.
+//!
+//! The encoders are `const fn`s working straight from the
+//! bits of the sample; the decoders look up tables built at
+//! compile time. Samples are 16-bit, scaled up from G.711's
+//! 14-bit (mu-law) or 13-bit (A-law) values.
+
+use crate::Sample;
+
+/// WAV `fmt ` format tag for A-law.
+pub const FORMAT_ALAW: u16 = 6;
+
+/// WAV `fmt ` format tag for mu-law.
+pub const FORMAT_MULAW: u16 = 7;
+
+/// Offset added to mu-law magnitudes so that the segments
+/// start at powers of two.
+const MULAW_BIAS: i32 = 33;
+
+/// Largest mu-law magnitude, in 14-bit units.
+const MULAW_CLIP: i32 = 8_158;
+
+/// Encode `sample` as mu-law.
+pub const fn encode_mulaw(sample: Sample) -> u8 {
+    let mut magnitude = (sample >> 2) as i32;
+    let mask = if magnitude < 0 {
+        magnitude = -magnitude;
+        0x7f
+    } else {
+        0xff
+    };
+    if magnitude > MULAW_CLIP {
+        magnitude = MULAW_CLIP;
+    }
+    magnitude += MULAW_BIAS;
+    // The top bit is bit 5 in the first segment.
+    let segment = 31 - magnitude.leading_zeros() as i32 - 5;
+    let step = (magnitude >> (segment + 1)) & 0xf;
+    ((segment << 4 | step) as u8) ^ mask
+}
+
+/// Encode `sample` as A-law.
+pub const fn encode_alaw(sample: Sample) -> u8 {
+    let mut magnitude = (sample >> 3) as i32;
+    let mask = if magnitude < 0 {
+        magnitude = !magnitude;
+        0x55
+    } else {
+        0xd5
+    };
+    // The first two segments have the same step.
+    let segment = if magnitude < 32 {
+        0
+    } else {
+        31 - magnitude.leading_zeros() as i32 - 4
+    };
+    let step = (magnitude >> if segment < 2 { 1 } else { segment }) & 0xf;
+    ((segment << 4 | step) as u8) ^ mask
+}
+
+const fn mulaw_value(code: u8) -> Sample {
+    let code = !code;
+    let segment = (code >> 4) & 7;
+    let t = ((((code & 0xf) as i32) << 3) + (MULAW_BIAS << 2)) << segment;
+    let magnitude = t - (MULAW_BIAS << 2);
+    (if code & 0x80 != 0 { -magnitude } else { magnitude }) as Sample
+}
+
+const fn alaw_value(code: u8) -> Sample {
+    let code = code ^ 0x55;
+    let segment = (code >> 4) & 7;
+    let t = ((code & 0xf) as i32) << 4;
+    let magnitude = match segment {
+        0 => t + 8,
+        1 => t + 0x108,
+        _ => (t + 0x108) << (segment - 1),
+    };
+    (if code & 0x80 != 0 { magnitude } else { -magnitude }) as Sample
+}
+
+const fn table(law: Law) -> [Sample; 256] {
+    let mut table = [0; 256];
+    let mut code = 0;
+    while code < 256 {
+        table[code] = match law {
+            Law::MuLaw => mulaw_value(code as u8),
+            Law::ALaw => alaw_value(code as u8),
+        };
+        code += 1;
+    }
+    table
+}
+
+static MULAW_TABLE: [Sample; 256] = table(Law::MuLaw);
+
+static ALAW_TABLE: [Sample; 256] = table(Law::ALaw);
+
+/// Decode a mu-law byte.
+pub fn decode_mulaw(code: u8) -> Sample {
+    MULAW_TABLE[code as usize]
+}
+
+/// Decode an A-law byte.
+pub fn decode_alaw(code: u8) -> Sample {
+    ALAW_TABLE[code as usize]
+}
+
+/// A G.711 companding law.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Law {
+    MuLaw,
+    ALaw,
+}
+
+impl Law {
+    pub fn name(self) -> &'static str {
+        match self {
+            Law::MuLaw => "mulaw",
+            Law::ALaw => "alaw",
+        }
+    }
+
+    /// The law called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Law::MuLaw, Law::ALaw]
+            .into_iter()
+            .find(|law| law.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn encode(self, sample: Sample) -> u8 {
+        match self {
+            Law::MuLaw => encode_mulaw(sample),
+            Law::ALaw => encode_alaw(sample),
+        }
+    }
+
+    pub fn decode(self, code: u8) -> Sample {
+        match self {
+            Law::MuLaw => decode_mulaw(code),
+            Law::ALaw => decode_alaw(code),
+        }
+    }
+
+    /// Encode `samples` into `codes`, as many as fit.
+    /// Returns the number encoded.
+    pub fn encode_slice(self, samples: &[Sample], codes: &mut [u8]) -> usize {
+        for (code, &sample) in codes.iter_mut().zip(samples) {
+            *code = self.encode(sample);
+        }
+        samples.len().min(codes.len())
+    }
+
+    /// Iterator over the samples of `codes`.
+    pub fn decode_iter(self, codes: &[u8]) -> impl ExactSizeIterator<Item = Sample> + Clone + '_ {
+        codes.iter().map(move |&code| self.decode(code))
+    }
+
+    /// `sample` as it comes out the other end of a G.711
+    /// link.
+    pub fn companded(self, sample: Sample) -> Sample {
+        self.decode(self.encode(sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The decoder outputs of G.711 Tables 1a and 2a, scaled
+    // to 16 bits, for the positive codes 0x80 to 0xff in
+    // order. A negative code decodes to the negation of its
+    // positive twin.
+    const MULAW_REFERENCE: [Sample; 128] = [
+        32_124, 31_100, 30_076, 29_052, 28_028, 27_004, 25_980, 24_956,
+        23_932, 22_908, 21_884, 20_860, 19_836, 18_812, 17_788, 16_764,
+        15_996, 15_484, 14_972, 14_460, 13_948, 13_436, 12_924, 12_412,
+        11_900, 11_388, 10_876, 10_364, 9_852, 9_340, 8_828, 8_316,
+        7_932, 7_676, 7_420, 7_164, 6_908, 6_652, 6_396, 6_140,
+        5_884, 5_628, 5_372, 5_116, 4_860, 4_604, 4_348, 4_092,
+        3_900, 3_772, 3_644, 3_516, 3_388, 3_260, 3_132, 3_004,
+        2_876, 2_748, 2_620, 2_492, 2_364, 2_236, 2_108, 1_980,
+        1_884, 1_820, 1_756, 1_692, 1_628, 1_564, 1_500, 1_436,
+        1_372, 1_308, 1_244, 1_180, 1_116, 1_052, 988, 924,
+        876, 844, 812, 780, 748, 716, 684, 652,
+        620, 588, 556, 524, 492, 460, 428, 396,
+        372, 356, 340, 324, 308, 292, 276, 260,
+        244, 228, 212, 196, 180, 164, 148, 132,
+        120, 112, 104, 96, 88, 80, 72, 64,
+        56, 48, 40, 32, 24, 16, 8, 0,
+    ];
+
+    const ALAW_REFERENCE: [Sample; 128] = [
+        5_504, 5_248, 6_016, 5_760, 4_480, 4_224, 4_992, 4_736,
+        7_552, 7_296, 8_064, 7_808, 6_528, 6_272, 7_040, 6_784,
+        2_752, 2_624, 3_008, 2_880, 2_240, 2_112, 2_496, 2_368,
+        3_776, 3_648, 4_032, 3_904, 3_264, 3_136, 3_520, 3_392,
+        22_016, 20_992, 24_064, 23_040, 17_920, 16_896, 19_968, 18_944,
+        30_208, 29_184, 32_256, 31_232, 26_112, 25_088, 28_160, 27_136,
+        11_008, 10_496, 12_032, 11_520, 8_960, 8_448, 9_984, 9_472,
+        15_104, 14_592, 16_128, 15_616, 13_056, 12_544, 14_080, 13_568,
+        344, 328, 376, 360, 280, 264, 312, 296,
+        472, 456, 504, 488, 408, 392, 440, 424,
+        88, 72, 120, 104, 24, 8, 56, 40,
+        216, 200, 248, 232, 152, 136, 184, 168,
+        1_376, 1_312, 1_504, 1_440, 1_120, 1_056, 1_248, 1_184,
+        1_888, 1_824, 2_016, 1_952, 1_632, 1_568, 1_760, 1_696,
+        688, 656, 752, 720, 560, 528, 624, 592,
+        944, 912, 1_008, 976, 816, 784, 880, 848,
+    ];
+
+    // The encoder decision values of Tables 1a and 2a: where
+    // each segment starts, and the width of its 16 intervals,
+    // in 13-bit (A-law) or 14-bit (mu-law) units. Magnitudes
+    // past the last interval are clipped to it.
+    const MULAW_SEGMENTS: [(i32, i32); 8] = [
+        (-1, 2), (31, 4), (95, 8), (223, 16), (479, 32), (991, 64), (2_015, 128), (4_063, 256),
+    ];
+    const ALAW_SEGMENTS: [(i32, i32); 8] = [
+        (0, 2), (32, 2), (64, 4), (128, 8), (256, 16), (512, 32), (1_024, 64), (2_048, 128),
+    ];
+
+    /// The code for `magnitude` by the decision values, with
+    /// the bits in `mask` inverted.
+    fn reference_code(segments: &[(i32, i32); 8], magnitude: i32, mask: u8) -> u8 {
+        let segment = segments.iter().rposition(|&(start, _)| start <= magnitude).unwrap();
+        let (start, width) = segments[segment];
+        let step = ((magnitude - start) / width).min(15);
+        (segment << 4 | step as usize) as u8 ^ mask
+    }
+
+    #[test]
+    fn decoders_match_reference() {
+        for code in 0..=255u8 {
+            let positive = (code & 0x7f) as usize;
+            let (mu, a) = (MULAW_REFERENCE[positive], ALAW_REFERENCE[positive]);
+            let sign = if code & 0x80 != 0 { 1 } else { -1 };
+            assert_eq!(decode_mulaw(code), sign * mu, "{:#x}", code);
+            assert_eq!(decode_alaw(code), sign * a, "{:#x}", code);
+        }
+    }
+
+    #[test]
+    fn mulaw_encoder_matches_reference() {
+        for sample in Sample::MIN..=Sample::MAX {
+            let units = sample as i32 >> 2;
+            let expected = if units < 0 {
+                reference_code(&MULAW_SEGMENTS, -units, 0x7f)
+            } else {
+                reference_code(&MULAW_SEGMENTS, units, 0xff)
+            };
+            assert_eq!(encode_mulaw(sample), expected, "{}", sample);
+        }
+    }
+
+    #[test]
+    fn alaw_encoder_matches_reference() {
+        for sample in Sample::MIN..=Sample::MAX {
+            let units = sample as i32 >> 3;
+            let expected = if units < 0 {
+                reference_code(&ALAW_SEGMENTS, -units - 1, 0x55)
+            } else {
+                reference_code(&ALAW_SEGMENTS, units, 0xd5)
+            };
+            assert_eq!(encode_alaw(sample), expected, "{}", sample);
+        }
+    }
+
+    #[test]
+    fn decoded_values_encode_to_themselves() {
+        for law in [Law::MuLaw, Law::ALaw] {
+            for code in 0..=255u8 {
+                let value = law.decode(code);
+                // Mu-law has two codes for zero.
+                let code = if law == Law::MuLaw && value == 0 { 0xff } else { code };
+                assert_eq!(law.encode(value), code, "{} {:#x}", law.name(), code);
+            }
+        }
+    }
+
+    #[test]
+    fn error_follows_level() {
+        // Within half a step, and the step is about 1/16 of
+        // the segment: roughly 3% of the level at worst.
+        for law in [Law::MuLaw, Law::ALaw] {
+            for sample in Sample::MIN..=Sample::MAX {
+                let error = (law.companded(sample) as i32 - sample as i32).abs();
+                let allowed = (sample as i32).abs() / 30 + 16;
+                assert!(error <= allowed, "{} {} {}", law.name(), sample, error);
+            }
+        }
+        assert_eq!(Law::MuLaw.companded(0), 0);
+        assert_eq!(Law::ALaw.companded(0), 8);
+        assert_eq!(Law::MuLaw.companded(Sample::MAX), 32_124);
+        assert_eq!(Law::ALaw.companded(Sample::MIN), -32_256);
+    }
+
+    #[test]
+    fn slices() {
+        let samples = [0, 1_000, -1_000, Sample::MAX];
+        let mut codes = [0; 3];
+        assert_eq!(Law::ALaw.encode_slice(&samples, &mut codes), 3);
+        assert_eq!(codes, [0xd5, 0xfa, 0x7a]);
+        let decoded: Vec<Sample> = Law::ALaw.decode_iter(&codes).collect();
+        assert_eq!(decoded, [8, 1_008, -1_008]);
+        assert_eq!(Law::MuLaw.encode_slice(&samples, &mut codes), 3);
+        assert_eq!(codes, [0xff, 0xce, 0x4e]);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Law::from_name("MuLaw"), Some(Law::MuLaw));
+        assert_eq!(Law::from_name("alaw"), Some(Law::ALaw));
+        assert_eq!(Law::from_name("ulaw"), None);
+    }
+}
@@ -0,0 +1,76 @@
+//! Hardware-agnostic audio generation for the MicroBit v2.
+//!
+//! Everything here is `no_std` and knows nothing about the
+//! nRF52833: generators just produce samples, and the
+//! firmware decides how to get them out to the speaker. This
+//! lets the audio code be tested on the build host with
+//! `cargo test-host`.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod adpcm;
+pub mod blep;
+pub mod capture;
+pub mod console;
+pub mod controls;
+pub mod envelope;
+pub mod fft;
+pub mod filter;
+pub mod g711;
+pub mod loopback;
+pub mod lpc;
+pub mod meter;
+pub mod midi;
+pub mod mixer;
+pub mod osc;
+pub mod pwm;
+pub mod resample;
+pub mod rtttl;
+pub mod saadc;
+pub mod smf;
+pub mod songs;
+pub mod square;
+pub mod stream;
+pub mod synth;
+pub mod theremin;
+pub mod tuner;
+pub mod tuning;
+pub mod wav;
+pub mod wavetable;
+
+/// A single signed 16-bit audio sample.
+pub type Sample = i16;
+
+/// A source of audio samples at a fixed sample rate.
+pub trait ToneGenerator {
+    /// Sample rate of this generator in samples per second.
+    fn sample_rate(&self) -> u32;
+
+    /// Produce the next sample.
+    fn next_sample(&mut self) -> Sample;
+
+    /// Restart the generator from the beginning of its
+    /// waveform.
+    fn reset(&mut self);
+
+    /// Borrow this generator as an (infinite) iterator over
+    /// its samples.
+    fn samples(&mut self) -> Samples<'_, Self>
+    where
+        Self: Sized,
+    {
+        Samples(self)
+    }
+}
+
+/// Iterator over the samples of a [ToneGenerator]. Never
+/// ends: use [Iterator::take] to get a finite run.
+pub struct Samples<'a, G>(&'a mut G);
+
+impl<G: ToneGenerator> Iterator for Samples<'_, G> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        Some(self.0.next_sample())
+    }
+}
@@ -0,0 +1,113 @@
+//! Live microphone pass-through.
+//!
+//! The onboard microphone sits a couple of centimetres from
+//! the speaker, so played back with any gain it howls. A
+//! [Loopback] amplifies the microphone, notches out the
+//! strongest tone with an [AdaptiveNotch] and then limits
+//! what is left with a [Limiter]. It can also put the
+//! result through a [G.711](crate::g711) codec, to hear what
+//! the microphone would sound like over a telephone or radio
+//! link.
+
+use crate::{
+    filter::{AdaptiveNotch, Limiter},
+    g711::Law,
+    Sample,
+};
+
+/// Gain in percent when none is set.
+pub const DEFAULT_GAIN_PERCENT: u16 = 200;
+
+/// Highest gain in percent.
+pub const MAX_GAIN_PERCENT: u16 = 800;
+
+/// Level the output is limited to.
+pub const LIMIT: Sample = Sample::MAX / 2;
+
+/// How quickly the limiter lets go.
+pub const LIMIT_RELEASE_MS: u32 = 200;
+
+/// Microphone-to-speaker processing chain.
+pub struct Loopback {
+    gain: f32,
+    notch: AdaptiveNotch,
+    limiter: Limiter,
+    codec: Option<Law>,
+}
+
+impl Loopback {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            gain: DEFAULT_GAIN_PERCENT as f32 / 100.0,
+            notch: AdaptiveNotch::default(),
+            limiter: Limiter::new(sample_rate, LIMIT, LIMIT_RELEASE_MS),
+            codec: None,
+        }
+    }
+
+    /// Set the gain in percent, up to [MAX_GAIN_PERCENT].
+    pub fn set_gain(&mut self, percent: u16) {
+        self.gain = percent.min(MAX_GAIN_PERCENT) as f32 / 100.0;
+    }
+
+    /// Companding to put the output through, if any.
+    pub fn set_codec(&mut self, codec: Option<Law>) {
+        self.codec = codec;
+    }
+
+    pub fn process(&mut self, x: Sample) -> Sample {
+        let amplified = (x as f32 * self.gain).clamp(Sample::MIN as f32, Sample::MAX as f32);
+        let notched = self.notch.process(amplified as Sample);
+        let limited = self.limiter.process(notched);
+        match self.codec {
+            Some(law) => law.companded(limited),
+            None => limited,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.notch.reset();
+        self.limiter.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{osc::Oscillator, osc::Waveform, ToneGenerator};
+
+    #[test]
+    fn gain_applied() {
+        let mut loopback = Loopback::new(16_000);
+        loopback.set_gain(0);
+        assert_eq!(loopback.process(1_000), 0);
+        loopback.set_gain(300);
+        // The notch passes the first sample as it is.
+        assert_eq!(loopback.process(1_000), 3_000);
+    }
+
+    #[test]
+    fn codec_applied() {
+        let mut loopback = Loopback::new(16_000);
+        loopback.set_gain(100);
+        loopback.set_codec(Some(Law::MuLaw));
+        assert_eq!(loopback.process(1_000), 988);
+        loopback.set_codec(None);
+        assert_eq!(loopback.process(-1_000), -1_000);
+    }
+
+    #[test]
+    fn howl_suppressed() {
+        let mut loopback = Loopback::new(16_000);
+        loopback.set_gain(MAX_GAIN_PERCENT);
+        let mut osc = Oscillator::new(16_000, Waveform::Sine, 3_000.0);
+        let out: Vec<_> = osc
+            .samples()
+            .take(16_000)
+            .map(|s| loopback.process(s / 16))
+            .collect();
+        let peak = |s: &[Sample]| s.iter().map(|&s| (s as i32).abs()).max().unwrap();
+        assert!(peak(&out) <= LIMIT as i32);
+        assert!(peak(&out[8_000..]) < LIMIT as i32 / 10);
+    }
+}
@@ -0,0 +1,577 @@
+//! LPC speech in the style of the TI TMS5220, as in the
+//! Speak & Spell and the Arduino "Talkie" library.
+//!
+//! Linear predictive coding models speech as a buzz (voiced
+//! sounds) or a hiss (unvoiced ones) shaped by the vocal
+//! tract, a ten-stage lattice filter. Every 25ms a frame
+//! gives new values for the loudness, the pitch of the buzz
+//! and the filter's reflection coefficients, each as an
+//! index into a table of the chip's values:
+//!
+//! ```text
+//! energy  4 bits   0: silence, no more bits; 15: stop
+//! repeat  1 bit    1: keep the last coefficients
+//! pitch   6 bits   0: unvoiced
+//! k1-k2   5 bits each
+//! k3-k4   4 bits each
+//! k5-k7   4 bits each  } voiced frames only, and not
+//! k8-k10  3 bits each  } repeats
+//! ```
+//!
+//! Talkie streams the bits from each byte least significant
+//! first, with the first bit of each field its most
+//! significant, so existing Talkie word arrays play as they
+//! are. [Speech] renders a bitstream at [SAMPLE_RATE],
+//! moving the values from one frame towards the next over
+//! eight interpolation periods as the chip does.
+
+use crate::Sample;
+
+/// Sample rate of speech.
+pub const SAMPLE_RATE: u32 = 8_000;
+
+/// Samples in a frame: 25ms.
+pub const FRAME_SAMPLES: u32 = 200;
+
+/// Samples in each of the eight interpolation periods of a
+/// frame.
+const PERIOD_SAMPLES: u32 = FRAME_SAMPLES / 8;
+
+/// Energy index that ends the speech.
+const STOP_ENERGY: u8 = 15;
+
+// Parameter tables of the TMS5220. The reflection
+// coefficients are scaled by 512.
+
+const ENERGY: [i32; 16] = [0, 1, 2, 3, 4, 6, 8, 11, 16, 23, 33, 47, 63, 85, 114, 0];
+
+/// Pitch periods, in samples.
+const PITCH: [i32; 64] = [
+    0, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37,
+    38, 39, 40, 41, 42, 44, 46, 48, 50, 52, 53, 56, 58, 60, 62, 65, 68, 70, 72, 76, 78, 80, 84, 86,
+    91, 94, 98, 101, 105, 109, 114, 118, 122, 127, 132, 137, 142, 148, 153, 159,
+];
+
+const K1: [i32; 32] = [
+    -501, -498, -497, -495, -493, -491, -488, -482, -478, -474, -469, -464, -459, -452, -445, -437,
+    -412, -380, -339, -288, -227, -158, -81, -1, 80, 157, 226, 287, 337, 379, 411, 436,
+];
+
+const K2: [i32; 32] = [
+    -328, -303, -274, -244, -211, -175, -138, -99, -59, -18, 24, 64, 105, 143, 180, 215, 248, 278,
+    306, 331, 354, 374, 392, 408, 422, 435, 445, 455, 463, 470, 476, 506,
+];
+
+const K3: [i32; 16] = [
+    -441, -387, -333, -279, -225, -171, -117, -63, -9, 45, 98, 152, 206, 260, 314, 368,
+];
+
+const K4: [i32; 16] = [
+    -328, -273, -217, -161, -106, -50, 5, 61, 116, 172, 228, 283, 339, 394, 450, 506,
+];
+
+const K5: [i32; 16] = [
+    -328, -282, -235, -189, -142, -96, -50, -3, 43, 90, 136, 182, 229, 275, 322, 368,
+];
+
+const K6: [i32; 16] = [
+    -256, -212, -168, -123, -79, -35, 10, 54, 98, 143, 187, 232, 276, 320, 365, 409,
+];
+
+const K7: [i32; 16] = [
+    -308, -260, -212, -164, -117, -69, -21, 27, 75, 122, 170, 218, 266, 314, 361, 409,
+];
+
+const K8: [i32; 8] = [-256, -161, -66, 29, 124, 219, 314, 409];
+
+const K9: [i32; 8] = [-256, -176, -96, -15, 65, 146, 226, 307];
+
+const K10: [i32; 8] = [-205, -132, -59, 14, 87, 160, 234, 307];
+
+/// The coefficient tables, and the bits of each index.
+const K_TABLES: [(&[i32], u32); 10] = [
+    (&K1, 5),
+    (&K2, 5),
+    (&K3, 4),
+    (&K4, 4),
+    (&K5, 4),
+    (&K6, 4),
+    (&K7, 4),
+    (&K8, 3),
+    (&K9, 3),
+    (&K10, 3),
+];
+
+/// One period of the buzz of voiced speech.
+const CHIRP: [i8; 52] = [
+    0x00, 0x03, 0x0f, 0x28, 0x4c, 0x6c, 0x71, 0x50, 0x25, 0x26, 0x4c, 0x44, 0x1a, 0x32, 0x3b,
+    0x13, 0x37, 0x1a, 0x25, 0x1f, 0x1d, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Interpolation shift at the start of each period: the
+/// values move `1 / 2^shift` of the way to their targets.
+const SHIFTS: [u32; 8] = [0, 3, 3, 3, 2, 2, 1, 1];
+
+/// Excitation of unvoiced speech, plus or minus.
+const NOISE: i32 = 64;
+
+/// Limit of the lattice filter's values, which the chip
+/// holds to 14 bits and sign.
+const LATTICE_MAX: i32 = (1 << 14) - 1;
+
+/// Limit of the output: 12 bits and sign.
+const OUTPUT_MAX: i32 = (1 << 11) - 1;
+
+/// A frame of a bitstream, as table indices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+    /// No sound, keeping the other values.
+    Silence,
+    /// The end of the speech.
+    Stop,
+    /// New energy and pitch, keeping the coefficients.
+    Repeat { energy: u8, pitch: u8 },
+    /// Noise through the first four coefficients.
+    Unvoiced { energy: u8, k: [u8; 4] },
+    Voiced { energy: u8, pitch: u8, k: [u8; 10] },
+}
+
+/// Iterator over the frames of a bitstream. Ends at a stop
+/// frame, or at a frame cut short by the end of the data.
+#[derive(Debug, Clone)]
+pub struct Frames<'a> {
+    data: &'a [u8],
+    /// Next bit to read.
+    pos: usize,
+}
+
+impl<'a> Frames<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// The next `bits` bits, first bit most significant.
+    fn read(&mut self, bits: u32) -> Option<u8> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.data.get(self.pos / 8)?;
+            value = value << 1 | (byte >> (self.pos % 8)) & 1;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn frame(&mut self) -> Option<Frame> {
+        let energy = self.read(4)?;
+        if energy == 0 {
+            return Some(Frame::Silence);
+        }
+        if energy == STOP_ENERGY {
+            return Some(Frame::Stop);
+        }
+        let repeat = self.read(1)? == 1;
+        let pitch = self.read(6)?;
+        if repeat {
+            return Some(Frame::Repeat { energy, pitch });
+        }
+        let mut k = [0; 10];
+        let voiced = pitch != 0;
+        let count = if voiced { 10 } else { 4 };
+        for (k, &(_, bits)) in k.iter_mut().zip(&K_TABLES).take(count) {
+            *k = self.read(bits)?;
+        }
+        if voiced {
+            return Some(Frame::Voiced { energy, pitch, k });
+        }
+        Some(Frame::Unvoiced {
+            energy,
+            k: [k[0], k[1], k[2], k[3]],
+        })
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        match self.frame() {
+            Some(Frame::Stop) | None => {
+                self.pos = self.data.len() * 8;
+                None
+            }
+            frame => frame,
+        }
+    }
+}
+
+/// The synthesis values: energy, pitch period, and the ten
+/// coefficients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Params {
+    energy: i32,
+    pitch: i32,
+    k: [i32; 10],
+}
+
+impl Params {
+    /// These values changed by `frame`.
+    fn update(self, frame: Frame) -> Self {
+        let mut next = self;
+        match frame {
+            Frame::Silence | Frame::Stop => next.energy = 0,
+            Frame::Repeat { energy, pitch } => {
+                next.energy = ENERGY[energy as usize];
+                next.pitch = PITCH[pitch as usize];
+            }
+            Frame::Unvoiced { energy, k } => {
+                next.energy = ENERGY[energy as usize];
+                next.pitch = 0;
+                next.k = [0; 10];
+                for (i, &k) in k.iter().enumerate() {
+                    next.k[i] = K_TABLES[i].0[k as usize];
+                }
+            }
+            Frame::Voiced { energy, pitch, k } => {
+                next.energy = ENERGY[energy as usize];
+                next.pitch = PITCH[pitch as usize];
+                for (i, &k) in k.iter().enumerate() {
+                    next.k[i] = K_TABLES[i].0[k as usize];
+                }
+            }
+        }
+        next
+    }
+
+    /// Move `1 / 2^shift` of the way to `target`.
+    fn approach(&mut self, target: &Params, shift: u32) {
+        let step = |from: &mut i32, to: i32| *from += (to - *from) >> shift;
+        step(&mut self.energy, target.energy);
+        step(&mut self.pitch, target.pitch);
+        for (k, &to) in self.k.iter_mut().zip(&target.k) {
+            step(k, to);
+        }
+    }
+}
+
+/// Speech rendered from a bitstream, at [SAMPLE_RATE].
+#[derive(Debug, Clone)]
+pub struct Speech<'a> {
+    frames: Frames<'a>,
+    current: Params,
+    target: Params,
+    /// Sample within the frame.
+    t: u32,
+    /// Sample within the pitch period.
+    pitch_count: i32,
+    /// Noise generator.
+    rng: u16,
+    /// Lattice filter state.
+    x: [i32; 10],
+    /// Set by the last frame: fading out to the end.
+    stopping: bool,
+}
+
+impl<'a> Speech<'a> {
+    /// Speak the Talkie bitstream `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            frames: Frames::new(data),
+            current: Params::default(),
+            target: Params::default(),
+            t: 0,
+            pitch_count: 0,
+            rng: 1,
+            x: [0; 10],
+            stopping: false,
+        }
+    }
+
+    /// Start a frame: finish moving to the last one, and
+    /// take the next as the target. Returns `false` at the
+    /// end of the speech.
+    fn next_frame(&mut self) -> bool {
+        if self.stopping {
+            return false;
+        }
+        self.current = self.target;
+        let frame = self.frames.next().unwrap_or(Frame::Stop);
+        self.stopping = frame == Frame::Stop;
+        self.target = self.current.update(frame);
+        // No gliding into speech from silence, or between
+        // voiced and unvoiced.
+        let was_silent = self.current.energy == 0;
+        let is_silent = self.target.energy == 0;
+        let voicing_changes = (self.current.pitch == 0) != (self.target.pitch == 0);
+        if !is_silent && (was_silent || voicing_changes) {
+            self.current = self.target;
+        }
+        true
+    }
+
+    fn excitation(&mut self) -> i32 {
+        if self.current.pitch == 0 {
+            let bit = self.rng & 1;
+            self.rng >>= 1;
+            if bit != 0 {
+                self.rng ^= 0xb800;
+                NOISE
+            } else {
+                -NOISE
+            }
+        } else {
+            let chirp = CHIRP[(self.pitch_count as usize).min(CHIRP.len() - 1)] as i32;
+            self.pitch_count += 1;
+            if self.pitch_count >= self.current.pitch {
+                self.pitch_count = 0;
+            }
+            chirp
+        }
+    }
+
+    /// Run the excitation through the lattice filter.
+    fn filter(&mut self, excitation: i32) -> i32 {
+        let k = &self.current.k;
+        let limit = |v: i32| v.clamp(-LATTICE_MAX - 1, LATTICE_MAX);
+        let mut u = [0; 11];
+        u[10] = (excitation * self.current.energy) >> 3;
+        for i in (0..10).rev() {
+            u[i] = limit(u[i + 1] - ((k[i] * self.x[i]) >> 9));
+        }
+        for i in (1..10).rev() {
+            self.x[i] = limit(self.x[i - 1] + ((k[i - 1] * u[i - 1]) >> 9));
+        }
+        self.x[0] = u[0];
+        u[0]
+    }
+}
+
+impl Iterator for Speech<'_> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.t == 0 && !self.next_frame() {
+            return None;
+        }
+        if self.t > 0 && self.t.is_multiple_of(PERIOD_SAMPLES) {
+            let shift = SHIFTS[(self.t / PERIOD_SAMPLES) as usize];
+            self.current.approach(&self.target, shift);
+        }
+        self.t = (self.t + 1) % FRAME_SAMPLES;
+        let excitation = self.excitation();
+        let out = self.filter(excitation).clamp(-OUTPUT_MAX - 1, OUTPUT_MAX);
+        Some((out << 4) as Sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack `fields` of (bits, value) the way Talkie reads
+    /// them.
+    fn pack(fields: &[(u32, u8)]) -> Vec<u8> {
+        let mut data = vec![];
+        let mut pos = 0;
+        for &(bits, value) in fields {
+            for bit in (0..bits).rev() {
+                if pos % 8 == 0 {
+                    data.push(0);
+                }
+                *data.last_mut().unwrap() |= ((value >> bit) & 1) << (pos % 8);
+                pos += 1;
+            }
+        }
+        data
+    }
+
+    fn voiced(energy: u8, pitch: u8, k: [u8; 10]) -> Vec<(u32, u8)> {
+        let mut fields = vec![(4, energy), (1, 0), (6, pitch)];
+        fields.extend(K_TABLES.iter().zip(k).map(|(&(_, bits), k)| (bits, k)));
+        fields
+    }
+
+    #[test]
+    fn reads_talkie_bit_order() {
+        // Energy 0b0101, repeat, pitch 0b110011, each byte
+        // read from its lowest bit.
+        let mut frames = Frames::new(&[0b0111_1010, 0b0000_0110]);
+        assert_eq!(frames.next(), Some(Frame::Repeat { energy: 5, pitch: 0b110011 }));
+        // Then silence, and the end of the data.
+        assert_eq!(frames.next(), Some(Frame::Silence));
+        assert_eq!(frames.next(), None);
+        let fields = [(4, 9), (1, 0), (6, 0), (5, 31), (5, 1), (4, 8), (4, 15), (4, 15)];
+        let data = pack(&fields);
+        let mut frames = Frames::new(&data);
+        assert_eq!(frames.next(), Some(Frame::Unvoiced { energy: 9, k: [31, 1, 8, 15] }));
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    fn frames_end_at_stop_or_data() {
+        let mut fields = voiced(10, 40, [1, 2, 3, 4, 5, 6, 7, 0, 1, 2]);
+        fields.extend([(4, 0), (4, 15), (4, 10)]);
+        let data = pack(&fields);
+        let frames: Vec<Frame> = Frames::new(&data).collect();
+        assert_eq!(
+            frames,
+            [
+                Frame::Voiced { energy: 10, pitch: 40, k: [1, 2, 3, 4, 5, 6, 7, 0, 1, 2] },
+                Frame::Silence,
+            ]
+        );
+        // Cut short in the middle of a frame.
+        assert_eq!(Frames::new(&data[..3]).count(), 0);
+        assert_eq!(Frames::new(&[]).count(), 0);
+    }
+
+    #[test]
+    fn silence_is_silent() {
+        let data = pack(&[(4, 0), (4, 0), (4, 0), (4, 15)]);
+        let samples: Vec<Sample> = Speech::new(&data).collect();
+        // Three frames, and the stop frame fading out.
+        assert_eq!(samples.len(), 4 * FRAME_SAMPLES as usize);
+        assert!(samples.iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn ends_without_stop_frame() {
+        // The frame, the padding after it (a silent frame)
+        // and the fade out.
+        let data = pack(&voiced(10, 40, [16; 10]));
+        assert_eq!(Speech::new(&data).count(), 3 * FRAME_SAMPLES as usize);
+        assert_eq!(Speech::new(&[]).count(), FRAME_SAMPLES as usize);
+    }
+
+    // Regression references from tools/lpc_model.py, our own
+    // Python model of the same reading of the chip: run it
+    // with `references` to regenerate them. They pin down the
+    // integer arithmetic, but are not taken from a chip or
+    // another decoder; `lattice_matches_direct_form` checks
+    // the filter by a different method.
+
+    /// The first 40 samples of a voiced frame: energy 12,
+    /// pitch 45 and k1 to k10 at indices 20, 20, 8, 8, 8, 8,
+    /// 8, 3, 3, 3.
+    const VOICED_REFERENCE: [Sample; 40] = [
+        0, 368, 2_256, 6_400, 12_240, 16_736, 15_120, 4_960, -8_128, -10_992, 1_264, 12_064, 7_568,
+        2_096, 3_440, 1_792, 3_680, 2_320, -384, -464, 1_856, 128, -2_576, -2_112, -160, 1_472,
+        1_728, 640, -496, -496, 48, 144, -144, -240, 48, 384, 400, 160, -64, -112,
+    ];
+
+    /// The first 40 samples of an unvoiced frame: energy 12
+    /// and k1 to k4 at indices 20, 10, 8, 6.
+    const UNVOICED_REFERENCE: [Sample; 40] = [
+        8_064, -4_272, -10_480, -12_496, -13_440, -13_776, -13_856, -13_872, -13_888, -13_888,
+        -13_888, -13_888, 2_240, 9_744, 12_352, -2_640, 6_368, -4_736, -10_768, -12_624, -13_456,
+        -13_776, -13_856, -13_872, 2_240, -6_400, 4_832, -5_248, 5_232, -5_136, -10_864, -12_656,
+        2_656, -6_288, -11_280, -12_752, 2_624, 9_840, -3_760, 5_984,
+    ];
+
+    /// Samples 215 to 254 of the voiced frame at energy 8,
+    /// followed by a repeat at energy 13 and pitch 40: the
+    /// values start moving 25 samples into the second frame.
+    const REPEAT_REFERENCE: [Sample; 40] = [
+        64, 64, 48, 32, 32, 48, 64, 64, 48, 48, 64, 64, 48, 32, 32, 48, 64, 64, 48, 48, 64, 64, 48,
+        32, 176, 880, 2_480, 4_688, 6_384, 5_792, 1_952, -3_040, -4_144, 544, 4_672, 3_296, 1_776,
+        2_512, 1_008, 1_360,
+    ];
+
+    #[test]
+    fn voiced_frame_matches_reference() {
+        let data = pack(&voiced(12, 45, [20, 20, 8, 8, 8, 8, 8, 3, 3, 3]));
+        let samples: Vec<Sample> = Speech::new(&data).take(40).collect();
+        assert_eq!(samples, VOICED_REFERENCE);
+    }
+
+    #[test]
+    fn unvoiced_frame_matches_reference() {
+        let data = pack(&[(4, 12), (1, 0), (6, 0), (5, 20), (5, 10), (4, 8), (4, 6)]);
+        let samples: Vec<Sample> = Speech::new(&data).take(40).collect();
+        assert_eq!(samples, UNVOICED_REFERENCE);
+    }
+
+    #[test]
+    fn repeat_frame_matches_reference() {
+        let mut fields = voiced(8, 45, [20, 20, 8, 8, 8, 8, 8, 3, 3, 3]);
+        fields.extend([(4, 13), (1, 1), (6, 40)]);
+        let data = pack(&fields);
+        let samples: Vec<Sample> = Speech::new(&data).skip(215).take(40).collect();
+        assert_eq!(samples, REPEAT_REFERENCE);
+    }
+
+    /// The lattice checked against a different method: the
+    /// reflection coefficients stepped up (Levinson) to the
+    /// ten taps of a direct form all-pole filter, run in
+    /// floating point on the same chirp. A single frame from
+    /// silence holds its values, and energy 16 keeps clear of
+    /// the limits, so the two differ only by the lattice's
+    /// rounding down, a few units of the 12-bit output.
+    #[test]
+    fn lattice_matches_direct_form() {
+        let k_index = [20, 20, 8, 8, 8, 8, 8, 3, 3, 3];
+        let data = pack(&voiced(8, 45, k_index));
+        let samples: Vec<Sample> = Speech::new(&data).take(FRAME_SAMPLES as usize).collect();
+
+        let mut a = [0.0f64; 11];
+        for (m, &i) in k_index.iter().enumerate() {
+            let k = K_TABLES[m].0[i as usize] as f64 / 512.0;
+            let prev = a;
+            a[m + 1] = k;
+            for j in 1..=m {
+                a[j] = prev[j] + k * prev[m + 1 - j];
+            }
+        }
+        let pitch = PITCH[45] as usize;
+        let mut y = [0.0f64; FRAME_SAMPLES as usize];
+        for n in 0..y.len() {
+            let chirp = CHIRP[(n % pitch).min(CHIRP.len() - 1)] as f64;
+            let feedback: f64 = (1..=10).filter(|&j| j <= n).map(|j| a[j] * y[n - j]).sum();
+            y[n] = chirp * ENERGY[8] as f64 / 8.0 - feedback;
+        }
+
+        let peak = y.iter().fold(0.0f64, |m, v| m.max(v.abs()));
+        assert!(peak > 100.0 && peak < OUTPUT_MAX as f64, "{}", peak);
+        for (n, (&s, &y)) in samples.iter().zip(&y).enumerate() {
+            let error = (s as f64 / 16.0 - y).abs();
+            assert!(error < 16.0, "sample {}: {} vs {}", n, s as f64 / 16.0, y);
+        }
+    }
+
+    #[test]
+    fn voiced_speech_repeats_at_pitch() {
+        // Pitch index 45: 80 samples, 100Hz.
+        let data = pack(&voiced(12, 45, [20, 20, 8, 8, 8, 8, 8, 3, 3, 3]));
+        let samples: Vec<Sample> = Speech::new(&data).take(160).collect();
+        let loudest = |period: &[Sample]| (0..80).max_by_key(|&i| period[i]).unwrap();
+        assert_eq!(loudest(&samples[..80]), loudest(&samples[80..]));
+    }
+
+    #[test]
+    fn interpolates_between_frames() {
+        // Quiet, then loud with the same voicing: the energy
+        // rises through the second frame.
+        let mut fields = voiced(4, 45, [20, 20, 8, 8, 8, 8, 8, 3, 3, 3]);
+        fields.extend([(4, 14), (1, 1), (6, 45)]);
+        let data = pack(&fields);
+        let mut speech = Speech::new(&data);
+        let samples: Vec<Sample> = speech.by_ref().take(3 * FRAME_SAMPLES as usize).collect();
+        let peak = |s: &[Sample]| s.iter().map(|s| s.unsigned_abs()).max().unwrap();
+        let frame = FRAME_SAMPLES as usize;
+        let (quiet, rising, loud) = (
+            peak(&samples[frame - 80..frame]),
+            peak(&samples[frame + 80..frame + 160]),
+            peak(&samples[2 * frame..2 * frame + 80]),
+        );
+        assert!(quiet < rising && rising < loud, "{} {} {}", quiet, rising, loud);
+    }
+
+    #[test]
+    fn built_in_words_play() {
+        for &(name, data) in crate::songs::WORDS {
+            assert!(Frames::new(data).count() > 1, "{}", name);
+            let samples: Vec<Sample> = Speech::new(data).collect();
+            assert!(samples.iter().any(|&s| s.unsigned_abs() > 4_000), "{}", name);
+        }
+    }
+}
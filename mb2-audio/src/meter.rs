@@ -0,0 +1,299 @@
+//! Level and spectrum meters for a 5×5 LED display.
+//!
+//! The audio side only has to [Tap::push] each sample it
+//! plays, which is cheap enough for an interrupt handler.
+//! Some 30 times a second the display side then reads the
+//! tap, works out a [Frame] with a [LevelMeter] or a
+//! [Spectrum], and shows it. Both meters show levels in
+//! decibels over [RANGE_DB], as bars rising from the bottom
+//! row; the top LED of each bar is dimmed to show the
+//! fraction of a row.
+
+use crate::{
+    fft::{self, Complex, Window},
+    tuning::log2,
+    Sample,
+};
+
+/// LED brightnesses, 0 to [MAX_BRIGHTNESS], by row from the
+/// top and then by column.
+pub type Frame = [[u8; 5]; 5];
+
+pub const MAX_BRIGHTNESS: u8 = 9;
+
+/// How often meters should be updated.
+pub const FRAME_HZ: u32 = 30;
+
+/// Levels shown, in decibels below full scale.
+pub const RANGE_DB: f32 = 40.0;
+
+/// How fast the bars fall, in decibels per frame.
+pub const FALL_DB: f32 = 1.5;
+
+/// Samples in each spectrum.
+pub const SPECTRUM_LEN: usize = 64;
+
+/// Window applied before each spectrum.
+const WINDOW: Window = Window::Hann;
+
+/// Number of spectrum bands, one per column.
+pub const BANDS: usize = 5;
+
+/// What the display shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    Off,
+    Level,
+    Spectrum,
+    /// The note heard by the microphone: see
+    /// [tuner](crate::tuner).
+    Tuner,
+}
+
+impl View {
+    pub fn name(self) -> &'static str {
+        match self {
+            View::Off => "off",
+            View::Level => "level",
+            View::Spectrum => "spectrum",
+            View::Tuner => "tuner",
+        }
+    }
+
+    /// The view called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [View::Off, View::Level, View::Spectrum, View::Tuner]
+            .into_iter()
+            .find(|view| view.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// The last `N` samples played, and the peak since last
+/// asked.
+pub struct Tap<const N: usize> {
+    buf: [Sample; N],
+    pos: usize,
+    peak: u16,
+}
+
+impl<const N: usize> Default for Tap<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Tap<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            pos: 0,
+            peak: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: Sample) {
+        self.buf[self.pos] = sample;
+        self.pos = (self.pos + 1) % N;
+        self.peak = self.peak.max(sample.unsigned_abs());
+    }
+
+    /// Peak magnitude since the last call.
+    pub fn take_peak(&mut self) -> u16 {
+        core::mem::take(&mut self.peak)
+    }
+
+    /// The last `N` samples, oldest first.
+    pub fn recent(&self) -> [Sample; N] {
+        core::array::from_fn(|i| self.buf[(self.pos + i) % N])
+    }
+}
+
+/// `power` relative to full scale in decibels, bottoming
+/// out well below [RANGE_DB].
+fn power_db(power: f32, full_scale: f32) -> f32 {
+    if power <= 0.0 {
+        return -2.0 * RANGE_DB;
+    }
+    // 10 log10(x) = 10 log10(2) log2(x)
+    3.010_3 * (log2(power) - log2(full_scale))
+}
+
+/// Height in rows, from 0 to 5, of a bar at `db`.
+fn height(db: f32) -> f32 {
+    ((db + RANGE_DB) / RANGE_DB * 5.0).clamp(0.0, 5.0)
+}
+
+/// Bars of the given heights in rows, one per column.
+pub fn bars(heights: &[f32; 5]) -> Frame {
+    let mut frame = [[0; 5]; 5];
+    for (col, &height) in heights.iter().enumerate() {
+        for (row, line) in frame.iter_mut().rev().enumerate() {
+            let lit = (height - row as f32).clamp(0.0, 1.0);
+            line[col] = (lit * MAX_BRIGHTNESS as f32 + 0.5) as u8;
+        }
+    }
+    frame
+}
+
+/// A level that rises at once and falls by [FALL_DB] each
+/// frame.
+#[derive(Debug, Clone, Copy)]
+struct Ballistics {
+    db: f32,
+}
+
+impl Ballistics {
+    const fn new() -> Self {
+        Self { db: -RANGE_DB }
+    }
+
+    fn update(&mut self, db: f32) -> f32 {
+        self.db = db.max(self.db - FALL_DB).max(-RANGE_DB);
+        self.db
+    }
+}
+
+/// Peak level meter, filling the whole display.
+pub struct LevelMeter {
+    level: Ballistics,
+}
+
+impl Default for LevelMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LevelMeter {
+    pub const fn new() -> Self {
+        Self {
+            level: Ballistics::new(),
+        }
+    }
+
+    /// A frame for a [Tap::take_peak] result.
+    pub fn update(&mut self, peak: u16) -> Frame {
+        let (peak, full_scale) = (peak as f32, Sample::MAX as f32);
+        let db = power_db(peak * peak, full_scale * full_scale);
+        bars(&[height(self.level.update(db)); 5])
+    }
+}
+
+/// Five-band spectrum, an octave per band. At 16kHz, the
+/// bands are centred around 250Hz, 500Hz, 1kHz, 2kHz and
+/// 4kHz.
+pub struct Spectrum {
+    bands: [Ballistics; BANDS],
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Spectrum {
+    pub const fn new() -> Self {
+        Self {
+            bands: [Ballistics::new(); BANDS],
+        }
+    }
+
+    /// A frame for a [Tap::recent] result.
+    pub fn update(&mut self, samples: &[Sample; SPECTRUM_LEN]) -> Frame {
+        let mut samples = *samples;
+        // SPECTRUM_LEN is a supported length.
+        WINDOW.apply(&mut samples).unwrap();
+        let mut data = samples.map(|s| Complex::new(s, 0));
+        fft::fft(&mut data).unwrap();
+        // A full-scale sine puts half its amplitude into its
+        // bin (and half into the mirror image), scaled by the
+        // window.
+        let peak = Sample::MAX as f32 / 2.0 * WINDOW.coherent_gain();
+        let full_scale = peak * peak;
+        let mut heights = [0.0; BANDS];
+        for (band, bar) in heights.iter_mut().enumerate() {
+            // Bins 1, 2-3, 4-7, 8-15 and 16-31.
+            let bins = &data[1 << band..2 << band];
+            let power: f32 = bins.iter().map(|c| c.power() as f32).sum();
+            let db = power_db(power, full_scale);
+            *bar = height(self.bands[band].update(db));
+        }
+        bars(&heights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{osc::Oscillator, osc::Waveform, ToneGenerator};
+
+    #[test]
+    fn views_by_name() {
+        assert_eq!(View::from_name("Spectrum"), Some(View::Spectrum));
+        assert_eq!(View::from_name("LEVEL"), Some(View::Level));
+        assert_eq!(View::from_name("off"), Some(View::Off));
+        assert_eq!(View::from_name("tuner"), Some(View::Tuner));
+        assert_eq!(View::from_name("vu"), None);
+    }
+
+    #[test]
+    fn tap_keeps_recent_samples() {
+        let mut tap: Tap<4> = Tap::new();
+        for s in [1, -7, 2, 3, 4, 5] {
+            tap.push(s);
+        }
+        assert_eq!(tap.recent(), [2, 3, 4, 5]);
+        assert_eq!(tap.take_peak(), 7);
+        assert_eq!(tap.take_peak(), 0);
+    }
+
+    #[test]
+    fn bar_shapes() {
+        let frame = bars(&[0.0, 1.0, 2.5, 5.0, 0.25]);
+        assert_eq!(
+            frame,
+            [
+                [0, 0, 0, 9, 0],
+                [0, 0, 0, 9, 0],
+                [0, 0, 5, 9, 0],
+                [0, 0, 9, 9, 0],
+                [0, 9, 9, 9, 2],
+            ]
+        );
+    }
+
+    #[test]
+    fn level_rises_and_falls() {
+        let mut meter = LevelMeter::new();
+        assert_eq!(meter.update(0), [[0; 5]; 5]);
+        assert_eq!(meter.update(Sample::MAX as u16), [[9; 5]; 5]);
+        // Quieter peaks let it fall slowly.
+        assert_eq!(meter.update(Sample::MAX as u16 / 2)[0], [7; 5]);
+        let mut frames = 1;
+        while meter.update(0) != [[0; 5]; 5] {
+            frames += 1;
+        }
+        // The full range takes most of a second.
+        assert_eq!(frames, 26);
+    }
+
+    #[test]
+    fn spectrum_bands() {
+        for (band, freq) in [250.0, 500.0, 1_000.0, 2_000.0, 4_000.0].into_iter().enumerate() {
+            let mut osc = Oscillator::new(16_000, Waveform::Sine, freq);
+            let mut samples = [0; SPECTRUM_LEN];
+            samples.iter_mut().for_each(|s| *s = osc.next_sample());
+            let frame = Spectrum::new().update(&samples);
+            // The window spreads a tone a bin either side, so
+            // a neighbouring band may just reach the top row.
+            for (col, &top) in frame[0].iter().enumerate() {
+                if col == band {
+                    assert_eq!(top, MAX_BRIGHTNESS, "{}Hz", freq);
+                } else {
+                    assert!(top <= MAX_BRIGHTNESS / 2, "{}Hz in band {}", freq, col);
+                }
+            }
+        }
+    }
+}
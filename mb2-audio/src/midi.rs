@@ -0,0 +1,453 @@
+//! MIDI 1.0 byte-stream parsing, and a simple instrument
+//! that plays the messages on a [Mixer].
+//!
+//! The [Parser] takes bytes one at a time, as they arrive
+//! from a serial port at 31250 baud, and returns each
+//! message once it is complete. It follows running status,
+//! passes realtime messages through whenever they arrive
+//! (even in the middle of another message), and skips
+//! system exclusive and system common messages.
+
+use crate::{envelope::UNITY, mixer::Mixer, tuning};
+
+/// MIDI serial baud rate.
+pub const BAUD_RATE: u32 = 31_250;
+
+/// Largest pitch bend: the centre is zero.
+pub const BEND_MAX: i16 = 8191;
+
+/// System realtime messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Realtime {
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+}
+
+/// A channel voice message, or a realtime message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    /// Note off, or note on with velocity zero.
+    NoteOff { channel: u8, key: u8, velocity: u8 },
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    PolyPressure { channel: u8, key: u8, pressure: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelPressure { channel: u8, pressure: u8 },
+    /// Pitch bend from `-8192` to [BEND_MAX].
+    PitchBend { channel: u8, value: i16 },
+    Realtime(Realtime),
+}
+
+/// Number of data bytes following `status`.
+fn data_len(status: u8) -> usize {
+    match status {
+        0xc0..=0xdf | 0xf1 | 0xf3 => 1,
+        0xf6 => 0,
+        _ => 2,
+    }
+}
+
+/// Incremental MIDI byte-stream parser.
+#[derive(Debug, Clone, Default)]
+pub struct Parser {
+    /// Running status, or the system common message being
+    /// skipped.
+    status: Option<u8>,
+    data: [u8; 2],
+    len: usize,
+    in_sysex: bool,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the next byte, returning a message if it
+    /// completes one.
+    pub fn feed(&mut self, byte: u8) -> Option<Message> {
+        match byte {
+            0xf8..=0xff => {
+                let realtime = match byte {
+                    0xf8 => Realtime::Clock,
+                    0xfa => Realtime::Start,
+                    0xfb => Realtime::Continue,
+                    0xfc => Realtime::Stop,
+                    0xfe => Realtime::ActiveSensing,
+                    0xff => Realtime::Reset,
+                    _ => return None,
+                };
+                return Some(Message::Realtime(realtime));
+            }
+            0xf0 => {
+                self.in_sysex = true;
+                self.status = None;
+            }
+            0xf7 => {
+                self.in_sysex = false;
+                self.status = None;
+            }
+            0x80..=0xf6 => {
+                self.in_sysex = false;
+                // Tune request has no data and does nothing.
+                self.status = (byte != 0xf6).then_some(byte);
+                self.len = 0;
+            }
+            _ => {
+                if self.in_sysex {
+                    return None;
+                }
+                let status = self.status?;
+                self.data[self.len] = byte;
+                self.len += 1;
+                if self.len == data_len(status) {
+                    self.len = 0;
+                    if status >= 0xf0 {
+                        // System common: no running status.
+                        self.status = None;
+                        return None;
+                    }
+                    return Some(self.message(status));
+                }
+            }
+        }
+        None
+    }
+
+    fn message(&self, status: u8) -> Message {
+        let channel = status & 0x0f;
+        let [d0, d1] = self.data;
+        match status >> 4 {
+            0x8 => Message::NoteOff {
+                channel,
+                key: d0,
+                velocity: d1,
+            },
+            0x9 if d1 == 0 => Message::NoteOff {
+                channel,
+                key: d0,
+                velocity: 64,
+            },
+            0x9 => Message::NoteOn {
+                channel,
+                key: d0,
+                velocity: d1,
+            },
+            0xa => Message::PolyPressure {
+                channel,
+                key: d0,
+                pressure: d1,
+            },
+            0xb => Message::ControlChange {
+                channel,
+                controller: d0,
+                value: d1,
+            },
+            0xc => Message::ProgramChange {
+                channel,
+                program: d0,
+            },
+            0xd => Message::ChannelPressure {
+                channel,
+                pressure: d0,
+            },
+            _ => Message::PitchBend {
+                channel,
+                value: ((d1 as i16) << 7 | d0 as i16) - 8192,
+            },
+        }
+    }
+}
+
+/// Controller numbers the [Instrument] responds to.
+pub mod cc {
+    pub const VOLUME: u8 = 7;
+    pub const ALL_SOUND_OFF: u8 = 120;
+    pub const RESET_CONTROLLERS: u8 = 121;
+    pub const ALL_NOTES_OFF: u8 = 123;
+}
+
+/// Plays MIDI messages on a [Mixer], like a very simple
+/// sound module. Notes are keyed by MIDI note number.
+#[derive(Debug, Clone)]
+pub struct Instrument {
+    channel: Option<u8>,
+    gain: u16,
+    bend: i16,
+    bend_range: f32,
+}
+
+impl Default for Instrument {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Instrument {
+    /// Respond on all channels, with a pitch bend range of
+    /// two semitones.
+    pub fn new() -> Self {
+        Self {
+            channel: None,
+            gain: UNITY / 2,
+            bend: 0,
+            bend_range: 2.0,
+        }
+    }
+
+    /// Respond only on `channel` (numbered from 0), or on
+    /// all channels if `None`.
+    pub fn set_channel(&mut self, channel: Option<u8>) {
+        self.channel = channel;
+    }
+
+    /// Set the Q15 gain of notes at full velocity.
+    pub fn set_gain(&mut self, gain: u16) {
+        self.gain = gain;
+    }
+
+    /// Set how many semitones a full pitch bend moves.
+    pub fn set_bend_range(&mut self, semitones: f32) {
+        self.bend_range = semitones;
+    }
+
+    /// Frequency of `key` with the current pitch bend.
+    pub fn freq(&self, key: u8) -> f32 {
+        let semitones = self.bend as f32 * self.bend_range / 8192.0;
+        tuning::midi_to_freq(key) * tuning::semitone_ratio(semitones)
+    }
+
+    fn bend_to<const N: usize>(&mut self, value: i16, mixer: &mut Mixer<N>) {
+        self.bend = value;
+        for index in 0..N {
+            if let Some(key) = mixer.voice_key(index) {
+                mixer.voice_mut(index).tone.set_freq(self.freq(key));
+            }
+        }
+    }
+
+    /// Play `message` on `mixer`.
+    pub fn handle<const N: usize>(&mut self, message: Message, mixer: &mut Mixer<N>) {
+        let channel = match message {
+            Message::Realtime(Realtime::Reset) => {
+                mixer.all_notes_off();
+                mixer.set_master(UNITY);
+                self.bend_to(0, mixer);
+                return;
+            }
+            Message::Realtime(_) => return,
+            Message::NoteOff { channel, .. }
+            | Message::NoteOn { channel, .. }
+            | Message::PolyPressure { channel, .. }
+            | Message::ControlChange { channel, .. }
+            | Message::ProgramChange { channel, .. }
+            | Message::ChannelPressure { channel, .. }
+            | Message::PitchBend { channel, .. } => channel,
+        };
+        if self.channel.is_some_and(|c| c != channel) {
+            return;
+        }
+        match message {
+            Message::NoteOn { key, velocity, .. } => {
+                let gain = (self.gain as u32 * velocity as u32 / 127) as u16;
+                mixer.note_on(key, self.freq(key), gain);
+            }
+            Message::NoteOff { key, .. } => mixer.note_off(key),
+            Message::PitchBend { value, .. } => self.bend_to(value, mixer),
+            Message::ControlChange {
+                controller, value, ..
+            } => match controller {
+                cc::VOLUME => mixer.set_master((value as u32 * UNITY as u32 / 127) as u16),
+                cc::ALL_SOUND_OFF | cc::ALL_NOTES_OFF => mixer.all_notes_off(),
+                cc::RESET_CONTROLLERS => self.bend_to(0, mixer),
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{envelope::Adsr, osc::Waveform};
+    use Message::{
+        ChannelPressure, ControlChange, NoteOff, NoteOn, PitchBend, PolyPressure, ProgramChange,
+    };
+
+    fn parse(bytes: &[u8]) -> Vec<Message> {
+        let mut parser = Parser::new();
+        bytes.iter().filter_map(|&b| parser.feed(b)).collect()
+    }
+
+    #[test]
+    fn channel_messages() {
+        let messages = parse(&[
+            0x90, 60, 100, // note on
+            0x83, 60, 10, // note off
+            0xa1, 61, 20, // poly pressure
+            0xb2, 7, 90, // control change
+            0xc3, 5, // program change
+            0xd4, 30, // channel pressure
+            0xe5, 0x00, 0x40, // pitch bend, centre
+            0xe5, 0x7f, 0x7f, // pitch bend, top
+            0xe5, 0x00, 0x00, // pitch bend, bottom
+        ]);
+        assert_eq!(
+            messages,
+            [
+                NoteOn { channel: 0, key: 60, velocity: 100 },
+                NoteOff { channel: 3, key: 60, velocity: 10 },
+                PolyPressure { channel: 1, key: 61, pressure: 20 },
+                ControlChange { channel: 2, controller: 7, value: 90 },
+                ProgramChange { channel: 3, program: 5 },
+                ChannelPressure { channel: 4, pressure: 30 },
+                PitchBend { channel: 5, value: 0 },
+                PitchBend { channel: 5, value: BEND_MAX },
+                PitchBend { channel: 5, value: -8192 },
+            ]
+        );
+    }
+
+    #[test]
+    fn running_status() {
+        let messages = parse(&[0x91, 60, 100, 64, 100, 60, 0, 0xc0, 1, 2, 64]);
+        assert_eq!(
+            messages,
+            [
+                NoteOn { channel: 1, key: 60, velocity: 100 },
+                NoteOn { channel: 1, key: 64, velocity: 100 },
+                NoteOff { channel: 1, key: 60, velocity: 64 },
+                ProgramChange { channel: 0, program: 1 },
+                ProgramChange { channel: 0, program: 2 },
+                ProgramChange { channel: 0, program: 64 },
+            ]
+        );
+    }
+
+    #[test]
+    fn realtime_interleaved() {
+        let messages = parse(&[0x90, 0xf8, 60, 0xfa, 100, 0xf9, 62, 0xfe, 90, 0xff]);
+        assert_eq!(
+            messages,
+            [
+                Message::Realtime(Realtime::Clock),
+                Message::Realtime(Realtime::Start),
+                NoteOn { channel: 0, key: 60, velocity: 100 },
+                Message::Realtime(Realtime::ActiveSensing),
+                NoteOn { channel: 0, key: 62, velocity: 90 },
+                Message::Realtime(Realtime::Reset),
+            ]
+        );
+    }
+
+    #[test]
+    fn sysex_and_system_common_skipped() {
+        let messages = parse(&[
+            0x90, 60, 100, // note on
+            0xf0, 0x7e, 0x7f, 0xf8, 0x09, 0x01, 0xf7, // sysex, with a clock
+            61, 100, // no running status after sysex
+            0x90, 62, 100, // note on
+            0xf2, 0x10, 0x20, // song position
+            63, 100, // no running status after system common
+            0xf3, 4, 0xf6, 0xf1, 0x55, // song select, tune request, quarter frame
+            0x80, 62, 0, // note off
+            0xf0, 1, 2, 0x90, 64, 100, // unterminated sysex
+        ]);
+        assert_eq!(
+            messages,
+            [
+                NoteOn { channel: 0, key: 60, velocity: 100 },
+                Message::Realtime(Realtime::Clock),
+                NoteOn { channel: 0, key: 62, velocity: 100 },
+                NoteOff { channel: 0, key: 62, velocity: 0 },
+                NoteOn { channel: 0, key: 64, velocity: 100 },
+            ]
+        );
+    }
+
+    #[test]
+    fn stray_data_ignored() {
+        assert_eq!(parse(&[1, 2, 3, 0x90, 60]), []);
+    }
+
+    fn mixer() -> Mixer<4> {
+        let adsr = Adsr {
+            attack_ms: 0,
+            decay_ms: 0,
+            sustain: UNITY,
+            release_ms: 0,
+        };
+        Mixer::new(8_000, Waveform::Sine, adsr)
+    }
+
+    fn play(instrument: &mut Instrument, mixer: &mut Mixer<4>, bytes: &[u8]) {
+        let mut parser = Parser::new();
+        for &b in bytes {
+            if let Some(message) = parser.feed(b) {
+                instrument.handle(message, mixer);
+            }
+        }
+    }
+
+    #[test]
+    fn instrument_plays_notes() {
+        let mut instrument = Instrument::new();
+        let mut mixer = mixer();
+        play(&mut instrument, &mut mixer, &[0x90, 69, 127, 72, 64]);
+        assert_eq!(mixer.voice_key(0), Some(69));
+        assert_eq!(mixer.voice(0).tone.freq_hz(), 440.0);
+        assert_eq!(mixer.voice_key(1), Some(72));
+        play(&mut instrument, &mut mixer, &[0x80, 69, 0]);
+        assert_eq!(mixer.voice_key(0), None);
+        assert_eq!(mixer.voice_key(1), Some(72));
+        play(&mut instrument, &mut mixer, &[0xb0, cc::ALL_NOTES_OFF, 0]);
+        assert_eq!(mixer.voice_key(1), None);
+    }
+
+    #[test]
+    fn instrument_bends_sounding_notes() {
+        let mut instrument = Instrument::new();
+        let mut mixer = mixer();
+        play(&mut instrument, &mut mixer, &[0x90, 69, 100, 0xe0, 0x7f, 0x7f]);
+        // Nearly two semitones up.
+        let b4 = tuning::midi_to_freq(71);
+        assert!((mixer.voice(0).tone.freq_hz() - b4).abs() < 0.1);
+        // New notes are bent too.
+        play(&mut instrument, &mut mixer, &[0x90, 57, 100]);
+        let b3 = tuning::midi_to_freq(59);
+        assert!((mixer.voice(1).tone.freq_hz() - b3).abs() < 0.1);
+        play(&mut instrument, &mut mixer, &[0xe0, 0x00, 0x20]);
+        let g4 = tuning::midi_to_freq(68);
+        assert!((mixer.voice(0).tone.freq_hz() - g4).abs() < 0.01);
+        play(&mut instrument, &mut mixer, &[0xb0, cc::RESET_CONTROLLERS, 0]);
+        assert_eq!(mixer.voice(0).tone.freq_hz(), 440.0);
+    }
+
+    #[test]
+    fn instrument_volume_and_reset() {
+        let mut instrument = Instrument::new();
+        let mut mixer = mixer();
+        play(&mut instrument, &mut mixer, &[0xb0, cc::VOLUME, 0]);
+        assert_eq!(mixer.master(), 0);
+        play(&mut instrument, &mut mixer, &[0xb0, cc::VOLUME, 127, 0x90, 60, 100]);
+        assert_eq!(mixer.master(), UNITY);
+        play(&mut instrument, &mut mixer, &[0xb0, cc::VOLUME, 64, 0xff]);
+        assert_eq!(mixer.master(), UNITY);
+        assert_eq!(mixer.voice_key(0), None);
+    }
+
+    #[test]
+    fn instrument_channel_filter() {
+        let mut instrument = Instrument::new();
+        instrument.set_channel(Some(2));
+        let mut mixer = mixer();
+        play(&mut instrument, &mut mixer, &[0x90, 60, 100, 0x92, 62, 100]);
+        assert_eq!(mixer.voice_key(0), Some(62));
+        assert_eq!(mixer.active_voices(), 1);
+    }
+}
@@ -0,0 +1,415 @@
+//! Polyphonic voice mixer.
+//!
+//! A fixed bank of voices, each an oscillator shaped by an
+//! envelope, is summed to one output with per-voice gain
+//! and a master volume. Notes are started and stopped by a
+//! caller-chosen key (a MIDI note number, say); when every
+//! voice is busy, a new note steals one.
+
+use crate::{
+    envelope::{Adsr, Enveloped, UNITY},
+    osc::{Oscillator, Waveform},
+    Sample, ToneGenerator,
+};
+
+/// A single mixer voice.
+pub type Voice = Enveloped<Oscillator>;
+
+/// How the mixed output is kept in range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clip {
+    /// Saturate at full scale.
+    Hard,
+    /// Pass quiet signals unchanged, and compress loud ones
+    /// smoothly towards full scale.
+    Soft,
+}
+
+/// Level above which [Clip::Soft] starts compressing.
+const SOFT_KNEE: i32 = Sample::MAX as i32 / 2;
+
+/// Soft-clip `x`: linear up to [SOFT_KNEE], then a rational
+/// curve with matching slope that approaches but never
+/// reaches full scale.
+fn soft_clip(x: i32) -> Sample {
+    let magnitude = x.unsigned_abs() as i64;
+    let knee = SOFT_KNEE as i64;
+    let y = if magnitude <= knee {
+        magnitude
+    } else {
+        let over = magnitude - knee;
+        let room = Sample::MAX as i64 - knee;
+        knee + over * room / (over + room)
+    };
+    (y as i32 * x.signum()) as Sample
+}
+
+fn hard_clip(x: i32) -> Sample {
+    x.clamp(-(Sample::MAX as i32), Sample::MAX as i32) as Sample
+}
+
+/// Scale `x` by Q15 `gain`.
+fn scale(x: i32, gain: u16) -> i32 {
+    ((x as i64 * gain as i64) >> 15) as i32
+}
+
+struct Slot {
+    voice: Voice,
+    gain: u16,
+    key: Option<u8>,
+    /// When the current note started: larger is newer.
+    started: u32,
+}
+
+/// Mixer of `N` voices.
+pub struct Mixer<const N: usize> {
+    slots: [Slot; N],
+    master: u16,
+    clip: Clip,
+    clock: u32,
+}
+
+impl<const N: usize> Mixer<N> {
+    /// `N` silent voices at `sample_rate`, all with the given
+    /// waveform and envelope.
+    pub fn new(sample_rate: u32, waveform: Waveform, adsr: Adsr) -> Self {
+        Self {
+            slots: core::array::from_fn(|_| Slot {
+                voice: Enveloped::new(Oscillator::new(sample_rate, waveform, 0.0), adsr),
+                gain: UNITY,
+                key: None,
+                started: 0,
+            }),
+            master: UNITY,
+            clip: Clip::Soft,
+            clock: 0,
+        }
+    }
+
+    /// Start a note for `key` at `freq_hz` with Q15 `gain`,
+    /// returning the voice index used. A key that is already
+    /// held keeps its voice, which takes the new pitch and
+    /// gain without restarting its envelope: see
+    /// [Self::retrigger] for that. Otherwise an idle voice is
+    /// used if there is one; if not, the oldest released
+    /// voice is stolen, or failing that the oldest voice of
+    /// all.
+    pub fn note_on(&mut self, key: u8, freq_hz: f32, gain: u16) -> usize {
+        let index = self
+            .slots
+            .iter()
+            .position(|s| s.key == Some(key) && s.voice.env.is_active())
+            .or_else(|| self.slots.iter().position(|s| !s.voice.env.is_active()))
+            .or_else(|| self.oldest(|s| s.key.is_none()))
+            .or_else(|| self.oldest(|_| true))
+            .unwrap();
+        self.clock = self.clock.wrapping_add(1);
+        let slot = &mut self.slots[index];
+        slot.voice.tone.set_freq(freq_hz);
+        slot.voice.env.gate_on();
+        slot.gain = gain;
+        slot.key = Some(key);
+        slot.started = self.clock;
+        index
+    }
+
+    /// Strike the note held for `key` again, restarting its
+    /// attack from the current level. Does nothing if `key`
+    /// is not held.
+    pub fn retrigger(&mut self, key: u8) {
+        for slot in &mut self.slots {
+            if slot.key == Some(key) {
+                slot.voice.env.retrigger();
+            }
+        }
+    }
+
+    /// Release the note for `key`, if it is playing.
+    pub fn note_off(&mut self, key: u8) {
+        for slot in &mut self.slots {
+            if slot.key == Some(key) {
+                slot.voice.env.gate_off();
+                slot.key = None;
+            }
+        }
+    }
+
+    /// Release every note.
+    pub fn all_notes_off(&mut self) {
+        for slot in &mut self.slots {
+            slot.voice.env.gate_off();
+            slot.key = None;
+        }
+    }
+
+    /// Index of the oldest voice matching `filter`.
+    fn oldest(&self, filter: impl Fn(&Slot) -> bool) -> Option<usize> {
+        let clock = self.clock;
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| filter(s))
+            .max_by_key(|(_, s)| clock.wrapping_sub(s.started))
+            .map(|(i, _)| i)
+    }
+
+    pub fn voice(&self, index: usize) -> &Voice {
+        &self.slots[index].voice
+    }
+
+    pub fn voice_mut(&mut self, index: usize) -> &mut Voice {
+        &mut self.slots[index].voice
+    }
+
+    /// Key of the note held on voice `index`, if any.
+    pub fn voice_key(&self, index: usize) -> Option<u8> {
+        self.slots[index].key
+    }
+
+    /// Q15 gain of voice `index`.
+    pub fn gain(&self, index: usize) -> u16 {
+        self.slots[index].gain
+    }
+
+    pub fn set_gain(&mut self, index: usize, gain: u16) {
+        self.slots[index].gain = gain;
+    }
+
+    pub fn master(&self) -> u16 {
+        self.master
+    }
+
+    /// Set the Q15 master volume.
+    pub fn set_master(&mut self, master: u16) {
+        self.master = master;
+    }
+
+    pub fn set_clip(&mut self, clip: Clip) {
+        self.clip = clip;
+    }
+
+    /// Set the waveform of every voice.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        for slot in &mut self.slots {
+            slot.voice.tone.set_waveform(waveform);
+        }
+    }
+
+    /// Set the envelope of every voice.
+    pub fn set_adsr(&mut self, adsr: Adsr) {
+        for slot in &mut self.slots {
+            slot.voice.env.set_adsr(adsr);
+        }
+    }
+
+    /// Number of voices still sounding (including releases).
+    pub fn active_voices(&self) -> usize {
+        self.slots.iter().filter(|s| s.voice.env.is_active()).count()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active_voices() > 0
+    }
+}
+
+impl<const N: usize> ToneGenerator for Mixer<N> {
+    fn sample_rate(&self) -> u32 {
+        self.slots[0].voice.sample_rate()
+    }
+
+    fn next_sample(&mut self) -> Sample {
+        let mut sum = 0i32;
+        for slot in &mut self.slots {
+            if slot.voice.env.is_active() {
+                sum += scale(slot.voice.next_sample() as i32, slot.gain);
+            }
+        }
+        let sum = scale(sum, self.master);
+        match self.clip {
+            Clip::Hard => hard_clip(sum),
+            Clip::Soft => soft_clip(sum),
+        }
+    }
+
+    fn reset(&mut self) {
+        for slot in &mut self.slots {
+            slot.voice.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::Stage;
+
+    const RATE: u32 = 16_000;
+
+    /// Instant attack and release, full sustain.
+    const GATE: Adsr = Adsr {
+        attack_ms: 0,
+        decay_ms: 0,
+        sustain: UNITY,
+        release_ms: 0,
+    };
+
+    fn mixer<const N: usize>(waveform: Waveform) -> Mixer<N> {
+        Mixer::new(RATE, waveform, GATE)
+    }
+
+    #[test]
+    fn silent_with_no_notes() {
+        let mut mixer = mixer::<4>(Waveform::Square);
+        assert!(mixer.samples().take(100).all(|s| s == 0));
+        assert!(!mixer.is_active());
+    }
+
+    #[test]
+    fn sums_voices_with_gain() {
+        let mut mixer = mixer::<4>(Waveform::Square);
+        mixer.set_clip(Clip::Hard);
+        mixer.note_on(1, 100.0, UNITY / 4);
+        mixer.note_on(2, 100.0, UNITY / 8);
+        // Two in-phase squares at 1/4 and 1/8, after the
+        // band-limited edge at the start.
+        let s = mixer.samples().nth(4).unwrap() as i32;
+        assert!((s - 32767 * 3 / 8).abs() <= 2, "{}", s);
+    }
+
+    #[test]
+    fn master_volume_scales() {
+        let mut mixer = mixer::<2>(Waveform::Square);
+        mixer.note_on(1, 100.0, UNITY / 2);
+        mixer.set_master(UNITY / 2);
+        let s = mixer.samples().nth(4).unwrap() as i32;
+        assert!((s - 32767 / 4).abs() <= 2, "{}", s);
+    }
+
+    #[test]
+    fn overflow_never_wraps() {
+        for clip in [Clip::Hard, Clip::Soft] {
+            let mut mixer = mixer::<8>(Waveform::Square);
+            mixer.set_clip(clip);
+            for key in 0..8 {
+                mixer.note_on(key, 100.0, UNITY);
+            }
+            let samples: Vec<Sample> = mixer.samples().take(320).collect();
+            // High half-period then low, away from the
+            // band-limited edges: no sign flips.
+            assert!(samples[2..79].iter().all(|&s| s > 30_000), "{:?}", clip);
+            assert!(samples[82..159].iter().all(|&s| s < -30_000), "{:?}", clip);
+        }
+    }
+
+    #[test]
+    fn soft_clip_curve() {
+        // Unchanged below the knee.
+        assert_eq!(soft_clip(10_000), 10_000);
+        assert_eq!(soft_clip(-SOFT_KNEE), -SOFT_KNEE as Sample);
+        // Monotonic, bounded and odd above it.
+        let mut last = soft_clip(SOFT_KNEE);
+        for x in (SOFT_KNEE..1_000_000).step_by(97) {
+            let y = soft_clip(x);
+            assert!(y >= last && y < Sample::MAX);
+            assert_eq!(soft_clip(-x), -y);
+            last = y;
+        }
+        assert!(soft_clip(i32::MAX) < Sample::MAX);
+        assert!(soft_clip(i32::MIN) > -Sample::MAX);
+    }
+
+    #[test]
+    fn note_off_releases_key() {
+        let mut mixer = mixer::<4>(Waveform::Sine);
+        mixer.note_on(60, 261.6, UNITY);
+        mixer.note_on(64, 329.6, UNITY);
+        assert_eq!(mixer.active_voices(), 2);
+        mixer.note_off(60);
+        mixer.next_sample();
+        assert_eq!(mixer.active_voices(), 1);
+        assert_eq!(mixer.voice_key(1), Some(64));
+    }
+
+    #[test]
+    fn same_key_reuses_same_voice() {
+        let mut mixer = mixer::<4>(Waveform::Sine);
+        let a = mixer.note_on(60, 261.6, UNITY);
+        let b = mixer.note_on(60, 261.6, UNITY);
+        assert_eq!(a, b);
+        assert_eq!(mixer.active_voices(), 1);
+    }
+
+    #[test]
+    fn held_key_keeps_its_envelope() {
+        let adsr = Adsr {
+            attack_ms: 10,
+            decay_ms: 10,
+            sustain: UNITY / 2,
+            release_ms: 10,
+        };
+        let mut mixer: Mixer<4> = Mixer::new(RATE, Waveform::Sine, adsr);
+        let index = mixer.note_on(60, 261.6, UNITY);
+        mixer.samples().take(80).for_each(drop);
+        // A second note on, halfway through the attack, just
+        // changes the pitch and gain.
+        let level = mixer.voice(index).env.level();
+        assert_eq!(mixer.note_on(60, 300.0, UNITY / 2), index);
+        assert_eq!(mixer.voice(index).env.stage(), Stage::Attack);
+        assert_eq!(mixer.voice(index).env.level(), level);
+        assert_eq!(mixer.voice(index).tone.freq_hz(), 300.0);
+        assert_eq!(mixer.gain(index), UNITY / 2);
+        mixer.samples().take(800).for_each(drop);
+        assert_eq!(mixer.note_on(60, 261.6, UNITY), index);
+        assert_eq!(mixer.voice(index).env.stage(), Stage::Sustain);
+        assert_eq!(mixer.voice(index).env.level(), UNITY / 2);
+        // Retriggering strikes it again.
+        mixer.retrigger(60);
+        assert_eq!(mixer.voice(index).env.stage(), Stage::Attack);
+        mixer.samples().take(100).for_each(drop);
+        assert!(mixer.voice(index).env.level() > UNITY / 2);
+        // A key that isn't held is left alone.
+        mixer.note_off(60);
+        mixer.retrigger(60);
+        assert_eq!(mixer.voice(index).env.stage(), Stage::Release);
+    }
+
+    #[test]
+    fn steals_oldest_when_full() {
+        let mut mixer = mixer::<3>(Waveform::Sine);
+        assert_eq!(mixer.note_on(1, 100.0, UNITY), 0);
+        assert_eq!(mixer.note_on(2, 200.0, UNITY), 1);
+        assert_eq!(mixer.note_on(3, 300.0, UNITY), 2);
+        assert_eq!(mixer.note_on(4, 400.0, UNITY), 0);
+        assert_eq!(mixer.voice_key(0), Some(4));
+        assert_eq!(mixer.note_on(5, 500.0, UNITY), 1);
+        // Key 1 was stolen, so releasing it does nothing.
+        mixer.note_off(1);
+        assert_eq!(mixer.active_voices(), 3);
+    }
+
+    #[test]
+    fn steals_released_voice_before_held_one() {
+        let adsr = Adsr {
+            release_ms: 1_000,
+            ..GATE
+        };
+        let mut mixer: Mixer<3> = Mixer::new(RATE, Waveform::Sine, adsr);
+        mixer.note_on(1, 100.0, UNITY);
+        mixer.note_on(2, 200.0, UNITY);
+        mixer.note_on(3, 300.0, UNITY);
+        mixer.next_sample();
+        // Voice 1 is still releasing, so no voice is idle, but
+        // it goes before the held ones.
+        mixer.note_off(2);
+        assert_eq!(mixer.note_on(4, 400.0, UNITY), 1);
+        assert_eq!(mixer.voice_key(0), Some(1));
+    }
+
+    #[test]
+    fn waveform_applies_to_all_voices() {
+        let mut mixer = mixer::<2>(Waveform::Sine);
+        mixer.set_waveform(Waveform::Saw);
+        assert_eq!(mixer.voice(0).tone.waveform(), Waveform::Saw);
+        assert_eq!(mixer.voice(1).tone.waveform(), Waveform::Saw);
+    }
+}
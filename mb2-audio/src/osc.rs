@@ -0,0 +1,301 @@
+//! Direct digital synthesis oscillator.
+//!
+//! A 32-bit phase accumulator is advanced by a fixed tuning
+//! word each sample, and its top bits index a single-period
+//! waveform table. At 16 kHz the frequency resolution is
+//! under 4µHz, and any frequency below Nyquist can be
+//! played, not just those with a whole number of samples
+//! per period.
+//!
+//! The square and the saw jump, and a table lookup would
+//! alias at every jump, so those two are computed with
+//! [PolyBLEP](crate::blep) instead.
+
+use crate::{
+    blep::{self, BlepWaveform},
+    wavetable, Sample, ToneGenerator,
+};
+
+/// Oscillator waveform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Triangle,
+    Saw,
+}
+
+impl Waveform {
+    /// The waveform after this one, cycling back to the
+    /// start.
+    pub fn next(self) -> Self {
+        match self {
+            Waveform::Sine => Waveform::Square,
+            Waveform::Square => Waveform::Triangle,
+            Waveform::Triangle => Waveform::Saw,
+            Waveform::Saw => Waveform::Sine,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Waveform::Sine => "sine",
+            Waveform::Square => "square",
+            Waveform::Triangle => "triangle",
+            Waveform::Saw => "saw",
+        }
+    }
+
+    /// The waveform called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut w = Waveform::Sine;
+        for _ in 0..4 {
+            if w.name().eq_ignore_ascii_case(name) {
+                return Some(w);
+            }
+            w = w.next();
+        }
+        None
+    }
+
+    fn table(self) -> &'static wavetable::Table {
+        match self {
+            Waveform::Sine => &wavetable::SINE,
+            Waveform::Square => &wavetable::SQUARE,
+            Waveform::Triangle => &wavetable::TRIANGLE,
+            Waveform::Saw => &wavetable::SAW,
+        }
+    }
+}
+
+/// Tuning word giving `freq_hz` at `sample_rate`: the
+/// per-sample phase increment, where `2**32` is one period.
+/// Frequencies are clamped to `0..sample_rate / 2`.
+pub fn tuning_word(sample_rate: u32, freq_hz: f32) -> u32 {
+    let freq = (freq_hz as f64).clamp(0.0, sample_rate as f64 / 2.0);
+    (freq * (1u64 << 32) as f64 / sample_rate as f64 + 0.5) as u32
+}
+
+/// Table-lookup oscillator with a 32-bit phase accumulator.
+pub struct Oscillator {
+    sample_rate: u32,
+    waveform: Waveform,
+    tuning_word: u32,
+    phase: u32,
+}
+
+impl Oscillator {
+    pub fn new(sample_rate: u32, waveform: Waveform, freq_hz: f32) -> Self {
+        Self {
+            sample_rate,
+            waveform,
+            tuning_word: tuning_word(sample_rate, freq_hz),
+            phase: 0,
+        }
+    }
+
+    /// Change frequency without a phase discontinuity.
+    pub fn set_freq(&mut self, freq_hz: f32) {
+        self.tuning_word = tuning_word(self.sample_rate, freq_hz);
+    }
+
+    /// The frequency actually being played, after rounding to
+    /// the nearest tuning word.
+    pub fn freq_hz(&self) -> f32 {
+        (self.tuning_word as f64 * self.sample_rate as f64 / (1u64 << 32) as f64) as f32
+    }
+
+    pub fn tuning_word(&self) -> u32 {
+        self.tuning_word
+    }
+
+    pub fn set_tuning_word(&mut self, tuning_word: u32) {
+        self.tuning_word = tuning_word;
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    /// Current phase: `2**32` is one period.
+    pub fn phase(&self) -> u32 {
+        self.phase
+    }
+}
+
+impl ToneGenerator for Oscillator {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_sample(&mut self) -> Sample {
+        let sample = match self.waveform {
+            Waveform::Square => blep::sample(BlepWaveform::Square, self.phase, self.tuning_word),
+            // Half a period on from the band-limited saw,
+            // which jumps at phase 0: the table's saw starts
+            // from zero.
+            Waveform::Saw => {
+                let phase = self.phase.wrapping_add(1 << 31);
+                blep::sample(BlepWaveform::Saw, phase, self.tuning_word)
+            }
+            Waveform::Sine | Waveform::Triangle => {
+                wavetable::lookup(self.waveform.table(), self.phase)
+            }
+        };
+        self.phase = self.phase.wrapping_add(self.tuning_word);
+        sample
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frequency of `samples` at `sample_rate`, measured
+    /// between the first and last rising zero crossings,
+    /// with crossing times linearly interpolated.
+    fn measure_freq(samples: &[Sample], sample_rate: u32) -> f64 {
+        let mut crossings = Vec::new();
+        for (i, w) in samples.windows(2).enumerate() {
+            let (a, b) = (w[0] as f64, w[1] as f64);
+            if a < 0.0 && b >= 0.0 {
+                crossings.push(i as f64 + a / (a - b));
+            }
+        }
+        let periods = (crossings.len() - 1) as f64;
+        let span = crossings.last().unwrap() - crossings[0];
+        periods * sample_rate as f64 / span
+    }
+
+    fn render(osc: &mut Oscillator, seconds: u32) -> Vec<Sample> {
+        let n = (osc.sample_rate() * seconds) as usize;
+        osc.samples().take(n).collect()
+    }
+
+    #[test]
+    fn sub_hz_resolution() {
+        for freq in [440.0, 440.1, 440.25, 1_000.5, 27.5] {
+            let osc = Oscillator::new(16_000, Waveform::Sine, freq);
+            let error = (osc.freq_hz() - freq).abs();
+            assert!(error < 1e-4, "{}Hz: error {}", freq, error);
+        }
+    }
+
+    #[test]
+    fn measured_frequency_matches_reference() {
+        for waveform in [Waveform::Sine, Waveform::Triangle] {
+            for freq in [55.0, 440.0, 440.25, 1_234.5, 3_999.9] {
+                let mut osc = Oscillator::new(16_000, waveform, freq);
+                let measured = measure_freq(&render(&mut osc, 4), 16_000);
+                let error = (measured - freq as f64).abs();
+                assert!(error < 0.005, "{:?} {}Hz: measured {}", waveform, freq, measured);
+            }
+        }
+    }
+
+    #[test]
+    fn non_integer_period_does_not_drift() {
+        // 1000Hz at 44.1kHz is 44.1 samples per period: a
+        // sample-counting square wave can only do 44 or 45.
+        let mut osc = Oscillator::new(44_100, Waveform::Sine, 1_000.0);
+        let measured = measure_freq(&render(&mut osc, 2), 44_100);
+        assert!((measured - 1_000.0).abs() < 0.005, "{}", measured);
+    }
+
+    #[test]
+    fn sine_matches_reference() {
+        let freq = 441.5;
+        let mut osc = Oscillator::new(16_000, Waveform::Sine, freq);
+        for (i, s) in osc.samples().take(16_000).enumerate() {
+            let t = i as f64 / 16_000.0;
+            let expected = (2.0 * std::f64::consts::PI * freq as f64 * t).sin() * 32767.0;
+            let error = (s as f64 - expected).abs();
+            // Linear interpolation of a 256-entry table is good
+            // to about 0.01%, plus a little phase drift.
+            assert!(error < 20.0, "sample {}: {} vs {}", i, s, expected);
+        }
+    }
+
+    #[test]
+    fn waveform_shapes() {
+        // Quarter-period steps at 4kHz / 16kHz.
+        let mut osc = Oscillator::new(16_000, Waveform::Triangle, 4_000.0);
+        let quarters: Vec<Sample> = osc.samples().take(4).collect();
+        assert_eq!(quarters, [0, 32767, 0, -32767]);
+        // Sixteenths at 1kHz. The saw and the square are
+        // band-limited, so their jumps pass through zero.
+        osc.set_freq(1_000.0);
+        osc.set_waveform(Waveform::Saw);
+        osc.reset();
+        let saw: Vec<Sample> = osc.samples().take(16).collect();
+        assert_eq!(saw[..9], [0, 4095, 8191, 12287, 16383, 20479, 24575, 28671, 0]);
+        assert_eq!(saw[9..], [-28671, -24575, -20479, -16383, -12287, -8191, -4095]);
+        osc.set_waveform(Waveform::Square);
+        osc.reset();
+        let square: Vec<Sample> = osc.samples().take(16).collect();
+        assert_eq!(square[0], 0);
+        assert!(square[1..8].iter().all(|&s| s == 32767));
+        assert_eq!(square[8], 0);
+        assert!(square[9..].iter().all(|&s| s == -32767));
+    }
+
+    #[test]
+    fn set_freq_keeps_phase() {
+        let mut osc = Oscillator::new(16_000, Waveform::Sine, 1_000.0);
+        osc.samples().take(3).for_each(drop);
+        let phase = osc.phase();
+        osc.set_freq(2_000.0);
+        assert_eq!(osc.phase(), phase);
+    }
+
+    #[test]
+    fn frequency_clamped() {
+        let osc = Oscillator::new(16_000, Waveform::Sine, 20_000.0);
+        assert_eq!(osc.tuning_word(), 1 << 31);
+        let osc = Oscillator::new(16_000, Waveform::Sine, -5.0);
+        assert_eq!(osc.tuning_word(), 0);
+    }
+
+    #[test]
+    fn square_and_saw_are_band_limited() {
+        use crate::blep::BlepOscillator;
+        let mut osc = Oscillator::new(16_000, Waveform::Square, 1_234.5);
+        let mut blep = BlepOscillator::new(16_000, BlepWaveform::Square, 1_234.5);
+        assert!(osc.samples().zip(blep.samples()).take(1_000).all(|(a, b)| a == b));
+        // The saw half a period on.
+        let mut osc = Oscillator::new(16_000, Waveform::Saw, 1_000.0);
+        let mut blep = BlepOscillator::new(16_000, BlepWaveform::Saw, 1_000.0);
+        let blep: Vec<Sample> = blep.samples().take(24).collect();
+        let osc: Vec<Sample> = osc.samples().take(16).collect();
+        assert_eq!(osc, blep[8..]);
+    }
+
+    #[test]
+    fn waveforms_cycle() {
+        let mut w = Waveform::Sine;
+        for _ in 0..4 {
+            w = w.next();
+        }
+        assert_eq!(w, Waveform::Sine);
+        assert_eq!(Waveform::Sine.next(), Waveform::Square);
+    }
+
+    #[test]
+    fn waveform_names() {
+        let mut w = Waveform::Sine;
+        for _ in 0..4 {
+            assert_eq!(Waveform::from_name(w.name()), Some(w));
+            w = w.next();
+        }
+        assert_eq!(Waveform::from_name("SAW"), Some(Waveform::Saw));
+        assert_eq!(Waveform::from_name("noise"), None);
+    }
+}
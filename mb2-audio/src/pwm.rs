@@ -0,0 +1,105 @@
+//! Sample playback through a PWM peripheral.
+//!
+//! The PWM is run with one PWM period per audio sample: the
+//! counter top sets the sample rate, and each sample becomes
+//! a duty value between zero and the counter top, ready for
+//! the buffers the PWM's sequences read by EasyDMA.
+
+use crate::{Sample, ToneGenerator};
+
+/// Clock driving the PWM counter (nRF52 PWM with no
+/// prescaling).
+pub const PWM_CLOCK_HZ: u32 = 16_000_000;
+
+/// Lowest supported sample rate.
+pub const MIN_SAMPLE_RATE: u32 = 8_000;
+
+/// Highest supported sample rate. At this rate the counter
+/// top is 500, so samples have about 9 bits of resolution.
+pub const MAX_SAMPLE_RATE: u32 = 32_000;
+
+/// Reasons PWM playback cannot be set up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PwmError {
+    /// Sample rate was outside
+    /// [MIN_SAMPLE_RATE]..=[MAX_SAMPLE_RATE].
+    SampleRateOutOfRange(u32),
+}
+
+/// Counter top giving the sample rate nearest to
+/// `sample_rate`. The actual sample rate is
+/// `PWM_CLOCK_HZ / top`.
+pub fn countertop(sample_rate: u32) -> Result<u16, PwmError> {
+    if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+        return Err(PwmError::SampleRateOutOfRange(sample_rate));
+    }
+    Ok(((PWM_CLOCK_HZ + sample_rate / 2) / sample_rate) as u16)
+}
+
+/// PWM duty value for `sample` with counter top `top`: the
+/// full sample range maps linearly onto `0..top`.
+pub fn duty(sample: Sample, top: u16) -> u16 {
+    let offset = (sample as i32 + 0x8000) as u32;
+    ((offset * top as u32) >> 16) as u16
+}
+
+/// Fill `buf` with duty values for successive samples from
+/// `tone`.
+pub fn fill<G: ToneGenerator>(buf: &mut [u16], top: u16, tone: &mut G) {
+    for (d, s) in buf.iter_mut().zip(tone.samples()) {
+        *d = duty(s, top);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::square::Square;
+
+    #[test]
+    fn countertop_for_common_rates() {
+        assert_eq!(countertop(8_000), Ok(2_000));
+        assert_eq!(countertop(16_000), Ok(1_000));
+        assert_eq!(countertop(32_000), Ok(500));
+        // 725.6 rounds up.
+        assert_eq!(countertop(22_050), Ok(726));
+    }
+
+    #[test]
+    fn countertop_rejects_out_of_range() {
+        assert_eq!(
+            countertop(7_999),
+            Err(PwmError::SampleRateOutOfRange(7_999)),
+        );
+        assert_eq!(
+            countertop(44_100),
+            Err(PwmError::SampleRateOutOfRange(44_100)),
+        );
+    }
+
+    #[test]
+    fn duty_spans_counter() {
+        assert_eq!(duty(Sample::MIN, 1_000), 0);
+        assert_eq!(duty(0, 1_000), 500);
+        assert_eq!(duty(Sample::MAX, 1_000), 999);
+        assert_eq!(duty(-Sample::MAX, 500), 0);
+    }
+
+    #[test]
+    fn duty_is_monotonic() {
+        let mut last = 0;
+        for s in Sample::MIN..=Sample::MAX {
+            let d = duty(s, 2_000);
+            assert!(d >= last && d < 2_000);
+            last = d;
+        }
+    }
+
+    #[test]
+    fn fill_square() {
+        let mut square = Square::new(16_000, 4_000);
+        let mut buf = [0; 8];
+        fill(&mut buf, 1_000, &mut square);
+        assert_eq!(buf, [999, 999, 0, 0, 999, 999, 0, 0]);
+    }
+}
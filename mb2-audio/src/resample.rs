@@ -0,0 +1,105 @@
+//! Sample rate conversion.
+//!
+//! A [Resampler] reads samples at one rate and produces them
+//! at another by linear interpolation, stepping through the
+//! input in 16.16 fixed point. Linear interpolation is crude
+//! as filters go, but the speaker is cruder, and it costs
+//! only a multiply per sample.
+
+use crate::Sample;
+
+/// Fractional bits of the input position.
+const FRAC_BITS: u32 = 16;
+
+const ONE: u32 = 1 << FRAC_BITS;
+
+/// The samples of `input`, at a different rate.
+#[derive(Debug, Clone)]
+pub struct Resampler<I> {
+    input: I,
+    /// Input samples per output sample, in 16.16.
+    step: u32,
+    /// Position between `a` and `b`, in 16.16.
+    frac: u32,
+    a: Option<Sample>,
+    b: Option<Sample>,
+}
+
+impl<I: Iterator<Item = Sample>> Resampler<I> {
+    /// Convert `input` from `from_hz` to `to_hz`.
+    pub fn new(mut input: I, from_hz: u32, to_hz: u32) -> Self {
+        let step = (((from_hz as u64) << FRAC_BITS) / to_hz as u64).clamp(1, u32::MAX as u64);
+        let a = input.next();
+        let b = input.next();
+        Self {
+            input,
+            step: step as u32,
+            frac: 0,
+            a,
+            b,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Sample>> Iterator for Resampler<I> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let a = self.a?;
+        // Past the last input sample, hold it.
+        let b = self.b.unwrap_or(a);
+        let delta = (b as i64 - a as i64) * self.frac as i64;
+        let sample = a as i64 + (delta >> FRAC_BITS);
+        self.frac += self.step;
+        while self.frac >= ONE && self.a.is_some() {
+            self.frac -= ONE;
+            self.a = self.b;
+            self.b = self.input.next();
+        }
+        Some(sample as Sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{osc::Oscillator, osc::Waveform, ToneGenerator};
+
+    fn resample(input: &[Sample], from_hz: u32, to_hz: u32) -> Vec<Sample> {
+        Resampler::new(input.iter().copied(), from_hz, to_hz).collect()
+    }
+
+    #[test]
+    fn same_rate_unchanged() {
+        let input = [1, -2, 3, 32_767, -32_768];
+        assert_eq!(resample(&input, 8_000, 8_000), input);
+        assert_eq!(resample(&[], 8_000, 16_000), []);
+        assert_eq!(resample(&[5], 8_000, 8_000), [5]);
+    }
+
+    #[test]
+    fn upsampling_interpolates() {
+        assert_eq!(resample(&[0, 100, -100], 8_000, 16_000), [0, 50, 100, 0, -100, -100]);
+        assert_eq!(
+            resample(&[-32_768, 32_767], 1, 4),
+            [-32_768, -16_385, -1, 16_383, 32_767, 32_767, 32_767, 32_767]
+        );
+    }
+
+    #[test]
+    fn downsampling_skips() {
+        assert_eq!(resample(&[0, 1, 2, 3, 4, 5, 6], 16_000, 8_000), [0, 2, 4, 6]);
+        assert_eq!(resample(&[0, 30, 60, 90, 120, 150], 12_000, 8_000), [0, 45, 90, 135]);
+    }
+
+    #[test]
+    fn keeps_pitch_and_length() {
+        // One second of 440Hz at 11025Hz, played at 16kHz.
+        let mut osc = Oscillator::new(11_025, Waveform::Sine, 440.0);
+        let input: Vec<Sample> = osc.samples().take(11_025).collect();
+        let output = resample(&input, 11_025, 16_000);
+        assert!(output.len().abs_diff(16_000) <= 2, "{}", output.len());
+        let crossings = output.windows(2).filter(|w| w[0] < 0 && w[1] >= 0).count();
+        assert!(crossings.abs_diff(440) <= 1, "{}", crossings);
+    }
+}
@@ -0,0 +1,495 @@
+//! RTTTL (Nokia ringtone) parsing and playback.
+//!
+//! An RTTTL string has three colon-separated sections: a
+//! name, default settings, and comma-separated notes:
+//!
+//! ```text
+//! Tetris:d=4,o=5,b=160:e6,8b,8c6,8d6,16e6,16d6,8c6,8b
+//! ```
+//!
+//! The settings give the default duration `d` (as a
+//! fraction of a whole note), default octave `o`, and tempo
+//! `b` in quarter notes per minute. Each note is
+//! `[duration]letter[#][.][octave][.]`, where the letter is
+//! `a`–`g` (or `h`, German for B), or `p` for a pause, and
+//! `.` makes the note half again as long.
+//!
+//! Parsing is done lazily and without allocation. Errors
+//! report the byte offset in the string where parsing went
+//! wrong.
+
+use crate::{envelope::UNITY, mixer::Mixer, tuning};
+
+/// What went wrong parsing an RTTTL string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtttlErrorKind {
+    /// The string ended before the notes section.
+    MissingSection,
+    /// A setting other than `d`, `o` or `b`.
+    UnknownSetting,
+    /// A setting without `=`.
+    ExpectedEquals,
+    /// A number was expected.
+    ExpectedNumber,
+    /// Duration not one of 1, 2, 4, 8, 16 or 32.
+    BadDuration,
+    /// Octave outside 3..=8.
+    BadOctave,
+    /// Tempo outside 1..=999.
+    BadTempo,
+    /// A note letter was expected.
+    ExpectedNote,
+    /// Something other than a comma after a note.
+    ExpectedComma,
+}
+
+/// An RTTTL parse error at byte offset `pos`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtttlError {
+    pub pos: usize,
+    pub kind: RtttlErrorKind,
+}
+
+/// A note (or pause) of a melody.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    /// MIDI note number, or `None` for a pause.
+    pub midi: Option<u8>,
+    pub duration_ms: u32,
+}
+
+impl Note {
+    /// Frequency in Hz, or `None` for a pause.
+    pub fn freq_hz(&self) -> Option<f32> {
+        self.midi.map(tuning::midi_to_freq)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Defaults {
+    duration: u32,
+    octave: u32,
+    bpm: u32,
+}
+
+/// A parsed RTTTL header, with its notes still to be read.
+#[derive(Debug, Clone, Copy)]
+pub struct Rtttl<'a> {
+    src: &'a str,
+    name: &'a str,
+    defaults: Defaults,
+    notes_start: usize,
+}
+
+/// Minimal byte cursor for parsing.
+struct Cursor<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, kind: RtttlErrorKind) -> RtttlError {
+        RtttlError {
+            pos: self.pos,
+            kind,
+        }
+    }
+
+    /// Parse a decimal number, if there is one.
+    fn number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        let mut n: u32 = 0;
+        while let Some(c) = self.peek().filter(u8::is_ascii_digit) {
+            n = n.saturating_mul(10).saturating_add((c - b'0') as u32);
+            self.pos += 1;
+        }
+        (self.pos > start).then_some(n)
+    }
+}
+
+fn valid_duration(d: u32) -> bool {
+    matches!(d, 1 | 2 | 4 | 8 | 16 | 32)
+}
+
+fn valid_octave(o: u32) -> bool {
+    (3..=8).contains(&o)
+}
+
+impl<'a> Rtttl<'a> {
+    /// Parse the name and settings of `src`. The notes are
+    /// parsed as they are read by [Self::notes].
+    pub fn parse(src: &'a str) -> Result<Self, RtttlError> {
+        let missing = RtttlError {
+            pos: src.len(),
+            kind: RtttlErrorKind::MissingSection,
+        };
+        let name_end = src.find(':').ok_or(missing)?;
+        let mut cursor = Cursor {
+            src: src.as_bytes(),
+            pos: name_end + 1,
+        };
+        let mut defaults = Defaults {
+            duration: 4,
+            octave: 6,
+            bpm: 63,
+        };
+        loop {
+            cursor.skip_spaces();
+            match cursor.peek() {
+                None => return Err(missing),
+                Some(b':') => break,
+                Some(b',') => {
+                    cursor.pos += 1;
+                    continue;
+                }
+                _ => (),
+            }
+            let key = cursor.peek().unwrap().to_ascii_lowercase();
+            let key_pos = cursor.pos;
+            cursor.pos += 1;
+            cursor.skip_spaces();
+            if !cursor.eat(b'=') {
+                return Err(cursor.error(RtttlErrorKind::ExpectedEquals));
+            }
+            cursor.skip_spaces();
+            let value_pos = cursor.pos;
+            let value = cursor
+                .number()
+                .ok_or(cursor.error(RtttlErrorKind::ExpectedNumber))?;
+            let bad = |kind| RtttlError {
+                pos: value_pos,
+                kind,
+            };
+            match key {
+                b'd' if valid_duration(value) => defaults.duration = value,
+                b'd' => return Err(bad(RtttlErrorKind::BadDuration)),
+                b'o' if valid_octave(value) => defaults.octave = value,
+                b'o' => return Err(bad(RtttlErrorKind::BadOctave)),
+                b'b' if (1..=999).contains(&value) => defaults.bpm = value,
+                b'b' => return Err(bad(RtttlErrorKind::BadTempo)),
+                _ => {
+                    return Err(RtttlError {
+                        pos: key_pos,
+                        kind: RtttlErrorKind::UnknownSetting,
+                    })
+                }
+            }
+        }
+        Ok(Self {
+            src,
+            name: &src[..name_end],
+            defaults,
+            notes_start: cursor.pos + 1,
+        })
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Tempo in quarter notes per minute.
+    pub fn bpm(&self) -> u32 {
+        self.defaults.bpm
+    }
+
+    /// Iterator over the notes. Stops after the first error.
+    pub fn notes(&self) -> Notes<'a> {
+        Notes {
+            cursor: Cursor {
+                src: self.src.as_bytes(),
+                pos: self.notes_start,
+            },
+            defaults: self.defaults,
+            failed: false,
+        }
+    }
+
+    /// Check that every note parses.
+    pub fn validate(&self) -> Result<(), RtttlError> {
+        self.notes().try_for_each(|note| note.map(drop))
+    }
+}
+
+/// Iterator over the notes of an [Rtttl].
+pub struct Notes<'a> {
+    cursor: Cursor<'a>,
+    defaults: Defaults,
+    failed: bool,
+}
+
+impl Notes<'_> {
+    fn note(&mut self) -> Result<Note, RtttlError> {
+        let c = &mut self.cursor;
+        let duration_pos = c.pos;
+        let duration = c.number().unwrap_or(self.defaults.duration);
+        if !valid_duration(duration) {
+            return Err(RtttlError {
+                pos: duration_pos,
+                kind: RtttlErrorKind::BadDuration,
+            });
+        }
+        let semitone = match c.peek().map(|c| c.to_ascii_lowercase()) {
+            Some(b'c') => Some(0),
+            Some(b'd') => Some(2),
+            Some(b'e') => Some(4),
+            Some(b'f') => Some(5),
+            Some(b'g') => Some(7),
+            Some(b'a') => Some(9),
+            Some(b'b') | Some(b'h') => Some(11),
+            Some(b'p') => None,
+            _ => return Err(c.error(RtttlErrorKind::ExpectedNote)),
+        };
+        c.pos += 1;
+        let sharp = c.eat(b'#');
+        let mut dotted = c.eat(b'.');
+        let octave_pos = c.pos;
+        let octave = c.number().unwrap_or(self.defaults.octave);
+        if !valid_octave(octave) {
+            return Err(RtttlError {
+                pos: octave_pos,
+                kind: RtttlErrorKind::BadOctave,
+            });
+        }
+        dotted |= c.eat(b'.');
+        c.skip_spaces();
+        if !(c.peek().is_none() || c.eat(b',')) {
+            return Err(c.error(RtttlErrorKind::ExpectedComma));
+        }
+
+        // A whole note is four beats.
+        let mut duration_ms = 240_000 / (self.defaults.bpm * duration);
+        if dotted {
+            duration_ms += duration_ms / 2;
+        }
+        let midi = semitone.map(|s| (12 * (octave + 1) + s + sharp as u32) as u8);
+        Ok(Note { midi, duration_ms })
+    }
+}
+
+impl Iterator for Notes<'_> {
+    type Item = Result<Note, RtttlError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.skip_spaces();
+        if self.failed || self.cursor.peek().is_none() {
+            return None;
+        }
+        let note = self.note();
+        self.failed = note.is_err();
+        Some(note)
+    }
+}
+
+/// Fraction of each note (in eighths) that sounds before the
+/// gate closes, so repeated notes are articulated.
+const NOTE_EIGHTHS: u32 = 7;
+
+/// Plays an RTTTL melody on a [Mixer], one sample at a time.
+pub struct Player<'a> {
+    notes: Notes<'a>,
+    sample_rate: u32,
+    gain: u16,
+    /// Samples left in the current note.
+    left: u32,
+    /// Samples left in the current note when its gate closes.
+    release_at: u32,
+    key: Option<u8>,
+}
+
+impl<'a> Player<'a> {
+    /// Play `rtttl` at `sample_rate`.
+    pub fn new(rtttl: &Rtttl<'a>, sample_rate: u32) -> Self {
+        Self {
+            notes: rtttl.notes(),
+            sample_rate,
+            gain: UNITY / 2,
+            left: 0,
+            release_at: 0,
+            key: None,
+        }
+    }
+
+    /// Set the Q15 gain of notes.
+    pub fn set_gain(&mut self, gain: u16) {
+        self.gain = gain;
+    }
+
+    /// Advance one sample, starting and stopping notes on
+    /// `mixer` as needed. Returns `false` once the melody has
+    /// finished (or hit a malformed note).
+    pub fn tick<const N: usize>(&mut self, mixer: &mut Mixer<N>) -> bool {
+        if self.left == self.release_at {
+            if let Some(key) = self.key.take() {
+                mixer.note_off(key);
+            }
+        }
+        if self.left == 0 {
+            let Some(Ok(note)) = self.notes.next() else {
+                return false;
+            };
+            self.left = (note.duration_ms as u64 * self.sample_rate as u64 / 1_000).max(1) as u32;
+            self.release_at = self.left - self.left * NOTE_EIGHTHS / 8;
+            if let (Some(midi), Some(freq)) = (note.midi, note.freq_hz()) {
+                mixer.note_on(midi, freq, self.gain);
+                self.key = Some(midi);
+            }
+        }
+        self.left -= 1;
+        true
+    }
+
+    /// Stop the current note, if any.
+    pub fn stop<const N: usize>(&mut self, mixer: &mut Mixer<N>) {
+        if let Some(key) = self.key.take() {
+            mixer.note_off(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{envelope::Adsr, osc::Waveform, songs::RINGTONES};
+    use RtttlErrorKind::*;
+
+    fn notes(src: &str) -> Result<Vec<Note>, RtttlError> {
+        Rtttl::parse(src)?.notes().collect()
+    }
+
+    fn error(src: &str) -> (usize, RtttlErrorKind) {
+        let e = notes(src).unwrap_err();
+        (e.pos, e.kind)
+    }
+
+    #[test]
+    fn classic_ringtones_parse() {
+        let expected = [
+            ("Tetris", 160, 42),
+            ("Simpsons", 160, 23),
+            ("Entertainer", 140, 38),
+            ("Indiana", 250, 55),
+            ("TakeOnMe", 160, 32),
+        ];
+        assert_eq!(RINGTONES.len(), expected.len());
+        for (src, (name, bpm, count)) in RINGTONES.iter().zip(expected) {
+            let rtttl = Rtttl::parse(src).unwrap();
+            assert_eq!(rtttl.name(), name);
+            assert_eq!(rtttl.bpm(), bpm);
+            let notes = notes(src).unwrap();
+            assert_eq!(notes.len(), count, "{}", name);
+        }
+    }
+
+    #[test]
+    fn tetris_opening() {
+        let notes = notes(RINGTONES[0]).unwrap();
+        // Quarter note at 160bpm is 375ms.
+        assert_eq!(notes[0], Note { midi: Some(88), duration_ms: 375 });
+        assert_eq!(notes[1], Note { midi: Some(83), duration_ms: 187 });
+        assert_eq!(notes[4], Note { midi: Some(88), duration_ms: 93 });
+        assert_eq!(notes[22], Note { midi: None, duration_ms: 187 });
+    }
+
+    #[test]
+    fn note_syntax() {
+        let notes = notes("x:d=8,o=5,b=120:c,4c#6,2p.,16g#.,a#4.,h,  E6 , 1b.7").unwrap();
+        let expected = [
+            (Some(72), 250),
+            (Some(85), 500),
+            (None, 1_500),
+            (Some(80), 187),
+            (Some(70), 375),
+            (Some(83), 250),
+            (Some(88), 250),
+            (Some(107), 3_000),
+        ];
+        let actual: Vec<_> = notes.iter().map(|n| (n.midi, n.duration_ms)).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn defaults_when_unspecified() {
+        let notes = notes("x::a").unwrap();
+        // d=4, o=6, b=63.
+        assert_eq!(notes, [Note { midi: Some(93), duration_ms: 952 }]);
+        assert_eq!(self::notes("x:b=100:").unwrap(), []);
+    }
+
+    #[test]
+    fn note_frequencies() {
+        let notes = notes("x:d=4,o=4,b=60:a,a5,c").unwrap();
+        assert_eq!(notes[0].freq_hz(), Some(440.0));
+        assert_eq!(notes[1].freq_hz(), Some(880.0));
+        assert!((notes[2].freq_hz().unwrap() - 261.626).abs() < 0.01);
+        assert_eq!(Note { midi: None, duration_ms: 1 }.freq_hz(), None);
+    }
+
+    #[test]
+    fn malformed_rejected_with_position() {
+        assert_eq!(error("no sections"), (11, MissingSection));
+        assert_eq!(error("x:d=4"), (5, MissingSection));
+        assert_eq!(error("x:d=4,q=5:a"), (6, UnknownSetting));
+        assert_eq!(error("x:d4:a"), (3, ExpectedEquals));
+        assert_eq!(error("x:d=:a"), (4, ExpectedNumber));
+        assert_eq!(error("x:d=3:a"), (4, BadDuration));
+        assert_eq!(error("x:o=9:a"), (4, BadOctave));
+        assert_eq!(error("x:b=0:a"), (4, BadTempo));
+        assert_eq!(error("x::a,3b,c"), (5, BadDuration));
+        assert_eq!(error("x::a,8x,c"), (6, ExpectedNote));
+        assert_eq!(error("x::a,b9,c"), (6, BadOctave));
+        assert_eq!(error("x::a,b6;c"), (7, ExpectedComma));
+        assert_eq!(error("x::a,,c"), (5, ExpectedNote));
+    }
+
+    #[test]
+    fn notes_stop_after_error() {
+        let rtttl = Rtttl::parse("x::a,z,b,c").unwrap();
+        let results: Vec<_> = rtttl.notes().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+        assert!(rtttl.validate().is_err());
+    }
+
+    #[test]
+    fn player_follows_timing() {
+        let adsr = Adsr {
+            attack_ms: 0,
+            decay_ms: 0,
+            sustain: UNITY,
+            release_ms: 0,
+        };
+        let mut mixer: Mixer<2> = Mixer::new(1_000, Waveform::Sine, adsr);
+        // 250ms each at 1kHz: 250 samples.
+        let rtttl = Rtttl::parse("x:d=4,o=4,b=240:a,p,c5").unwrap();
+        let mut player = Player::new(&rtttl, 1_000);
+        let mut keys = Vec::new();
+        while player.tick(&mut mixer) {
+            keys.push(mixer.voice_key(0).or(mixer.voice_key(1)));
+        }
+        assert_eq!(keys.len(), 750);
+        // Each note sounds for 7/8 of its time.
+        assert!(keys[..218].iter().all(|&k| k == Some(69)));
+        assert!(keys[219..500].iter().all(|&k| k.is_none()));
+        assert!(keys[500..718].iter().all(|&k| k == Some(72)));
+        assert!(keys[719..].iter().all(|&k| k.is_none()));
+    }
+}
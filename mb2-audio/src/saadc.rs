@@ -0,0 +1,75 @@
+//! Sample capture through the nRF52 SAADC.
+//!
+//! The SAADC's internal timer triggers each conversion, so
+//! the sample rate is set by a capture-compare value. The
+//! 12-bit single-ended results are scaled up to full-range
+//! samples, which sit on the microphone's bias until a
+//! [DcBlocker](crate::filter::DcBlocker) takes it out.
+
+use crate::Sample;
+
+/// Clock driving the SAADC sample timer.
+pub const SAADC_CLOCK_HZ: u32 = 16_000_000;
+
+/// Lowest supported sample rate: the timer compare value
+/// can be at most 2047.
+pub const MIN_SAMPLE_RATE: u32 = 8_000;
+
+/// Highest supported sample rate.
+pub const MAX_SAMPLE_RATE: u32 = 32_000;
+
+/// Reasons SAADC capture cannot be set up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaadcError {
+    /// Sample rate was outside
+    /// [MIN_SAMPLE_RATE]..=[MAX_SAMPLE_RATE].
+    SampleRateOutOfRange(u32),
+}
+
+/// Sample timer compare value giving the sample rate nearest
+/// to `sample_rate`. The actual sample rate is
+/// `SAADC_CLOCK_HZ / cc`.
+pub fn sample_cc(sample_rate: u32) -> Result<u16, SaadcError> {
+    if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+        return Err(SaadcError::SampleRateOutOfRange(sample_rate));
+    }
+    Ok(((SAADC_CLOCK_HZ + sample_rate / 2) / sample_rate) as u16)
+}
+
+/// Scale a 12-bit single-ended result to a sample. Results
+/// can dip slightly below zero from noise; anything out of
+/// range is clamped.
+pub fn to_sample(raw: i16) -> Sample {
+    raw.clamp(-4096, 4095) << 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cc_for_common_rates() {
+        assert_eq!(sample_cc(8_000), Ok(2_000));
+        assert_eq!(sample_cc(16_000), Ok(1_000));
+        assert_eq!(sample_cc(32_000), Ok(500));
+        assert_eq!(sample_cc(22_050), Ok(726));
+        // The compare value field is 11 bits.
+        assert!(sample_cc(MIN_SAMPLE_RATE).unwrap() < 2_048);
+    }
+
+    #[test]
+    fn rate_out_of_range_rejected() {
+        assert_eq!(sample_cc(7_999), Err(SaadcError::SampleRateOutOfRange(7_999)));
+        assert_eq!(sample_cc(32_001), Err(SaadcError::SampleRateOutOfRange(32_001)));
+    }
+
+    #[test]
+    fn results_scaled() {
+        assert_eq!(to_sample(0), 0);
+        assert_eq!(to_sample(2_048), 16_384);
+        assert_eq!(to_sample(4_095), 32_760);
+        assert_eq!(to_sample(-3), -24);
+        assert_eq!(to_sample(i16::MIN), -32_768);
+        assert_eq!(to_sample(i16::MAX), 32_760);
+    }
+}
@@ -0,0 +1,684 @@
+//! Standard MIDI File (SMF) parsing and playback.
+//!
+//! An SMF is an `MThd` header chunk followed by `MTrk` track
+//! chunks. Each track is a list of events, each preceded by
+//! a variable-length delta time in ticks; the header gives
+//! the number of ticks per quarter note, and tempo
+//! meta-events give the length of a quarter note in
+//! microseconds.
+//!
+//! Formats 0 (one track) and 1 (several simultaneous
+//! tracks) are supported; format 2 and SMPTE timing are
+//! not. The tracks are merged into a single timeline as they
+//! are read, without allocation. Only note and tempo events
+//! are reported: everything else is skipped. Running status
+//! is honoured, and is kept across meta and sysex events
+//! for the sake of files that rely on it.
+
+use crate::{envelope::UNITY, mixer::Mixer, tuning};
+
+/// Most tracks an [Smf] can hold.
+pub const MAX_TRACKS: usize = 16;
+
+/// Tempo until the first tempo event: 120 bpm.
+pub const DEFAULT_TEMPO_US: u32 = 500_000;
+
+/// Channel conventionally used for percussion (channel 10
+/// as numbered from 1), which the [Player] leaves out.
+pub const PERCUSSION_CHANNEL: u8 = 9;
+
+/// What went wrong parsing an SMF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmfErrorKind {
+    /// No valid `MThd` chunk at the start.
+    BadHeader,
+    /// Format other than 0 or 1.
+    UnsupportedFormat(u16),
+    /// Division given as SMPTE frames rather than ticks.
+    SmpteTiming,
+    /// More than [MAX_TRACKS] tracks.
+    TooManyTracks,
+    /// The data ended in the middle of something.
+    Truncated,
+    /// A variable-length number longer than four bytes.
+    BadNumber,
+    /// A data byte with no running status, or a status byte
+    /// not allowed in a file.
+    BadStatus,
+}
+
+/// An SMF parse error at byte offset `pos`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmfError {
+    pub pos: usize,
+    pub kind: SmfErrorKind,
+}
+
+/// A musical event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    /// Note off, or note on with velocity zero.
+    NoteOff { channel: u8, key: u8 },
+    /// New tempo in microseconds per quarter note.
+    Tempo(u32),
+}
+
+/// An [Event] and when it happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedEvent {
+    /// Ticks since the start.
+    pub tick: u32,
+    /// Microseconds since the start.
+    pub time_us: u64,
+    pub event: Event,
+}
+
+/// A parsed SMF header and track list, with the events
+/// still to be read.
+#[derive(Debug, Clone, Copy)]
+pub struct Smf<'a> {
+    data: &'a [u8],
+    format: u16,
+    division: u16,
+    /// Start and end offsets of each track's events.
+    tracks: [(usize, usize); MAX_TRACKS],
+    ntracks: usize,
+}
+
+fn error(pos: usize, kind: SmfErrorKind) -> SmfError {
+    SmfError { pos, kind }
+}
+
+fn be_u16(b: &[u8]) -> u16 {
+    u16::from_be_bytes([b[0], b[1]])
+}
+
+fn be_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+impl<'a> Smf<'a> {
+    /// Parse the header of `data` and find its tracks. The
+    /// events are parsed as they are read by [Self::events].
+    /// Chunks other than `MTrk` are skipped.
+    pub fn parse(data: &'a [u8]) -> Result<Self, SmfError> {
+        if data.len() < 14 || &data[..4] != b"MThd" {
+            return Err(error(0, SmfErrorKind::BadHeader));
+        }
+        let header_len = be_u32(&data[4..]) as usize;
+        if header_len < 6 {
+            return Err(error(4, SmfErrorKind::BadHeader));
+        }
+        let format = be_u16(&data[8..]);
+        let declared = be_u16(&data[10..]) as usize;
+        let division = be_u16(&data[12..]);
+        match format {
+            0 if declared != 1 => return Err(error(10, SmfErrorKind::BadHeader)),
+            0 | 1 => (),
+            _ => return Err(error(8, SmfErrorKind::UnsupportedFormat(format))),
+        }
+        if division & 0x8000 != 0 {
+            return Err(error(12, SmfErrorKind::SmpteTiming));
+        }
+        if division == 0 {
+            return Err(error(12, SmfErrorKind::BadHeader));
+        }
+        if declared > MAX_TRACKS {
+            return Err(error(10, SmfErrorKind::TooManyTracks));
+        }
+
+        let mut tracks = [(0, 0); MAX_TRACKS];
+        let mut ntracks = 0;
+        let mut pos = 8usize.saturating_add(header_len);
+        while ntracks < declared {
+            if data.len().saturating_sub(pos) < 8 {
+                return Err(error(pos.min(data.len()), SmfErrorKind::Truncated));
+            }
+            let len = be_u32(&data[pos + 4..]) as usize;
+            let start = pos + 8;
+            let end = start.saturating_add(len);
+            if end > data.len() {
+                return Err(error(data.len(), SmfErrorKind::Truncated));
+            }
+            if &data[pos..pos + 4] == b"MTrk" {
+                tracks[ntracks] = (start, end);
+                ntracks += 1;
+            }
+            pos = end;
+        }
+        Ok(Self {
+            data,
+            format,
+            division,
+            tracks,
+            ntracks,
+        })
+    }
+
+    pub fn format(&self) -> u16 {
+        self.format
+    }
+
+    /// Ticks per quarter note.
+    pub fn division(&self) -> u16 {
+        self.division
+    }
+
+    pub fn tracks(&self) -> usize {
+        self.ntracks
+    }
+
+    /// Iterator over the events of all tracks, in time
+    /// order. Simultaneous events come in track order. Stops
+    /// after the first error.
+    pub fn events(&self) -> Events<'a> {
+        Events {
+            tracks: core::array::from_fn(|i| {
+                let (start, end) = self.tracks[i];
+                Track {
+                    data: self.data,
+                    pos: start,
+                    end,
+                    status: None,
+                    tick: 0,
+                }
+            }),
+            pending: [None; MAX_TRACKS],
+            ntracks: self.ntracks,
+            refill: None,
+            primed: false,
+            failed: false,
+            division: self.division as u64,
+            tempo: DEFAULT_TEMPO_US,
+            base_tick: 0,
+            base_us: 0,
+        }
+    }
+
+    /// Check that every event parses.
+    pub fn validate(&self) -> Result<(), SmfError> {
+        self.events().try_for_each(|event| event.map(drop))
+    }
+}
+
+/// Reader for the events of one track.
+struct Track<'a> {
+    data: &'a [u8],
+    pos: usize,
+    end: usize,
+    status: Option<u8>,
+    tick: u32,
+}
+
+impl Track<'_> {
+    fn byte(&mut self) -> Result<u8, SmfError> {
+        if self.pos >= self.end {
+            return Err(error(self.pos, SmfErrorKind::Truncated));
+        }
+        let b = self.data[self.pos];
+        self.pos += 1;
+        Ok(b)
+    }
+
+    /// Read a variable-length number: seven bits per byte,
+    /// most significant first, top bit set on all but the
+    /// last byte.
+    fn number(&mut self) -> Result<u32, SmfError> {
+        let start = self.pos;
+        let mut n = 0;
+        for _ in 0..4 {
+            let b = self.byte()?;
+            n = (n << 7) | (b & 0x7f) as u32;
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(error(start, SmfErrorKind::BadNumber))
+    }
+
+    /// Skip `len` bytes of event data, returning them.
+    fn skip(&mut self, len: u32) -> Result<&[u8], SmfError> {
+        let start = self.pos;
+        if len as usize > self.end - start {
+            return Err(error(self.end, SmfErrorKind::Truncated));
+        }
+        self.pos += len as usize;
+        Ok(&self.data[start..self.pos])
+    }
+
+    /// The next note or tempo event and its tick, or `None`
+    /// at the end of the track.
+    fn next_event(&mut self) -> Result<Option<(u32, Event)>, SmfError> {
+        loop {
+            if self.pos >= self.end {
+                return Ok(None);
+            }
+            let delta = self.number()?;
+            self.tick = self.tick.saturating_add(delta);
+            let status_pos = self.pos;
+            let status = match self.data.get(self.pos) {
+                Some(&b) if b & 0x80 != 0 => {
+                    self.pos += 1;
+                    b
+                }
+                Some(_) => match self.status {
+                    Some(status) => status,
+                    None => return Err(error(status_pos, SmfErrorKind::BadStatus)),
+                },
+                None => return Err(error(status_pos, SmfErrorKind::Truncated)),
+            };
+            match status {
+                0xff => {
+                    let kind = self.byte()?;
+                    let len = self.number()?;
+                    let data = self.skip(len)?;
+                    match kind {
+                        0x2f => {
+                            self.pos = self.end;
+                            return Ok(None);
+                        }
+                        0x51 if data.len() == 3 => {
+                            let tempo = u32::from_be_bytes([0, data[0], data[1], data[2]]);
+                            return Ok(Some((self.tick, Event::Tempo(tempo))));
+                        }
+                        _ => (),
+                    }
+                }
+                0xf0 | 0xf7 => {
+                    let len = self.number()?;
+                    self.skip(len)?;
+                }
+                0xf1..=0xfe => return Err(error(status_pos, SmfErrorKind::BadStatus)),
+                _ => {
+                    self.status = Some(status);
+                    let channel = status & 0x0f;
+                    let data1 = self.byte()?;
+                    let data2 = match status >> 4 {
+                        0xc | 0xd => 0,
+                        _ => self.byte()?,
+                    };
+                    let event = match (status >> 4, data2) {
+                        (0x8, _) | (0x9, 0) => Event::NoteOff { channel, key: data1 },
+                        (0x9, velocity) => Event::NoteOn {
+                            channel,
+                            key: data1,
+                            velocity,
+                        },
+                        _ => continue,
+                    };
+                    return Ok(Some((self.tick, event)));
+                }
+            }
+        }
+    }
+}
+
+/// Iterator over the merged events of an [Smf].
+pub struct Events<'a> {
+    tracks: [Track<'a>; MAX_TRACKS],
+    /// Next event of each track.
+    pending: [Option<(u32, Event)>; MAX_TRACKS],
+    ntracks: usize,
+    /// Track whose pending event was last taken.
+    refill: Option<usize>,
+    primed: bool,
+    failed: bool,
+    division: u64,
+    tempo: u32,
+    /// Tick and time of the last tempo change.
+    base_tick: u32,
+    base_us: u64,
+}
+
+impl Events<'_> {
+    fn fill(&mut self, track: usize) -> Result<(), SmfError> {
+        self.pending[track] = self.tracks[track].next_event()?;
+        Ok(())
+    }
+
+    fn fill_pending(&mut self) -> Result<(), SmfError> {
+        if !self.primed {
+            self.primed = true;
+            for track in 0..self.ntracks {
+                self.fill(track)?;
+            }
+        }
+        if let Some(track) = self.refill.take() {
+            self.fill(track)?;
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<TimedEvent, SmfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        if let Err(e) = self.fill_pending() {
+            self.failed = true;
+            return Some(Err(e));
+        }
+        let mut next: Option<(usize, u32)> = None;
+        for (track, pending) in self.pending[..self.ntracks].iter().enumerate() {
+            if let Some((tick, _)) = pending {
+                if next.is_none_or(|(_, t)| *tick < t) {
+                    next = Some((track, *tick));
+                }
+            }
+        }
+        let (track, tick) = next?;
+        let (_, event) = self.pending[track].take().unwrap();
+        self.refill = Some(track);
+
+        let ticks = (tick - self.base_tick) as u64;
+        let time_us = self.base_us + ticks * self.tempo as u64 / self.division;
+        if let Event::Tempo(tempo) = event {
+            self.tempo = tempo;
+            self.base_tick = tick;
+            self.base_us = time_us;
+        }
+        Some(Ok(TimedEvent {
+            tick,
+            time_us,
+            event,
+        }))
+    }
+}
+
+/// Plays an SMF on a [Mixer], one sample at a time. Notes
+/// are keyed by MIDI note number alone, whatever their
+/// channel, and the percussion channel is left out.
+pub struct Player<'a> {
+    events: Events<'a>,
+    sample_rate: u32,
+    gain: u16,
+    /// Samples played so far.
+    now: u64,
+    /// The next event, once read, and its sample.
+    next: Option<(u64, Event)>,
+}
+
+impl<'a> Player<'a> {
+    /// Play `smf` at `sample_rate`.
+    pub fn new(smf: &Smf<'a>, sample_rate: u32) -> Self {
+        Self {
+            events: smf.events(),
+            sample_rate,
+            gain: UNITY / 2,
+            now: 0,
+            next: None,
+        }
+    }
+
+    /// Set the Q15 gain of notes at full velocity.
+    pub fn set_gain(&mut self, gain: u16) {
+        self.gain = gain;
+    }
+
+    /// Advance one sample, starting and stopping notes on
+    /// `mixer` as needed. Returns `false` once the file has
+    /// finished (or hit a malformed event).
+    pub fn tick<const N: usize>(&mut self, mixer: &mut Mixer<N>) -> bool {
+        loop {
+            let (at, event) = match self.next {
+                Some(next) => next,
+                None => {
+                    let Some(Ok(e)) = self.events.next() else {
+                        return false;
+                    };
+                    let at = e.time_us * self.sample_rate as u64 / 1_000_000;
+                    self.next = Some((at, e.event));
+                    (at, e.event)
+                }
+            };
+            if at > self.now {
+                break;
+            }
+            self.next = None;
+            match event {
+                Event::NoteOn {
+                    channel,
+                    key,
+                    velocity,
+                } if channel != PERCUSSION_CHANNEL => {
+                    let gain = (self.gain as u32 * velocity as u32 / 127) as u16;
+                    mixer.note_on(key, tuning::midi_to_freq(key), gain);
+                }
+                Event::NoteOff { channel, key } if channel != PERCUSSION_CHANNEL => {
+                    mixer.note_off(key);
+                }
+                _ => (),
+            }
+        }
+        self.now += 1;
+        true
+    }
+
+    /// Stop all notes.
+    pub fn stop<const N: usize>(&mut self, mixer: &mut Mixer<N>) {
+        mixer.all_notes_off();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{envelope::Adsr, osc::Waveform, songs::MIDI_SONGS};
+    use Event::*;
+    use SmfErrorKind::*;
+
+    fn events(data: &[u8]) -> Result<Vec<TimedEvent>, SmfError> {
+        Smf::parse(data)?.events().collect()
+    }
+
+    fn error(data: &[u8]) -> (usize, SmfErrorKind) {
+        let e = events(data).unwrap_err();
+        (e.pos, e.kind)
+    }
+
+    /// A format 0 file at 96 ticks per quarter with the given
+    /// track contents.
+    fn single_track(track: &[u8]) -> Vec<u8> {
+        let mut data = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk".to_vec();
+        data.extend((track.len() as u32).to_be_bytes());
+        data.extend(track);
+        data
+    }
+
+    fn on(key: u8, velocity: u8) -> Event {
+        NoteOn {
+            channel: 0,
+            key,
+            velocity,
+        }
+    }
+
+    fn off(key: u8) -> Event {
+        NoteOff { channel: 0, key }
+    }
+
+    #[test]
+    fn twinkle_timeline() {
+        let smf = Smf::parse(MIDI_SONGS[0].1).unwrap();
+        assert_eq!(smf.format(), 0);
+        assert_eq!(smf.tracks(), 1);
+        assert_eq!(smf.division(), 96);
+        let events = events(MIDI_SONGS[0].1).unwrap();
+        assert_eq!(events.len(), 30);
+        let timeline: Vec<_> = events[..5].iter().map(|e| (e.tick, e.time_us, e.event)).collect();
+        assert_eq!(
+            timeline,
+            [
+                (0, 0, Tempo(500_000)),
+                (0, 0, on(60, 100)),
+                (84, 437_500, off(60)),
+                (96, 500_000, on(60, 100)),
+                (180, 937_500, off(60)),
+            ]
+        );
+        let keys: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e.event {
+                NoteOn { key, .. } => Some(key),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(keys, [60, 60, 67, 67, 69, 69, 67, 65, 65, 64, 64, 62, 62, 60]);
+        // The last note is slowed to 100bpm.
+        let timeline: Vec<_> = events[27..].iter().map(|e| (e.tick, e.time_us, e.event)).collect();
+        assert_eq!(
+            timeline,
+            [
+                (1_344, 7_000_000, Tempo(600_000)),
+                (1_344, 7_000_000, on(60, 100)),
+                (1_524, 8_125_000, off(60)),
+            ]
+        );
+    }
+
+    #[test]
+    fn minuet_merges_tracks() {
+        let smf = Smf::parse(MIDI_SONGS[1].1).unwrap();
+        assert_eq!(smf.format(), 1);
+        assert_eq!(smf.tracks(), 3);
+        let events = events(MIDI_SONGS[1].1).unwrap();
+        // A tempo, then 32 melody and 11 bass notes.
+        assert_eq!(events.len(), 1 + 2 * 32 + 2 * 11);
+        assert!(events.windows(2).all(|w| w[0].tick <= w[1].tick));
+        let start: Vec<_> = events[..3].iter().map(|e| e.event).collect();
+        assert_eq!(
+            start,
+            [
+                Tempo(600_000),
+                on(74, 90),
+                NoteOn {
+                    channel: 1,
+                    key: 55,
+                    velocity: 70
+                },
+            ]
+        );
+        // The second melody note is an eighth after the first.
+        assert_eq!((events[4].tick, events[4].time_us, events[4].event), (120, 600_000, on(67, 90)));
+        // Eight bars of 3/4 at 100bpm, less a short gap.
+        let last = events.last().unwrap();
+        assert_eq!((last.tick, last.time_us), (2_870, 14_350_000));
+    }
+
+    #[test]
+    fn running_status_and_skipped_events() {
+        let data = single_track(&[
+            0x00, 0xf0, 0x03, 0x7e, 0x09, 0xf7, // sysex
+            0x00, 0xc1, 0x05, // program change, ignored
+            0x00, 0xb1, 0x07, 0x64, // controller, ignored
+            0x00, 0xe1, 0x00, 0x40, // pitch bend, ignored
+            0x00, 0x91, 0x40, 0x50, // note on, channel 1
+            0x10, 0x42, 0x50, // running status
+            0x10, 0xff, 0x01, 0x02, b'h', b'i', // text
+            0x10, 0x40, 0x00, // running status, velocity 0
+            0x81, 0x00, 0x81, 0x42, 0x00, // note off, delta 128
+            0x00, 0xff, 0x2f, 0x00, // end of track
+            0x00, 0x91, 0x40, 0x50, // after the end
+        ]);
+        let events: Vec<_> = events(&data)
+            .unwrap()
+            .iter()
+            .map(|e| (e.tick, e.event))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (0, NoteOn { channel: 1, key: 0x40, velocity: 0x50 }),
+                (16, NoteOn { channel: 1, key: 0x42, velocity: 0x50 }),
+                (48, NoteOff { channel: 1, key: 0x40 }),
+                (176, NoteOff { channel: 1, key: 0x42 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_chunks_skipped() {
+        let mut data = b"MThd\0\0\0\x06\0\0\0\x01\0\x60XFIH\0\0\0\x02ab".to_vec();
+        data.extend(b"MTrk\0\0\0\x04\0\x90\x3c\x40");
+        assert_eq!(events(&data).unwrap()[0].event, on(0x3c, 0x40));
+    }
+
+    #[test]
+    fn malformed_rejected_with_position() {
+        assert_eq!(error(b"RIFF\0\0\0\x06\0\0\0\x01\0\x60"), (0, BadHeader));
+        assert_eq!(error(b"MThd\0\0\0\x06\0\0"), (0, BadHeader));
+        assert_eq!(error(b"MThd\0\0\0\x06\0\x02\0\x01\0\x60"), (8, UnsupportedFormat(2)));
+        assert_eq!(error(b"MThd\0\0\0\x06\0\0\0\x02\0\x60"), (10, BadHeader));
+        assert_eq!(error(b"MThd\0\0\0\x06\0\0\0\x01\xe7\x28"), (12, SmpteTiming));
+        assert_eq!(error(b"MThd\0\0\0\x06\0\x01\0\x11\0\x60"), (10, TooManyTracks));
+        assert_eq!(error(b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTr"), (14, Truncated));
+        assert_eq!(error(b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk\0\0\0\x09\0"), (23, Truncated));
+        assert_eq!(error(&single_track(&[0x00, 0x3c, 0x40])), (23, BadStatus));
+        assert_eq!(error(&single_track(&[0x00, 0xf8])), (23, BadStatus));
+        assert_eq!(error(&single_track(&[0x00, 0x90, 0x3c])), (25, Truncated));
+        assert_eq!(error(&single_track(&[0xff, 0xff, 0xff, 0xff, 0x00])), (22, BadNumber));
+        assert_eq!(error(&single_track(&[0x00, 0xff, 0x51, 0x09, 0x00])), (27, Truncated));
+    }
+
+    #[test]
+    fn events_stop_after_error() {
+        let data = single_track(&[0x00, 0x90, 0x3c, 0x40, 0x00, 0xf8, 0x00, 0x90, 0x3c, 0x00]);
+        let smf = Smf::parse(&data).unwrap();
+        let results: Vec<_> = smf.events().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert!(smf.validate().is_err());
+        for (_, song) in MIDI_SONGS {
+            assert!(Smf::parse(song).unwrap().validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn player_follows_timing() {
+        let adsr = Adsr {
+            attack_ms: 0,
+            decay_ms: 0,
+            sustain: UNITY,
+            release_ms: 0,
+        };
+        let mut mixer: Mixer<4> = Mixer::new(1_000, Waveform::Sine, adsr);
+        let smf = Smf::parse(MIDI_SONGS[0].1).unwrap();
+        let mut player = Player::new(&smf, 1_000);
+        let mut keys = Vec::new();
+        while player.tick(&mut mixer) {
+            keys.push((0..4).find_map(|v| mixer.voice_key(v)));
+        }
+        // The last note ends at 8.125s.
+        assert_eq!(keys.len(), 8_125);
+        assert!(keys[..437].iter().all(|&k| k == Some(60)));
+        assert!(keys[437..500].iter().all(|&k| k.is_none()));
+        assert!(keys[1_000..1_437].iter().all(|&k| k == Some(67)));
+        assert_eq!(keys[8_124], Some(60));
+        assert!((0..4).all(|v| mixer.voice_key(v).is_none()));
+    }
+
+    #[test]
+    fn player_skips_percussion() {
+        let data = single_track(&[0x00, 0x99, 0x24, 0x7f, 0x00, 0x90, 0x3c, 0x7f, 0x60, 0x3c, 0x00]);
+        let adsr = Adsr {
+            attack_ms: 0,
+            decay_ms: 0,
+            sustain: UNITY,
+            release_ms: 0,
+        };
+        let mut mixer: Mixer<4> = Mixer::new(1_000, Waveform::Sine, adsr);
+        let smf = Smf::parse(&data).unwrap();
+        let mut player = Player::new(&smf, 1_000);
+        player.set_gain(UNITY);
+        assert!(player.tick(&mut mixer));
+        assert_eq!(mixer.active_voices(), 1);
+        assert_eq!(mixer.voice_key(0), Some(0x3c));
+        while player.tick(&mut mixer) {}
+        assert_eq!(mixer.voice_key(0), None);
+    }
+}
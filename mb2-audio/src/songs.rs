@@ -0,0 +1,120 @@
+//! Built-in melodies, sound clips and words.
+
+/// Classic ringtones in RTTTL format, for the
+/// [rtttl](crate::rtttl) player.
+pub static RINGTONES: &[&str] = &[
+    "Tetris:d=4,o=5,b=160:e6,8b,8c6,8d6,16e6,16d6,8c6,8b,a,8a,8c6,e6,8d6,8c6,b,8b,8c6,d6,e6,c6,a,2a,8p,d6,8f6,a6,8g6,8f6,e6,8e6,8c6,e6,8d6,8c6,b,8b,8c6,d6,e6,c6,a,a",
+    "Simpsons:d=4,o=5,b=160:c.6,e6,f#6,8a6,g.6,e6,c6,8a,8f#,8f#,8f#,2g,8p,8p,8f#,8f#,8f#,8g,a#.,8c6,8c6,8c6,c6",
+    "Entertainer:d=4,o=5,b=140:8d,8d#,8e,c6,8e,c6,8e,2c.6,8c6,8d6,8d#6,8e6,8c6,8d6,e6,8b,d6,2c6,p,8d,8d#,8e,c6,8e,c6,8e,2c.6,8p,8a,8g,8f#,8a,8c6,e6,8d6,8c6,8a,2d6",
+    "Indiana:d=4,o=5,b=250:e,8p,8f,8g,8p,1c6,8p.,d,8p,8e,1f,p.,g,8p,8a,8b,8p,1f6,p,a,8p,8b,2c6,2d6,2e6,e,8p,8f,8g,8p,1c6,p,d6,8p,8e6,1f.6,g,8p,8g,e.6,8p,d6,8p,8g,e.6,8p,d6,8p,8g,f.6,8p,e6,8p,8d6,2c6",
+    "TakeOnMe:d=4,o=4,b=160:8f#5,8f#5,8f#5,8d5,8p,8b,8p,8e5,8p,8e5,8p,8e5,8g#5,8g#5,8a5,8b5,8a5,8a5,8a5,8e5,8p,8d5,8p,8f#5,8p,8f#5,8p,8f#5,8e5,8e5,8f#5,8e5",
+];
+
+/// Named Standard MIDI files, for the [smf](crate::smf)
+/// player: "Twinkle, Twinkle, Little Star" (format 0) and
+/// the opening of Petzold's Minuet in G (format 1).
+pub static MIDI_SONGS: &[(&str, &[u8])] = &[
+    ("Twinkle", include_bytes!("../midi/twinkle.mid")),
+    ("Minuet", include_bytes!("../midi/minuet.mid")),
+];
+
+/// Named WAV clips, for the [wav](crate::wav) player: a
+/// bell (8-bit mono at 8kHz), a falling zap (16-bit stereo
+/// at 11025Hz) and a gong (IMA ADPCM mono at 16kHz, made
+/// with `cargo adpcm`).
+pub static CLIPS: &[(&str, &[u8])] = &[
+    ("Chime", include_bytes!("../clips/chime.wav")),
+    ("Zap", include_bytes!("../clips/zap.wav")),
+    ("Gong", include_bytes!("../clips/gong.wav")),
+];
+
+/// Named LPC bitstreams in the Talkie format, for the
+/// [lpc](crate::lpc) speech synthesizer: the five vowels,
+/// and "hi", made from textbook formant frequencies rather
+/// than recordings by `tools/lpc_model.py words`. Only add
+/// bitstreams whose license allows them into this MIT
+/// licensed crate: Talkie's own vocabularies are GPL.
+pub static WORDS: &[(&str, &[u8])] = &[
+    ("Vowels", &[
+        0x50, 0x8a, 0xc7, 0xad, 0x27, 0x5d, 0xfb, 0x2c, 0x66, 0x31, 0xcb, 0x59,
+        0xf5, 0x2a, 0xd7, 0xb6, 0x06, 0x28, 0xc5, 0xcf, 0x1d, 0x2a, 0xee, 0x7d,
+        0x16, 0xb3, 0x98, 0xe5, 0xac, 0x7a, 0x95, 0x6b, 0x5b, 0x03, 0xa4, 0xe2,
+        0x9f, 0x4f, 0x06, 0xf2, 0x9f, 0x8b, 0x5c, 0xe4, 0x32, 0x57, 0xb9, 0xca,
+        0xb5, 0xad, 0x01, 0x4a, 0xb1, 0x60, 0xcc, 0xb8, 0x6a, 0x9f, 0xc5, 0x2c,
+        0x66, 0x39, 0xab, 0x5e, 0xe5, 0xda, 0xd6, 0x00, 0xa5, 0x98, 0xc0, 0x3a,
+        0xd5, 0xb8, 0xf7, 0xa2, 0x17, 0xbd, 0xec, 0x55, 0xaf, 0x72, 0x6d, 0x6b,
+        0x80, 0x07,
+    ]),
+    ("Hi", &[
+        0x60, 0x80, 0xc7, 0xad, 0x33, 0xd0, 0xf2, 0xc7, 0xad, 0x27, 0x5d, 0xfb,
+        0xcc, 0x67, 0x3e, 0x8a, 0x1f, 0xcc, 0x2a, 0x5c, 0xfb, 0x28, 0x7e, 0x4d,
+        0x75, 0x77, 0xef, 0xad, 0xf8, 0x73, 0x55, 0xd5, 0xfc, 0xa7, 0xf2, 0xef,
+        0x77, 0x21, 0xf1, 0x9f, 0xaa, 0x7f, 0x3e, 0x19, 0xc8, 0x7f, 0xae, 0x72,
+        0x9d, 0x1b, 0xdf, 0xca, 0x16, 0x1e,
+    ]),
+];
+
+/// A built-in song of any kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Song {
+    Ringtone(&'static str),
+    Midi(&'static str, &'static [u8]),
+    Clip(&'static str, &'static [u8]),
+    Speech(&'static str, &'static [u8]),
+}
+
+impl Song {
+    pub fn name(&self) -> &'static str {
+        match self {
+            // The name is everything before the first colon.
+            Song::Ringtone(src) => src.split(':').next().unwrap(),
+            Song::Midi(name, _) | Song::Clip(name, _) | Song::Speech(name, _) => name,
+        }
+    }
+}
+
+/// Every built-in song: the ringtones, the MIDI files, the
+/// clips, then the words.
+pub fn all() -> impl Iterator<Item = Song> {
+    let ringtones = RINGTONES.iter().map(|&src| Song::Ringtone(src));
+    let midi = MIDI_SONGS.iter().map(|&(name, data)| Song::Midi(name, data));
+    let clips = CLIPS.iter().map(|&(name, data)| Song::Clip(name, data));
+    let words = WORDS.iter().map(|&(name, data)| Song::Speech(name, data));
+    ringtones.chain(midi).chain(clips).chain(words)
+}
+
+/// The built-in song called `name`, ignoring case.
+pub fn find(name: &str) -> Option<Song> {
+    all().find(|song| song.name().eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn songs_found_by_name() {
+        let names: Vec<_> = all().map(|song| song.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "Tetris",
+                "Simpsons",
+                "Entertainer",
+                "Indiana",
+                "TakeOnMe",
+                "Twinkle",
+                "Minuet",
+                "Chime",
+                "Zap",
+                "Gong",
+                "Vowels",
+                "Hi",
+            ]
+        );
+        assert_eq!(find("tetris"), Some(Song::Ringtone(RINGTONES[0])));
+        assert_eq!(find("MINUET"), Some(Song::Midi("Minuet", MIDI_SONGS[1].1)));
+        assert_eq!(find("zap"), Some(Song::Clip("Zap", CLIPS[1].1)));
+        assert_eq!(find("Macarena"), None);
+    }
+}
@@ -0,0 +1,285 @@
+//! Square wave generation.
+
+use crate::{Sample, ToneGenerator};
+
+/// Lowest supported square wave frequency. At this frequency
+/// a full period is 50 000µs, which still fits in a `u16`
+/// microsecond delay.
+pub const MIN_FREQ_HZ: f32 = 20.0;
+
+/// Highest supported square wave frequency.
+pub const MAX_FREQ_HZ: f32 = 20_000.0;
+
+/// Reasons a [SquareWave] cannot be constructed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SquareWaveError {
+    /// Frequency was outside [MIN_FREQ_HZ]..=[MAX_FREQ_HZ].
+    FreqOutOfRange(f32),
+    /// Duty cycle was outside 0.0..=1.0.
+    DutyOutOfRange(f32),
+}
+
+/// Description of a square wave by frequency and duty cycle
+/// (fraction of each period spent high).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SquareWave {
+    freq_hz: f32,
+    duty: f32,
+}
+
+impl SquareWave {
+    /// Square wave of `freq_hz` with the given `duty` cycle.
+    /// Fails if either is out of range (or NaN).
+    pub fn new(freq_hz: f32, duty: f32) -> Result<Self, SquareWaveError> {
+        if !(MIN_FREQ_HZ..=MAX_FREQ_HZ).contains(&freq_hz) {
+            return Err(SquareWaveError::FreqOutOfRange(freq_hz));
+        }
+        if !(0.0..=1.0).contains(&duty) {
+            return Err(SquareWaveError::DutyOutOfRange(duty));
+        }
+        Ok(Self { freq_hz, duty })
+    }
+
+    /// Frequency in Hz, within [MIN_FREQ_HZ]..=[MAX_FREQ_HZ].
+    pub fn freq_hz(&self) -> f32 {
+        self.freq_hz
+    }
+
+    /// Duty cycle: the fraction of each period spent high,
+    /// in 0.0..=1.0.
+    pub fn duty(&self) -> f32 {
+        self.duty
+    }
+
+    /// High and low times of one period, in units of
+    /// `1 / tick_hz` seconds.
+    ///
+    /// The period and the high time are each rounded to the
+    /// nearest tick, and the low time gets the remainder, so
+    /// that the overall frequency error is at most half a
+    /// tick per period regardless of duty cycle.
+    pub fn periods(&self, tick_hz: u32) -> (u32, u32) {
+        let period = tick_hz as f32 / self.freq_hz;
+        let total = round(period);
+        let high = round(period * self.duty).min(total);
+        (high, total - high)
+    }
+
+    /// High and low times of one period in microseconds,
+    /// suitable for `DelayUs<u16>`.
+    pub fn periods_us(&self) -> (u16, u16) {
+        let (high, low) = self.periods(1_000_000);
+        // The frequency range guarantees a period of at most
+        // 50 000µs.
+        (high as u16, low as u16)
+    }
+}
+
+/// Round a non-negative `f32` to the nearest integer, with
+/// halves rounding up. (`f32::round()` is not in `core`.)
+fn round(x: f32) -> u32 {
+    (x + 0.5) as u32
+}
+
+/// Square wave alternating between full positive and full
+/// negative amplitude.
+///
+/// The period is rounded to a whole number of samples, and
+/// the wave is high for the first half of each period.
+pub struct Square {
+    sample_rate: u32,
+    period: u32,
+    high: u32,
+    phase: u32,
+}
+
+impl Square {
+    /// Square wave of `freq_hz` at `sample_rate`. The
+    /// frequency is clamped so that a period is at least two
+    /// samples long.
+    pub fn new(sample_rate: u32, freq_hz: u32) -> Self {
+        let freq_hz = freq_hz.clamp(1, sample_rate / 2);
+        let period = (sample_rate + freq_hz / 2) / freq_hz;
+        Self {
+            sample_rate,
+            period,
+            high: period / 2,
+            phase: 0,
+        }
+    }
+
+    /// Square wave with the frequency and duty cycle of
+    /// `wave` at `sample_rate`. As with [Square::new], the
+    /// period is rounded to a whole number of samples.
+    pub fn from_wave(sample_rate: u32, wave: &SquareWave) -> Self {
+        let (high, low) = wave.periods(sample_rate);
+        let period = (high + low).max(2);
+        Self {
+            sample_rate,
+            period,
+            high: high.min(period),
+            phase: 0,
+        }
+    }
+
+    /// Length of one period in samples.
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+}
+
+impl ToneGenerator for Square {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_sample(&mut self) -> Sample {
+        let sample = if self.phase < self.high {
+            Sample::MAX
+        } else {
+            -Sample::MAX
+        };
+        self.phase += 1;
+        if self.phase == self.period {
+            self.phase = 0;
+        }
+        sample
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_khz_at_two_khz_alternates() {
+        // The original firmware loop: high 500µs, low 500µs.
+        let mut square = Square::new(2_000, 1_000);
+        let samples: Vec<Sample> = square.samples().take(6).collect();
+        assert_eq!(
+            samples,
+            [
+                Sample::MAX,
+                -Sample::MAX,
+                Sample::MAX,
+                -Sample::MAX,
+                Sample::MAX,
+                -Sample::MAX,
+            ],
+        );
+    }
+
+    #[test]
+    fn one_khz_at_one_mhz_has_500_sample_halves() {
+        let mut square = Square::new(1_000_000, 1_000);
+        assert_eq!(square.period(), 1_000);
+        let samples: Vec<Sample> = square.samples().take(2_000).collect();
+        for (i, s) in samples.iter().enumerate() {
+            let high = i % 1_000 < 500;
+            assert_eq!(*s > 0, high, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn period_rounds_to_nearest_sample() {
+        // 8000 / 3000 = 2.67 samples.
+        assert_eq!(Square::new(8_000, 3_000).period(), 3);
+        // 8000 / 3300 = 2.42 samples.
+        assert_eq!(Square::new(8_000, 3_300).period(), 2);
+    }
+
+    #[test]
+    fn frequency_clamped_to_nyquist() {
+        assert_eq!(Square::new(8_000, 100_000).period(), 2);
+        assert_eq!(Square::new(8_000, 0).period(), 8_000);
+    }
+
+    #[test]
+    fn from_wave_applies_duty() {
+        let wave = SquareWave::new(1_000.0, 0.25).unwrap();
+        let mut square = Square::from_wave(8_000, &wave);
+        assert_eq!(square.period(), 8);
+        let high = square.samples().take(8).filter(|&s| s > 0).count();
+        assert_eq!(high, 2);
+    }
+
+    #[test]
+    fn one_khz_half_duty_matches_original_loop() {
+        let wave = SquareWave::new(1_000.0, 0.5).unwrap();
+        assert_eq!(wave.periods_us(), (500, 500));
+    }
+
+    #[test]
+    fn periods_round_to_nearest_microsecond() {
+        // 440Hz: 2272.73µs period, 1136.36µs high.
+        let wave = SquareWave::new(440.0, 0.5).unwrap();
+        assert_eq!(wave.periods_us(), (1_136, 1_137));
+        // 20kHz: 50µs period, 12.5µs high rounds up.
+        let wave = SquareWave::new(20_000.0, 0.25).unwrap();
+        assert_eq!(wave.periods_us(), (13, 37));
+        // 3kHz: 333.33µs period, 233.33µs high.
+        let wave = SquareWave::new(3_000.0, 0.7).unwrap();
+        assert_eq!(wave.periods_us(), (233, 100));
+    }
+
+    #[test]
+    fn periods_at_range_limits_fit_u16() {
+        let wave = SquareWave::new(MIN_FREQ_HZ, 0.5).unwrap();
+        assert_eq!(wave.periods_us(), (25_000, 25_000));
+        let wave = SquareWave::new(MIN_FREQ_HZ, 1.0).unwrap();
+        assert_eq!(wave.periods_us(), (50_000, 0));
+        let wave = SquareWave::new(MAX_FREQ_HZ, 0.0).unwrap();
+        assert_eq!(wave.periods_us(), (0, 50));
+    }
+
+    #[test]
+    fn period_total_tracks_frequency() {
+        for freq in (20..=20_000).step_by(7) {
+            let wave = SquareWave::new(freq as f32, 0.3).unwrap();
+            let (high, low) = wave.periods_us();
+            let period = 1_000_000.0 / freq as f64;
+            let error = (high as f64 + low as f64 - period).abs();
+            assert!(error <= 0.5 + 1e-3, "{}Hz: error {}", freq, error);
+        }
+    }
+
+    #[test]
+    fn out_of_range_requests_rejected() {
+        assert_eq!(
+            SquareWave::new(19.9, 0.5),
+            Err(SquareWaveError::FreqOutOfRange(19.9)),
+        );
+        assert_eq!(
+            SquareWave::new(20_001.0, 0.5),
+            Err(SquareWaveError::FreqOutOfRange(20_001.0)),
+        );
+        assert_eq!(
+            SquareWave::new(1_000.0, -0.1),
+            Err(SquareWaveError::DutyOutOfRange(-0.1)),
+        );
+        assert_eq!(
+            SquareWave::new(1_000.0, 1.5),
+            Err(SquareWaveError::DutyOutOfRange(1.5)),
+        );
+        assert!(matches!(
+            SquareWave::new(f32::NAN, 0.5),
+            Err(SquareWaveError::FreqOutOfRange(_)),
+        ));
+        assert!(matches!(
+            SquareWave::new(1_000.0, f32::NAN),
+            Err(SquareWaveError::DutyOutOfRange(_)),
+        ));
+    }
+
+    #[test]
+    fn reset_restarts_period() {
+        let mut square = Square::new(8_000, 1_000);
+        square.samples().take(5).for_each(drop);
+        square.reset();
+        assert_eq!(square.next_sample(), Sample::MAX);
+    }
+}
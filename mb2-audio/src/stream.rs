@@ -0,0 +1,337 @@
+//! Continuous sample streaming through a double-buffered
+//! PWM sequencer.
+//!
+//! The PWM plays its two sequence buffers alternately,
+//! forever. Each time one finishes, [Streamer::on_seq_end]
+//! refills it from a [SampleSource] while the other one
+//! plays. If the source runs dry, or the refill comes too
+//! late to avoid replaying a stale buffer, an underrun is
+//! counted.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{pwm, Sample, ToneGenerator};
+
+/// A source of samples which may not always have one ready.
+pub trait SampleSource {
+    /// The next sample, or `None` if none is available yet.
+    fn next_sample(&mut self) -> Option<Sample>;
+}
+
+impl<G: ToneGenerator> SampleSource for G {
+    fn next_sample(&mut self) -> Option<Sample> {
+        Some(ToneGenerator::next_sample(self))
+    }
+}
+
+/// One of the two sequence buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seq {
+    Seq0,
+    Seq1,
+}
+
+impl Seq {
+    /// The other sequence buffer.
+    pub fn other(self) -> Self {
+        match self {
+            Seq::Seq0 => Seq::Seq1,
+            Seq::Seq1 => Seq::Seq0,
+        }
+    }
+}
+
+/// A PWM peripheral playing duty values from two sequence
+/// buffers: [Seq::Seq0], then [Seq::Seq1], then
+/// [Seq::Seq0] again, and so on.
+pub trait Sequencer {
+    /// Counter top: all duty values must be less than this.
+    fn countertop(&self) -> u16;
+
+    /// Run `f` on the contents of sequence buffer `seq`,
+    /// which is not currently playing.
+    fn with_buffer<F: FnOnce(&mut [u16])>(&mut self, seq: Seq, f: F);
+
+    /// Start playback from the beginning of [Seq::Seq0].
+    fn start(&mut self);
+
+    /// Stop playback.
+    fn stop(&mut self);
+
+    /// Clear the end event of `seq`, returning whether it
+    /// had occurred.
+    fn take_seq_end(&mut self, seq: Seq) -> bool;
+}
+
+/// Streams samples from a source to a [Sequencer].
+pub struct Streamer<'a, P, S> {
+    pwm: P,
+    source: S,
+    underruns: &'a AtomicU32,
+    next_end: Seq,
+    running: bool,
+}
+
+impl<'a, P: Sequencer, S: SampleSource> Streamer<'a, P, S> {
+    /// Stream from `source` to `pwm`, counting underruns in
+    /// `underruns`. Playback does not begin until
+    /// [Self::start].
+    pub fn new(pwm: P, source: S, underruns: &'a AtomicU32) -> Self {
+        Self {
+            pwm,
+            source,
+            underruns,
+            next_end: Seq::Seq0,
+            running: false,
+        }
+    }
+
+    /// Fill both buffers and start playback.
+    pub fn start(&mut self) {
+        self.pwm.take_seq_end(Seq::Seq0);
+        self.pwm.take_seq_end(Seq::Seq1);
+        self.refill(Seq::Seq0);
+        self.refill(Seq::Seq1);
+        self.next_end = Seq::Seq0;
+        self.pwm.start();
+        self.running = true;
+    }
+
+    /// Stop playback.
+    pub fn stop(&mut self) {
+        self.pwm.stop();
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Handle sequence end events: call this from the
+    /// PWM's interrupt handler.
+    pub fn on_seq_end(&mut self) {
+        let expected = self.pwm.take_seq_end(self.next_end);
+        let other = self.pwm.take_seq_end(self.next_end.other());
+        match (expected, other) {
+            (true, false) => {
+                self.refill(self.next_end);
+                self.next_end = self.next_end.other();
+            }
+            (true, true) => {
+                // Both buffers finished since the last call, so
+                // the hardware has wrapped around and is
+                // replaying `next_end`. Refill the other one,
+                // and wait for the replay to end.
+                self.underruns.fetch_add(1, Ordering::Relaxed);
+                self.refill(self.next_end.other());
+            }
+            (false, true) => {
+                // Out of step with the hardware somehow:
+                // believe the hardware.
+                self.refill(self.next_end.other());
+            }
+            (false, false) => (),
+        }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Give back the sequencer and source.
+    pub fn free(self) -> (P, S) {
+        (self.pwm, self.source)
+    }
+
+    /// Refill `seq` from the source, padding with silence
+    /// (and counting an underrun) if the source runs dry.
+    fn refill(&mut self, seq: Seq) {
+        let top = self.pwm.countertop();
+        let source = &mut self.source;
+        let mut dry = false;
+        self.pwm.with_buffer(seq, |buf| {
+            for d in buf {
+                let sample = source.next_sample().unwrap_or_else(|| {
+                    dry = true;
+                    0
+                });
+                *d = pwm::duty(sample, top);
+            }
+        });
+        if dry {
+            self.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    const TOP: u16 = 1_000;
+    const LEN: usize = 4;
+
+    /// Stand-in for the PWM peripheral. Buffers "play" when
+    /// the test calls [MockPwm::play_one], which records the
+    /// duty values output.
+    struct MockPwm {
+        bufs: [[u16; LEN]; 2],
+        ended: [bool; 2],
+        playing: Option<Seq>,
+        output: Vec<u16>,
+    }
+
+    fn index(seq: Seq) -> usize {
+        match seq {
+            Seq::Seq0 => 0,
+            Seq::Seq1 => 1,
+        }
+    }
+
+    impl MockPwm {
+        fn new() -> Self {
+            Self {
+                bufs: [[0; LEN]; 2],
+                ended: [false; 2],
+                playing: None,
+                output: Vec::new(),
+            }
+        }
+
+        fn play_one(&mut self) {
+            let seq = self.playing.unwrap();
+            self.output.extend_from_slice(&self.bufs[index(seq)]);
+            self.ended[index(seq)] = true;
+            self.playing = Some(seq.other());
+        }
+    }
+
+    impl Sequencer for MockPwm {
+        fn countertop(&self) -> u16 {
+            TOP
+        }
+
+        fn with_buffer<F: FnOnce(&mut [u16])>(&mut self, seq: Seq, f: F) {
+            assert_ne!(self.playing, Some(seq), "wrote playing buffer");
+            f(&mut self.bufs[index(seq)]);
+        }
+
+        fn start(&mut self) {
+            self.playing = Some(Seq::Seq0);
+        }
+
+        fn stop(&mut self) {
+            self.playing = None;
+        }
+
+        fn take_seq_end(&mut self, seq: Seq) -> bool {
+            core::mem::take(&mut self.ended[index(seq)])
+        }
+    }
+
+    /// Source yielding the values queued in it, then
+    /// running dry.
+    struct Queue(VecDeque<Sample>);
+
+    impl SampleSource for Queue {
+        fn next_sample(&mut self) -> Option<Sample> {
+            self.0.pop_front()
+        }
+    }
+
+    fn counting(n: i16) -> Queue {
+        Queue((0..n).map(|i| i * 1_000).collect())
+    }
+
+    fn duties(samples: impl IntoIterator<Item = Sample>) -> Vec<u16> {
+        samples.into_iter().map(|s| pwm::duty(s, TOP)).collect()
+    }
+
+    #[test]
+    fn streams_continuously() {
+        let underruns = AtomicU32::new(0);
+        let mut streamer = Streamer::new(MockPwm::new(), counting(32), &underruns);
+        assert!(!streamer.is_running());
+        streamer.start();
+        assert!(streamer.is_running());
+        for _ in 0..6 {
+            streamer.pwm.play_one();
+            streamer.on_seq_end();
+        }
+        assert_eq!(streamer.pwm.output, duties((0..24).map(|i| i * 1_000)));
+        assert_eq!(underruns.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn dry_source_pads_with_silence() {
+        let underruns = AtomicU32::new(0);
+        let mut streamer = Streamer::new(MockPwm::new(), counting(10), &underruns);
+        streamer.start();
+        for _ in 0..3 {
+            streamer.pwm.play_one();
+            streamer.on_seq_end();
+        }
+        let mut expected = duties((0..10).map(|i| i * 1_000));
+        expected.extend(duties([0, 0]));
+        assert_eq!(streamer.pwm.output, expected);
+        // One for each refill that ran dry.
+        assert_eq!(underruns.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn late_refill_counts_underrun() {
+        let underruns = AtomicU32::new(0);
+        let mut streamer = Streamer::new(MockPwm::new(), counting(32), &underruns);
+        streamer.start();
+        // Both buffers play before the interrupt is serviced.
+        streamer.pwm.play_one();
+        streamer.pwm.play_one();
+        streamer.on_seq_end();
+        assert_eq!(underruns.load(Ordering::Relaxed), 1);
+        // SEQ0 is replaying stale samples; SEQ1 has been
+        // refilled and follows it.
+        streamer.pwm.play_one();
+        streamer.on_seq_end();
+        streamer.pwm.play_one();
+        streamer.on_seq_end();
+        let mut expected = duties((0..8).map(|i| i * 1_000));
+        expected.extend(duties((0..4).map(|i| i * 1_000)));
+        expected.extend(duties((8..12).map(|i| i * 1_000)));
+        assert_eq!(streamer.pwm.output, expected);
+        // Back in step afterwards.
+        streamer.pwm.play_one();
+        streamer.on_seq_end();
+        assert_eq!(streamer.pwm.output[16..], duties((12..16).map(|i| i * 1_000)));
+        assert_eq!(underruns.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn spurious_interrupt_ignored() {
+        let underruns = AtomicU32::new(0);
+        let mut streamer = Streamer::new(MockPwm::new(), counting(16), &underruns);
+        streamer.start();
+        streamer.on_seq_end();
+        streamer.pwm.play_one();
+        streamer.on_seq_end();
+        streamer.pwm.play_one();
+        streamer.on_seq_end();
+        assert_eq!(streamer.pwm.output, duties((0..8).map(|i| i * 1_000)));
+        assert_eq!(underruns.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn tone_generators_are_sources() {
+        let underruns = AtomicU32::new(0);
+        let square = crate::square::Square::new(16_000, 4_000);
+        let mut streamer = Streamer::new(MockPwm::new(), square, &underruns);
+        streamer.start();
+        streamer.pwm.play_one();
+        streamer.on_seq_end();
+        assert_eq!(streamer.pwm.output, [999, 999, 0, 0]);
+    }
+}
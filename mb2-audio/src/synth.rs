@@ -0,0 +1,372 @@
+//! A voice mixer together with whatever is playing on it,
+//! and any sound clip or speech playing alongside.
+
+use crate::{
+    envelope::{Adsr, UNITY},
+    lpc::{self, Speech},
+    mixer::Mixer,
+    osc::Waveform,
+    resample::Resampler,
+    rtttl::{self, Rtttl},
+    smf::{self, Smf},
+    songs::Song,
+    wav::{self, Wav},
+    Sample, ToneGenerator,
+};
+
+/// Mixer key used by [Synth::play_tone]: outside the MIDI
+/// note range, so it never clashes with a melody note.
+pub const TONE_KEY: u8 = 128;
+
+/// A single note of fixed length.
+struct Tone {
+    freq_hz: f32,
+    /// Samples left to play.
+    left: u32,
+    started: bool,
+}
+
+impl Tone {
+    fn tick<const N: usize>(&mut self, mixer: &mut Mixer<N>) -> bool {
+        if !self.started {
+            mixer.note_on(TONE_KEY, self.freq_hz, UNITY / 2);
+            self.started = true;
+        }
+        if self.left == 0 {
+            mixer.note_off(TONE_KEY);
+            return false;
+        }
+        self.left -= 1;
+        true
+    }
+}
+
+/// Something playing on the mixer. There is only ever one,
+/// and no heap to box the larger variant on.
+#[allow(clippy::large_enum_variant)]
+enum Melody<'a> {
+    Tone(Tone),
+    Rtttl(rtttl::Player<'a>),
+    Smf(smf::Player<'a>),
+}
+
+impl Melody<'_> {
+    fn tick<const N: usize>(&mut self, mixer: &mut Mixer<N>) -> bool {
+        match self {
+            Melody::Tone(tone) => tone.tick(mixer),
+            Melody::Rtttl(player) => player.tick(mixer),
+            Melody::Smf(player) => player.tick(mixer),
+        }
+    }
+
+    fn stop<const N: usize>(&mut self, mixer: &mut Mixer<N>) {
+        match self {
+            Melody::Tone(_) => mixer.note_off(TONE_KEY),
+            Melody::Rtttl(player) => player.stop(mixer),
+            Melody::Smf(player) => player.stop(mixer),
+        }
+    }
+}
+
+/// Samples played alongside the mixer.
+#[derive(Debug, Clone)]
+enum ClipSource<'a> {
+    Wav(wav::Samples<'a>),
+    Speech(Speech<'a>),
+}
+
+impl Iterator for ClipSource<'_> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        match self {
+            ClipSource::Wav(samples) => samples.next(),
+            ClipSource::Speech(speech) => speech.next(),
+        }
+    }
+}
+
+/// A WAV clip or speech being played at the mixer's rate.
+type Clip<'a> = Resampler<ClipSource<'a>>;
+
+/// An `N`-voice [Mixer], optionally driven by a melody, or
+/// playing a clip or speech.
+pub struct Synth<'a, const N: usize> {
+    pub mixer: Mixer<N>,
+    melody: Option<Melody<'a>>,
+    clip: Option<Clip<'a>>,
+}
+
+impl<'a, const N: usize> Synth<'a, N> {
+    pub fn new(sample_rate: u32, waveform: Waveform, adsr: Adsr) -> Self {
+        Self {
+            mixer: Mixer::new(sample_rate, waveform, adsr),
+            melody: None,
+            clip: None,
+        }
+    }
+
+    /// Play a tone of `freq_hz` for `duration_ms`, replacing
+    /// any melody already playing.
+    pub fn play_tone(&mut self, freq_hz: f32, duration_ms: u32) {
+        self.stop();
+        let left = (duration_ms as u64 * self.mixer.sample_rate() as u64 / 1_000) as u32;
+        self.melody = Some(Melody::Tone(Tone {
+            freq_hz,
+            left,
+            started: false,
+        }));
+    }
+
+    /// Start playing `rtttl` from the beginning, replacing
+    /// any melody already playing.
+    pub fn play(&mut self, rtttl: &Rtttl<'a>) {
+        self.stop();
+        let player = rtttl::Player::new(rtttl, self.mixer.sample_rate());
+        self.melody = Some(Melody::Rtttl(player));
+    }
+
+    /// Start playing `smf` from the beginning, replacing any
+    /// melody already playing.
+    pub fn play_smf(&mut self, smf: &Smf<'a>) {
+        self.stop();
+        let player = smf::Player::new(smf, self.mixer.sample_rate());
+        self.melody = Some(Melody::Smf(player));
+    }
+
+    /// Start playing `wav` from the beginning, replacing any
+    /// melody or clip already playing. The clip is added to
+    /// the mixer's output, at full scale.
+    pub fn play_clip(&mut self, wav: &Wav<'a>) {
+        self.stop();
+        let rate = self.mixer.sample_rate();
+        let samples = ClipSource::Wav(wav.samples());
+        self.clip = Some(Resampler::new(samples, wav.sample_rate(), rate));
+    }
+
+    /// Start speaking the LPC bitstream `data`, as for
+    /// [Self::play_clip].
+    pub fn say(&mut self, data: &'a [u8]) {
+        self.stop();
+        let rate = self.mixer.sample_rate();
+        let speech = ClipSource::Speech(Speech::new(data));
+        self.clip = Some(Resampler::new(speech, lpc::SAMPLE_RATE, rate));
+    }
+
+    /// Start playing a built-in song. Returns `false`, playing
+    /// nothing, if it fails to parse.
+    pub fn play_song(&mut self, song: Song) -> bool {
+        match song {
+            Song::Ringtone(src) => match Rtttl::parse(src) {
+                Ok(rtttl) => self.play(&rtttl),
+                Err(_) => return false,
+            },
+            Song::Midi(_, data) => match Smf::parse(data) {
+                Ok(smf) => self.play_smf(&smf),
+                Err(_) => return false,
+            },
+            Song::Clip(_, data) => match Wav::parse(data) {
+                Ok(wav) => self.play_clip(&wav),
+                Err(_) => return false,
+            },
+            // Any bits are speech of some sort.
+            Song::Speech(_, data) => self.say(data),
+        }
+        true
+    }
+
+    /// Stop the melody, if any, letting its notes release,
+    /// and cut off the clip or speech.
+    pub fn stop(&mut self) {
+        if let Some(mut melody) = self.melody.take() {
+            melody.stop(&mut self.mixer);
+        }
+        self.clip = None;
+    }
+
+    /// True while a melody, clip or speech is playing.
+    pub fn is_playing(&self) -> bool {
+        self.melody.is_some() || self.clip.is_some()
+    }
+
+    /// True while anything is making sound.
+    pub fn is_active(&self) -> bool {
+        self.is_playing() || self.mixer.is_active()
+    }
+}
+
+impl<const N: usize> ToneGenerator for Synth<'_, N> {
+    fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate()
+    }
+
+    fn next_sample(&mut self) -> Sample {
+        if let Some(melody) = &mut self.melody {
+            if !melody.tick(&mut self.mixer) {
+                // Release any notes the melody left held.
+                melody.stop(&mut self.mixer);
+                self.melody = None;
+            }
+        }
+        let sample = self.mixer.next_sample();
+        let Some(clip) = &mut self.clip else {
+            return sample;
+        };
+        match clip.next() {
+            Some(clip_sample) => sample.saturating_add(clip_sample),
+            None => {
+                self.clip = None;
+                sample
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.mixer.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::songs;
+
+    const ADSR: Adsr = Adsr {
+        attack_ms: 5,
+        decay_ms: 50,
+        sustain: UNITY / 2,
+        release_ms: 50,
+    };
+
+    #[test]
+    fn melody_plays_to_completion() {
+        let mut synth: Synth<2> = Synth::new(8_000, Waveform::Sine, ADSR);
+        assert!(!synth.is_active());
+        let rtttl = Rtttl::parse("x:d=8,o=5,b=120:c,e,g").unwrap();
+        synth.play(&rtttl);
+        assert!(synth.is_playing());
+        // 3 x 250ms, plus the last release.
+        let samples: Vec<Sample> = synth.samples().take(8_000).collect();
+        assert!(samples[..6_000].iter().any(|&s| s != 0));
+        assert!(!synth.is_playing());
+        assert!(!synth.is_active());
+        assert!(samples[7_000..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn stop_releases_note() {
+        let mut synth: Synth<2> = Synth::new(8_000, Waveform::Sine, ADSR);
+        let rtttl = Rtttl::parse("x:d=1,o=5,b=60:c").unwrap();
+        synth.play(&rtttl);
+        synth.samples().take(100).for_each(drop);
+        synth.stop();
+        assert!(!synth.is_playing());
+        assert!(synth.is_active());
+        synth.samples().take(400).for_each(drop);
+        assert!(!synth.is_active());
+    }
+
+    #[test]
+    fn plays_midi() {
+        let mut synth: Synth<4> = Synth::new(8_000, Waveform::Sine, ADSR);
+        assert!(synth.play_song(songs::find("twinkle").unwrap()));
+        synth.samples().take(100).for_each(drop);
+        assert!(synth.is_playing());
+        assert_eq!(synth.mixer.voice_key(0), Some(60));
+        // The song lasts 8.125s.
+        synth.samples().take(65_000).for_each(drop);
+        assert!(!synth.is_playing());
+    }
+
+    #[test]
+    fn midi_releases_notes_held_at_end() {
+        // A note on and no note off, then the end of the
+        // track, or an event that can't be parsed.
+        let files: [&[u8]; 2] = [
+            b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk\0\0\0\x08\0\x90\x3c\x40\0\xff\x2f\0",
+            b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk\0\0\0\x06\0\x90\x3c\x40\0\xf8",
+        ];
+        for data in files {
+            let mut synth: Synth<2> = Synth::new(8_000, Waveform::Sine, ADSR);
+            synth.play_smf(&Smf::parse(data).unwrap());
+            synth.samples().take(100).for_each(drop);
+            assert!(!synth.is_playing());
+            assert_eq!(synth.mixer.voice_key(0), None);
+            // The 50ms release.
+            synth.samples().take(400).for_each(drop);
+            assert!(!synth.is_active());
+        }
+    }
+
+    #[test]
+    fn every_song_plays() {
+        let mut synth: Synth<4> = Synth::new(8_000, Waveform::Sine, ADSR);
+        for song in songs::all() {
+            assert!(synth.play_song(song), "{}", song.name());
+        }
+    }
+
+    #[test]
+    fn clip_plays_to_completion() {
+        let mut synth: Synth<2> = Synth::new(16_000, Waveform::Sine, ADSR);
+        assert!(synth.play_song(songs::find("chime").unwrap()));
+        assert!(synth.is_playing() && synth.is_active());
+        // 600ms at 8kHz, played at 16kHz.
+        let samples: Vec<Sample> = synth.samples().take(9_600).collect();
+        assert!(samples[..1_000].iter().any(|&s| s.unsigned_abs() > 10_000));
+        assert!(synth.is_playing());
+        synth.samples().take(2).for_each(drop);
+        assert!(!synth.is_playing() && !synth.is_active());
+        // Stopping cuts it off.
+        assert!(synth.play_song(songs::find("zap").unwrap()));
+        synth.stop();
+        assert!(!synth.is_playing());
+        assert_eq!(ToneGenerator::next_sample(&mut synth), 0);
+    }
+
+    #[test]
+    fn clips_mix_with_notes() {
+        let mut synth: Synth<2> = Synth::new(8_000, Waveform::Square, ADSR);
+        let mut alone: Synth<2> = Synth::new(8_000, Waveform::Square, ADSR);
+        let wav = Wav::parse(songs::CLIPS[0].1).unwrap();
+        synth.play_clip(&wav);
+        synth.mixer.note_on(1, 100.0, UNITY / 4);
+        alone.mixer.note_on(1, 100.0, UNITY / 4);
+        let clip: Vec<Sample> = wav.samples().take(1_000).collect();
+        for &expected in &clip {
+            let mixed = ToneGenerator::next_sample(&mut synth);
+            let note = ToneGenerator::next_sample(&mut alone);
+            assert_eq!(mixed, note.saturating_add(expected));
+        }
+    }
+
+    #[test]
+    fn speech_plays_to_completion() {
+        let mut synth: Synth<2> = Synth::new(16_000, Waveform::Sine, ADSR);
+        assert!(synth.play_song(songs::find("hi").unwrap()));
+        let speech: Vec<Sample> = Speech::new(songs::WORDS[1].1).collect();
+        // At twice the rate.
+        let samples: Vec<Sample> = synth.samples().take(2 * speech.len() - 1).collect();
+        assert!(synth.is_playing());
+        assert_eq!(samples[2 * 1_000], speech[1_000]);
+        synth.samples().take(2).for_each(drop);
+        assert!(!synth.is_playing() && !synth.is_active());
+    }
+
+    #[test]
+    fn tone_lasts_duration() {
+        let mut synth: Synth<2> = Synth::new(8_000, Waveform::Sine, ADSR);
+        synth.play_tone(440.0, 100);
+        synth.samples().take(799).for_each(drop);
+        assert_eq!(synth.mixer.voice_key(0), Some(TONE_KEY));
+        assert_eq!(synth.mixer.voice(0).tone.freq_hz(), 440.0);
+        synth.samples().take(2).for_each(drop);
+        assert_eq!(synth.mixer.voice_key(0), None);
+        assert!(!synth.is_playing());
+        // Stopping early releases the tone.
+        synth.play_tone(440.0, 1_000);
+        synth.samples().take(10).for_each(drop);
+        synth.stop();
+        assert_eq!(synth.mixer.voice_key(0), None);
+    }
+}
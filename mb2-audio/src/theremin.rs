@@ -0,0 +1,215 @@
+//! Tilt-controlled theremin.
+//!
+//! The board is played by tilting it, as read by an
+//! accelerometer. Tilting it from side to side (the X axis)
+//! sweeps the pitch over [RANGE_SEMITONES], left low and
+//! right high, and tilting it forwards (the Y axis) turns
+//! it up: level, it is silent. The pitch can be kept to the
+//! notes of a [Scale]. Both follow the tilt through a
+//! one-pole smoother, so the pitch glides from one note to
+//! the next rather than stepping, and the level changes
+//! without clicks.
+
+use crate::{
+    envelope::UNITY,
+    tuning::{self, Scale},
+};
+
+/// Mixer key for the theremin's note: outside the MIDI
+/// note range, and not [TONE_KEY](crate::synth::TONE_KEY).
+pub const THEREMIN_KEY: u8 = 129;
+
+/// MIDI note number of the lowest pitch: C3.
+pub const LOW_NOTE: f32 = 48.0;
+
+/// Range of pitches, from [LOW_NOTE].
+pub const RANGE_SEMITONES: f32 = 36.0;
+
+/// Tilt, in thousandths of gravity, for the end of each
+/// range: about 45 degrees.
+pub const MAX_TILT_MG: i32 = 700;
+
+/// Time constant of the pitch glide.
+pub const GLIDE_MS: u32 = 80;
+
+/// Time constant of the volume smoothing.
+pub const VOLUME_MS: u32 = 30;
+
+/// Gain at full tilt.
+pub const MAX_GAIN: u16 = UNITY / 2;
+
+/// Whether the theremin plays, and how its pitch is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    /// Any pitch.
+    Free,
+    /// Only notes of a scale on C.
+    Scale(Scale),
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Off => "off",
+            Mode::Free => "free",
+            Mode::Scale(scale) => scale.name(),
+        }
+    }
+
+    /// The mode called `name`, ignoring case: `off`, `free`
+    /// or the name of a scale.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Mode::Off, Mode::Free]
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+            .or_else(|| Scale::from_name(name).map(Mode::Scale))
+    }
+}
+
+/// Move `value` towards `target` by a one-pole smoother with
+/// time constant `tau_ms`, over `elapsed_ms`.
+fn smooth(value: f32, target: f32, elapsed_ms: u32, tau_ms: u32) -> f32 {
+    let k = elapsed_ms as f32 / (tau_ms + elapsed_ms) as f32;
+    value + (target - value) * k
+}
+
+/// Fraction of the way from level to [MAX_TILT_MG], from -1
+/// to 1.
+fn tilt(mg: i32) -> f32 {
+    (mg as f32 / MAX_TILT_MG as f32).clamp(-1.0, 1.0)
+}
+
+/// Turns tilt into pitch and volume.
+#[derive(Debug, Clone)]
+pub struct Theremin {
+    scale: Option<Scale>,
+    /// Fractional MIDI note number, once there is one.
+    note: Option<f32>,
+    gain: f32,
+}
+
+impl Default for Theremin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Theremin {
+    pub const fn new() -> Self {
+        Self {
+            scale: None,
+            note: None,
+            gain: 0.0,
+        }
+    }
+
+    /// Keep to the notes of `scale`, or play any pitch.
+    pub fn set_scale(&mut self, scale: Option<Scale>) {
+        self.scale = scale;
+    }
+
+    /// Start again from silence: the next pitch is taken as
+    /// it is, without a glide.
+    pub fn reset(&mut self) {
+        self.note = None;
+        self.gain = 0.0;
+    }
+
+    /// Take the acceleration along X and Y in thousandths of
+    /// gravity, `elapsed_ms` after the last call. Returns
+    /// the frequency to play in Hz and its Q15 gain.
+    pub fn update(&mut self, x_mg: i32, y_mg: i32, elapsed_ms: u32) -> (f32, u16) {
+        let mut target = LOW_NOTE + (tilt(x_mg) + 1.0) / 2.0 * RANGE_SEMITONES;
+        if let Some(scale) = self.scale {
+            target = scale.quantize(0, target) as f32;
+        }
+        let note = match self.note {
+            Some(note) => smooth(note, target, elapsed_ms, GLIDE_MS),
+            None => target,
+        };
+        self.note = Some(note);
+        let volume = tilt(y_mg).max(0.0);
+        self.gain = smooth(self.gain, volume, elapsed_ms, VOLUME_MS);
+        let freq = tuning::A4_HZ * tuning::semitone_ratio(note - tuning::A4_NOTE as f32);
+        (freq, (self.gain * MAX_GAIN as f32) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The note and gain after holding a tilt for `ms`.
+    fn hold(theremin: &mut Theremin, x_mg: i32, y_mg: i32, ms: u32) -> (f32, u16) {
+        let mut out = (0.0, 0);
+        for _ in 0..ms / 10 {
+            out = theremin.update(x_mg, y_mg, 10);
+        }
+        (tuning::freq_to_midi(out.0), out.1)
+    }
+
+    #[test]
+    fn tilt_sets_pitch() {
+        let mut theremin = Theremin::new();
+        let (note, _) = hold(&mut theremin, 0, 0, 10);
+        assert!((note - (LOW_NOTE + RANGE_SEMITONES / 2.0)).abs() < 0.01);
+        let (note, _) = hold(&mut theremin, -MAX_TILT_MG, 0, 1_000);
+        assert!((note - LOW_NOTE).abs() < 0.01);
+        // Tilting past the end changes nothing.
+        let (note, _) = hold(&mut theremin, 1_000, 0, 1_000);
+        assert!((note - (LOW_NOTE + RANGE_SEMITONES)).abs() < 0.01);
+    }
+
+    #[test]
+    fn tilt_sets_volume() {
+        let mut theremin = Theremin::new();
+        assert_eq!(hold(&mut theremin, 0, 0, 500).1, 0);
+        assert_eq!(hold(&mut theremin, 0, -MAX_TILT_MG, 500).1, 0);
+        let full = hold(&mut theremin, 0, MAX_TILT_MG, 500).1;
+        assert!(full > MAX_GAIN - 10 && full <= MAX_GAIN);
+        let half = hold(&mut theremin, 0, MAX_TILT_MG / 2, 500).1;
+        assert!(half.abs_diff(MAX_GAIN / 2) < 10);
+    }
+
+    #[test]
+    fn pitch_glides() {
+        let mut theremin = Theremin::new();
+        theremin.update(-MAX_TILT_MG, 0, 10);
+        // A sudden tilt: the pitch rises smoothly, with no
+        // step of more than a few semitones.
+        let mut last = LOW_NOTE;
+        for _ in 0..50 {
+            let (freq, _) = theremin.update(MAX_TILT_MG, 0, 10);
+            let note = tuning::freq_to_midi(freq);
+            assert!(note > last && note - last < 5.0, "{} to {}", last, note);
+            last = note;
+        }
+        assert!((last - (LOW_NOTE + RANGE_SEMITONES)).abs() < 0.1);
+        // Until reset.
+        theremin.reset();
+        let (freq, gain) = theremin.update(-MAX_TILT_MG, MAX_TILT_MG, 10);
+        assert!((tuning::freq_to_midi(freq) - LOW_NOTE).abs() < 0.01);
+        assert!(gain < MAX_GAIN / 2);
+    }
+
+    #[test]
+    fn pitch_keeps_to_scale() {
+        let mut theremin = Theremin::new();
+        theremin.set_scale(Some(Scale::Major));
+        for mg in (-MAX_TILT_MG..=MAX_TILT_MG).step_by(35) {
+            let (note, _) = hold(&mut theremin, mg, 0, 2_000);
+            let nearest = (note + 0.5) as u8;
+            assert!((note - nearest as f32).abs() < 0.01, "{}mg: {}", mg, note);
+            assert!(![1, 3, 6, 8, 10].contains(&(nearest % 12)), "{}mg: {}", mg, note);
+        }
+    }
+
+    #[test]
+    fn modes_by_name() {
+        assert_eq!(Mode::from_name("off"), Some(Mode::Off));
+        assert_eq!(Mode::from_name("Free"), Some(Mode::Free));
+        assert_eq!(Mode::from_name("major"), Some(Mode::Scale(Scale::Major)));
+        assert_eq!(Mode::from_name("wobble"), None);
+    }
+}
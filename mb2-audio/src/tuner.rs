@@ -0,0 +1,316 @@
+//! Chromatic tuner.
+//!
+//! [Yin] finds the pitch of a block of samples with the YIN
+//! method (de Cheveigné and Kawahara, 2002): the lag at
+//! which the signal best matches a delayed copy of itself is
+//! its period. A [Reading] turns the pitch into the nearest
+//! note and how far off it is, and [frame] shows that on the
+//! 5×5 display: the note letter on the left, a dot at the
+//! top of the fourth column for a sharp note name, and a
+//! needle in the last column that rises when the pitch is
+//! sharp and falls when it is flat.
+
+use crate::{
+    meter::{Frame, MAX_BRIGHTNESS},
+    tuning, Sample,
+};
+
+/// Lowest pitch looked for: below a bass guitar's low E.
+pub const MIN_FREQ_HZ: f32 = 40.0;
+
+/// Highest pitch looked for: above a ukulele's top fret.
+pub const MAX_FREQ_HZ: f32 = 1_200.0;
+
+/// Dips in the normalized difference below this count as a
+/// period. Lower is stricter.
+pub const THRESHOLD: f32 = 0.15;
+
+/// Blocks with a peak below this are taken as silence.
+pub const MIN_LEVEL: Sample = 500;
+
+/// Readings within this many cents show as in tune.
+pub const IN_TUNE_CENTS: f32 = 5.0;
+
+/// YIN pitch detector.
+pub struct Yin {
+    sample_rate: u32,
+    min_lag: usize,
+    max_lag: usize,
+}
+
+impl Yin {
+    /// Detect pitches from `min_hz` to `max_hz` at
+    /// `sample_rate`.
+    pub fn new(sample_rate: u32, min_hz: f32, max_hz: f32) -> Self {
+        let rate = sample_rate as f32;
+        Self {
+            sample_rate,
+            min_lag: ((rate / max_hz) as usize).max(2),
+            max_lag: (rate / min_hz) as usize + 1,
+        }
+    }
+
+    /// Fewest samples [Self::detect] needs: two periods of
+    /// the lowest pitch.
+    pub fn min_len(&self) -> usize {
+        2 * self.max_lag
+    }
+
+    /// The pitch of `samples` in Hz, or `None` if they are
+    /// too quiet, too few or have no clear pitch.
+    pub fn detect(&self, samples: &[Sample]) -> Option<f32> {
+        if samples.len() < self.min_len() {
+            return None;
+        }
+        let peak = samples.iter().map(|s| s.unsigned_abs()).max()?;
+        if peak < MIN_LEVEL as u16 {
+            return None;
+        }
+        let window = samples.len() - self.max_lag;
+        // Squared difference from the copy delayed by `lag`.
+        let difference = |lag: usize| -> f32 {
+            samples[..window]
+                .iter()
+                .zip(&samples[lag..])
+                .map(|(&a, &b)| {
+                    let d = (a as i32 - b as i32) as f32;
+                    d * d
+                })
+                .sum()
+        };
+        // The difference normalized by its mean over the
+        // smaller lags, so that it starts at 1 and only dips
+        // well below at the period.
+        let mut sum = 0.0;
+        let mut last = [0.0; 3];
+        let mut previous = 1.0;
+        let mut dipping = false;
+        for lag in 1..=self.max_lag {
+            let d = difference(lag);
+            sum += d;
+            let normalized = if sum > 0.0 { d * lag as f32 / sum } else { 1.0 };
+            last = [last[1], last[2], d];
+            if dipping && normalized >= previous {
+                // The bottom was at the lag before. The plain
+                // difference is the less skewed shape to fit.
+                return Some(self.sample_rate as f32 / refine(lag - 1, last));
+            }
+            previous = normalized;
+            dipping = dipping || (lag >= self.min_lag && normalized < THRESHOLD);
+        }
+        None
+    }
+}
+
+/// Fit a parabola through the values around the minimum at
+/// `lag` to find the fractional lag of its bottom.
+fn refine(lag: usize, [before, at, after]: [f32; 3]) -> f32 {
+    let curve = before - 2.0 * at + after;
+    if curve <= 0.0 {
+        return lag as f32;
+    }
+    lag as f32 + (before - after) / (2.0 * curve)
+}
+
+/// The note nearest a pitch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    /// MIDI note number.
+    pub note: u8,
+    /// How far the pitch is from the note, from -50 to 50.
+    pub cents: f32,
+}
+
+impl Reading {
+    /// The note nearest `freq_hz`, if it has a MIDI number.
+    pub fn from_freq(freq_hz: f32) -> Option<Self> {
+        let midi = tuning::freq_to_midi(freq_hz);
+        let note = (midi + 0.5) as i32;
+        if !(0.0..=127.0).contains(&midi) || !(0..=127).contains(&note) {
+            return None;
+        }
+        Some(Self {
+            note: note as u8,
+            cents: (midi - note as f32) * 100.0,
+        })
+    }
+
+    pub fn is_in_tune(&self) -> bool {
+        self.cents.abs() < IN_TUNE_CENTS
+    }
+
+    /// Exact frequency of the note.
+    pub fn reference_hz(&self) -> f32 {
+        tuning::midi_to_freq(self.note)
+    }
+}
+
+/// Letters A to G, three columns by five rows, with the
+/// most significant of the three bits on the left.
+const LETTERS: [[u8; 5]; 7] = [
+    [0b010, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b011, 0b100, 0b100, 0b100, 0b011],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b110, 0b100, 0b111],
+    [0b111, 0b100, 0b110, 0b100, 0b100],
+    [0b011, 0b100, 0b101, 0b101, 0b011],
+];
+
+/// Brightness of the letter of a note that is not in tune.
+const DIM: u8 = 4;
+
+/// The display for `reading`: blank if there is none.
+pub fn frame(reading: Option<Reading>) -> Frame {
+    let mut frame = [[0; 5]; 5];
+    let Some(reading) = reading else {
+        return frame;
+    };
+    let name = tuning::note_name(reading.note).as_bytes();
+    let brightness = if reading.is_in_tune() { MAX_BRIGHTNESS } else { DIM };
+    let glyph = &LETTERS[(name[0] - b'A') as usize];
+    for (row, bits) in frame.iter_mut().zip(glyph) {
+        for (col, led) in row[..3].iter_mut().enumerate() {
+            if bits & (0b100 >> col) != 0 {
+                *led = brightness;
+            }
+        }
+    }
+    if name.len() > 1 {
+        frame[0][3] = brightness;
+    }
+    // Each row of the needle is 20 cents, the middle one in
+    // tune.
+    let row = (2.0 - reading.cents / 20.0 + 0.5).clamp(0.0, 4.0) as usize;
+    frame[row][4] = MAX_BRIGHTNESS;
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{osc::Oscillator, osc::Waveform, ToneGenerator};
+    use std::f64::consts::PI;
+
+    fn tone(waveform: Waveform, freq: f32, len: usize) -> Vec<Sample> {
+        let mut osc = Oscillator::new(16_000, waveform, freq);
+        osc.samples().take(len).map(|s| s / 2).collect()
+    }
+
+    /// A plucked-string-like tone: `harmonics` harmonics,
+    /// falling off in level.
+    fn string(freq: f32, harmonics: u32, len: usize) -> Vec<Sample> {
+        (0..len)
+            .map(|i| {
+                let t = i as f64 / 16_000.0;
+                let y: f64 = (1..=harmonics)
+                    .map(|h| (2.0 * PI * (h as f64 * freq as f64) * t).sin() / h as f64)
+                    .sum();
+                (y * 8_000.0) as Sample
+            })
+            .collect()
+    }
+
+    fn yin() -> Yin {
+        Yin::new(16_000, MIN_FREQ_HZ, MAX_FREQ_HZ)
+    }
+
+    #[test]
+    fn finds_pitches() {
+        let yin = yin();
+        // Guitar strings, ukulele strings, and high E at the
+        // 12th fret.
+        for freq in [82.41, 110.0, 146.83, 196.0, 246.94, 329.63, 261.63, 392.0, 440.0, 659.26] {
+            let sine = tone(Waveform::Sine, freq, yin.min_len());
+            for samples in [sine, string(freq, 1, yin.min_len()), string(freq, 6, yin.min_len())] {
+                let found = yin.detect(&samples).unwrap();
+                let cents = 1_200.0 * tuning::log2(found / freq);
+                assert!(cents.abs() < 1.0, "{}Hz: {}Hz", freq, found);
+            }
+        }
+        // Higher up, the fit to the dip is rougher, but good
+        // enough to name the note.
+        let samples = string(1_046.5, 6, yin.min_len());
+        let reading = Reading::from_freq(yin.detect(&samples).unwrap()).unwrap();
+        assert_eq!(reading.note, 84);
+        assert!(reading.cents.abs() < 10.0);
+    }
+
+    #[test]
+    fn no_pitch() {
+        let yin = yin();
+        assert_eq!(yin.detect(&vec![0; yin.min_len()]), None);
+        // Too quiet.
+        let quiet: Vec<_> = tone(Waveform::Sine, 440.0, yin.min_len())
+            .iter()
+            .map(|s| s / 50)
+            .collect();
+        assert_eq!(yin.detect(&quiet), None);
+        // Too short.
+        assert_eq!(yin.detect(&tone(Waveform::Sine, 440.0, yin.min_len() - 1)), None);
+        // Noise.
+        let mut x = 1u32;
+        let noise: Vec<_> = (0..yin.min_len())
+            .map(|_| {
+                x = x.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (x >> 17) as Sample
+            })
+            .collect();
+        assert_eq!(yin.detect(&noise), None);
+    }
+
+    #[test]
+    fn readings() {
+        let a = Reading::from_freq(440.0).unwrap();
+        assert_eq!(a.note, 69);
+        assert!(a.cents.abs() < 0.01 && a.is_in_tune());
+        // 20 cents sharp of E2, and 30 flat of C4.
+        let e = Reading::from_freq(82.41 * 1.011_619).unwrap();
+        assert_eq!(e.note, 40);
+        assert!((e.cents - 20.0).abs() < 0.1 && !e.is_in_tune());
+        let c = Reading::from_freq(261.63 / 1.017_478).unwrap();
+        assert_eq!(c.note, 60);
+        assert!((c.cents + 30.0).abs() < 0.1);
+        assert_eq!(c.reference_hz(), tuning::midi_to_freq(60));
+        assert_eq!(Reading::from_freq(5.0), None);
+        assert_eq!(Reading::from_freq(20_000.0), None);
+    }
+
+    #[test]
+    fn frames() {
+        assert_eq!(frame(None), [[0; 5]; 5]);
+        let a = Reading {
+            note: 69,
+            cents: 1.0,
+        };
+        assert_eq!(
+            frame(Some(a)),
+            [
+                [0, 9, 0, 0, 0],
+                [9, 0, 9, 0, 0],
+                [9, 9, 9, 0, 9],
+                [9, 0, 9, 0, 0],
+                [9, 0, 9, 0, 0],
+            ]
+        );
+        let c_sharp = Reading {
+            note: 61,
+            cents: -45.0,
+        };
+        assert_eq!(
+            frame(Some(c_sharp)),
+            [
+                [0, 4, 4, 4, 0],
+                [4, 0, 0, 0, 0],
+                [4, 0, 0, 0, 0],
+                [4, 0, 0, 0, 0],
+                [0, 4, 4, 0, 9],
+            ]
+        );
+        let sharp = Reading {
+            note: 67,
+            cents: 25.0,
+        };
+        assert_eq!(frame(Some(sharp))[1][4], 9);
+    }
+}
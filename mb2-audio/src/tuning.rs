@@ -0,0 +1,669 @@
+//! Note numbers, frequencies and tunings.
+//!
+//! The free functions here work in twelve-tone equal
+//! temperament with A4 at 440Hz, which is all the built-in
+//! songs and MIDI need. A [Tuning] can be anything else:
+//! equal temperament with another A4, just intonation, or a
+//! scale read from a Scala `.scl` file. A [Scale] picks
+//! which notes of the twelve to keep to.
+//!
+//! A Scala file is plain text. Lines starting with `!` are
+//! comments; the first other line describes the scale, the
+//! next gives the number of notes, and then each note has a
+//! line of its own, as cents if it has a `.` and otherwise
+//! as a ratio to the tonic such as `3/2` or `2`:
+//!
+//! ```text
+//! ! pythagorean.scl
+//! Pythagorean pentatonic
+//!  5
+//! 9/8
+//! 81/64
+//! 3/2
+//! 27/16
+//! 2/1
+//! ```
+//!
+//! The tonic itself is left out, and the last note is the
+//! period the scale repeats at, usually the octave.
+
+/// MIDI note number of A4.
+pub const A4_NOTE: u8 = 69;
+
+/// Frequency of A4 in Hz.
+pub const A4_HZ: f32 = 440.0;
+
+/// Names of the notes of each octave, from C.
+pub const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// `2**(k/12)` for `k` in `0..12`.
+const SEMITONE_RATIOS: [f32; 12] = [
+    1.0,
+    1.059_463_1,
+    1.122_462,
+    1.189_207_1,
+    1.259_921,
+    1.334_839_9,
+    core::f32::consts::SQRT_2,
+    1.498_307,
+    1.587_401_1,
+    1.681_792_8,
+    1.781_797_4,
+    1.887_748_6,
+];
+
+/// Twelve-tone equal temperament frequency of MIDI note
+/// number `note`, with A4 at 440Hz.
+pub fn midi_to_freq(note: u8) -> f32 {
+    A4_HZ * interval_ratio(note as i32 - A4_NOTE as i32)
+}
+
+/// `2**(semitones/12)`, exactly for whole octaves.
+fn interval_ratio(semitones: i32) -> f32 {
+    let mut ratio = SEMITONE_RATIOS[semitones.rem_euclid(12) as usize];
+    let octaves = semitones.div_euclid(12);
+    for _ in 0..octaves.abs() {
+        if octaves > 0 {
+            ratio *= 2.0;
+        } else {
+            ratio /= 2.0;
+        }
+    }
+    ratio
+}
+
+/// Frequency ratio of an interval of `semitones`, which
+/// need not be whole: `2**(semitones/12)`.
+pub fn semitone_ratio(semitones: f32) -> f32 {
+    let whole = floor(semitones);
+    // Less than a semitone is left: a short series for exp
+    // is plenty.
+    let y = (semitones - whole as f32) * (core::f32::consts::LN_2 / 12.0);
+    interval_ratio(whole) * (1.0 + y * (1.0 + y * (0.5 + y / 6.0)))
+}
+
+/// The largest whole number no larger than `x`. (There is
+/// no `floor()` in `core`.)
+fn floor(x: f32) -> i32 {
+    let whole = x as i32;
+    if whole as f32 > x {
+        whole - 1
+    } else {
+        whole
+    }
+}
+
+/// `log2(x)` for positive `x`, good to about 1e-6. (There
+/// is no `log2()` in `core`.)
+pub fn log2(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    // Mantissa in 1..2, and ln(m) = 2 atanh((m - 1)/(m + 1))
+    // by its series: s is at most 1/3, so it converges fast.
+    let m = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let ln = 2.0 * s * (1.0 + s2 * (1.0 / 3.0 + s2 * (0.2 + s2 * (1.0 / 7.0 + s2 / 9.0))));
+    exponent as f32 + ln * core::f32::consts::LOG2_E
+}
+
+/// Fractional MIDI note number of `freq_hz` in twelve-tone
+/// equal temperament, with A4 at 440Hz.
+pub fn freq_to_midi(freq_hz: f32) -> f32 {
+    A4_NOTE as f32 + 12.0 * log2(freq_hz / A4_HZ)
+}
+
+/// Name of MIDI note number `note`, without its octave.
+pub fn note_name(note: u8) -> &'static str {
+    NOTE_NAMES[note as usize % 12]
+}
+
+/// A scale to keep notes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    /// Natural minor.
+    Minor,
+    /// Major pentatonic.
+    Pentatonic,
+}
+
+impl Scale {
+    pub fn name(self) -> &'static str {
+        match self {
+            Scale::Chromatic => "chromatic",
+            Scale::Major => "major",
+            Scale::Minor => "minor",
+            Scale::Pentatonic => "pentatonic",
+        }
+    }
+
+    /// The scale called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Scale::Chromatic, Scale::Major, Scale::Minor, Scale::Pentatonic]
+            .into_iter()
+            .find(|scale| scale.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Semitones above the tonic of each degree.
+    fn degrees(self) -> &'static [u8] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Pentatonic => &[0, 2, 4, 7, 9],
+        }
+    }
+
+    /// The note of the scale on `tonic` nearest fractional
+    /// MIDI note number `note`. Only the tonic's place in the
+    /// octave matters: 0 for C, 9 for A. The result is kept
+    /// within the MIDI range, even if that leaves the scale.
+    pub fn quantize(self, tonic: u8, note: f32) -> u8 {
+        let tonic = (tonic % 12) as i32;
+        let octave = floor((note - tonic as f32) / 12.0);
+        let base = tonic + 12 * octave;
+        let within = note - base as f32;
+        // The tonic an octave up is a candidate too.
+        let degree = self
+            .degrees()
+            .iter()
+            .map(|&d| d as i32)
+            .chain([12])
+            .min_by(|&a, &b| {
+                let (a, b) = ((within - a as f32).abs(), (within - b as f32).abs());
+                a.partial_cmp(&b).unwrap()
+            })
+            .unwrap();
+        (base + degree).clamp(0, 127) as u8
+    }
+
+    /// `freq_hz` moved to the nearest note of the scale on
+    /// `tonic`, as tuned by twelve-note `tuning`.
+    pub fn quantize_freq(self, tuning: &Tuning, tonic: u8, freq_hz: f32) -> f32 {
+        let near = tuning.nearest(freq_hz) as i32;
+        let in_scale = |note: i32| {
+            let degree = (note - tonic as i32).rem_euclid(12) as u8;
+            self.degrees().contains(&degree)
+        };
+        let distance = |note: i32| ratio_cents(freq_hz / tuning.freq(note as u8)).abs();
+        // No scale here leaves a gap wider than a fifth.
+        let note = (near - 6..=near + 6)
+            .filter(|&note| (0..=127).contains(&note) && in_scale(note))
+            .min_by(|&a, &b| distance(a).partial_cmp(&distance(b)).unwrap())
+            .unwrap_or(near);
+        tuning.freq(note as u8)
+    }
+}
+
+/// Most notes in each period of a [Tuning].
+pub const MAX_DEGREES: usize = 128;
+
+/// Five-limit just intonation of the chromatic scale, as
+/// ratios to the tonic.
+const JUST_RATIOS: [(u32, u32); 12] = [
+    (1, 1),
+    (16, 15),
+    (9, 8),
+    (6, 5),
+    (5, 4),
+    (4, 3),
+    (45, 32),
+    (3, 2),
+    (8, 5),
+    (5, 3),
+    (9, 5),
+    (15, 8),
+];
+
+/// Size in cents of the interval `ratio`.
+fn ratio_cents(ratio: f32) -> f32 {
+    1_200.0 * log2(ratio)
+}
+
+/// What went wrong reading a Scala file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalaErrorKind {
+    /// The file ended before all its notes.
+    Truncated,
+    /// The number of notes was not a number.
+    ExpectedNumber,
+    /// No notes at all.
+    NoNotes,
+    /// More than [MAX_DEGREES] notes.
+    TooManyNotes,
+    /// A note that is neither cents nor a positive ratio.
+    BadPitch,
+    /// A period that does not go up.
+    BadPeriod,
+}
+
+/// A Scala parse error on line `line`, counting from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScalaError {
+    pub line: usize,
+    pub kind: ScalaErrorKind,
+}
+
+/// Parse a Scala pitch: cents if it has a `.`, otherwise a
+/// ratio or a whole number. Anything after it on the line
+/// is a comment.
+fn scala_cents(line: &str) -> Option<f32> {
+    let word = line.split_ascii_whitespace().next()?;
+    if word.contains('.') {
+        return word.parse().ok();
+    }
+    let (num, den) = word.split_once('/').unwrap_or((word, "1"));
+    let (num, den): (u32, u32) = (num.parse().ok()?, den.parse().ok()?);
+    if num == 0 || den == 0 {
+        return None;
+    }
+    Some(ratio_cents(num as f32 / den as f32))
+}
+
+/// The frequency of every MIDI note. Notes are consecutive
+/// degrees of a scale, starting from a tonic note with a
+/// given frequency; after the last degree, the scale starts
+/// again a period higher.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    /// Each degree above the tonic, in cents. The first is
+    /// always 0.
+    cents: [f32; MAX_DEGREES],
+    len: usize,
+    period_cents: f32,
+    tonic: u8,
+    tonic_hz: f32,
+}
+
+impl Tuning {
+    /// Twelve-tone equal temperament with A4 at `a4_hz`.
+    pub fn equal(a4_hz: f32) -> Self {
+        let mut cents = [0.0; MAX_DEGREES];
+        for (degree, c) in cents[..12].iter_mut().enumerate() {
+            *c = 100.0 * degree as f32;
+        }
+        Self {
+            cents,
+            len: 12,
+            period_cents: 1_200.0,
+            tonic: A4_NOTE,
+            tonic_hz: a4_hz,
+        }
+    }
+
+    /// Just intonation on `tonic`, which keeps its pitch in
+    /// equal temperament with A4 at `a4_hz`.
+    pub fn just(tonic: u8, a4_hz: f32) -> Self {
+        let mut cents = [0.0; MAX_DEGREES];
+        for (c, &(num, den)) in cents.iter_mut().zip(&JUST_RATIOS) {
+            *c = ratio_cents(num as f32 / den as f32);
+        }
+        Self {
+            cents,
+            len: 12,
+            period_cents: 1_200.0,
+            tonic,
+            tonic_hz: Self::equal(a4_hz).freq(tonic),
+        }
+    }
+
+    /// The scale in Scala file `src`, on middle C at its
+    /// usual pitch. Use [Self::with_tonic] to move it.
+    pub fn from_scala(src: &str) -> Result<Self, ScalaError> {
+        let mut lines = src
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.starts_with('!'));
+        let mut last_line = 0;
+        let mut next_line = || {
+            let next = lines.next();
+            if let Some((line, _)) = next {
+                last_line = line;
+            }
+            next.ok_or(ScalaError {
+                line: last_line + 1,
+                kind: ScalaErrorKind::Truncated,
+            })
+        };
+        // The description isn't kept.
+        next_line()?;
+        let (line, count) = next_line()?;
+        let error = |line, kind| ScalaError { line, kind };
+        let count: usize = count
+            .split_ascii_whitespace()
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or(error(line, ScalaErrorKind::ExpectedNumber))?;
+        if count == 0 {
+            return Err(error(line, ScalaErrorKind::NoNotes));
+        }
+        if count > MAX_DEGREES {
+            return Err(error(line, ScalaErrorKind::TooManyNotes));
+        }
+        let mut cents = [0.0; MAX_DEGREES];
+        for degree in 1..=count {
+            let (line, pitch) = next_line()?;
+            let pitch = scala_cents(pitch).ok_or(error(line, ScalaErrorKind::BadPitch))?;
+            if degree == count {
+                if pitch <= 0.0 {
+                    return Err(error(line, ScalaErrorKind::BadPeriod));
+                }
+                cents[0] = pitch;
+            } else {
+                cents[degree] = pitch;
+            }
+        }
+        let period_cents = core::mem::take(&mut cents[0]);
+        Ok(Self {
+            cents,
+            len: count,
+            period_cents,
+            tonic: 60,
+            tonic_hz: midi_to_freq(60),
+        })
+    }
+
+    /// The same scale, starting on `tonic` at `tonic_hz`.
+    pub fn with_tonic(self, tonic: u8, tonic_hz: f32) -> Self {
+        Self {
+            tonic,
+            tonic_hz,
+            ..self
+        }
+    }
+
+    /// The same scale, moved so that `note` is at
+    /// `freq_hz`.
+    pub fn with_reference(self, note: u8, freq_hz: f32) -> Self {
+        let tonic_hz = self.tonic_hz * freq_hz / self.freq(note);
+        Self { tonic_hz, ..self }
+    }
+
+    /// Number of notes in each period.
+    pub fn degrees(&self) -> usize {
+        self.len
+    }
+
+    /// Frequency of MIDI note number `note`.
+    pub fn freq(&self, note: u8) -> f32 {
+        let offset = note as i32 - self.tonic as i32;
+        let periods = offset.div_euclid(self.len as i32);
+        let degree = offset.rem_euclid(self.len as i32) as usize;
+        let cents = periods as f32 * self.period_cents + self.cents[degree];
+        self.tonic_hz * semitone_ratio(cents / 100.0)
+    }
+
+    /// The note nearest in pitch to `freq_hz`, kept within
+    /// the MIDI range.
+    pub fn nearest(&self, freq_hz: f32) -> u8 {
+        let cents = ratio_cents(freq_hz / self.tonic_hz);
+        let periods = floor(cents / self.period_cents);
+        let within = cents - periods as f32 * self.period_cents;
+        // The tonic a period up is a candidate too.
+        let degree = self.cents[..self.len]
+            .iter()
+            .chain([&self.period_cents])
+            .map(|&c| (within - c).abs())
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|(degree, _)| degree)
+            .unwrap();
+        let note = self.tonic as i64 + periods as i64 * self.len as i64 + degree as i64;
+        note.clamp(0, 127) as u8
+    }
+
+    /// `freq_hz` moved to the nearest note.
+    pub fn quantize(&self, freq_hz: f32) -> f32 {
+        self.freq(self.nearest(freq_hz))
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::equal(A4_HZ)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_equal_temperament() {
+        for note in 0..=127 {
+            let expected = 440.0 * 2f64.powf((note as f64 - 69.0) / 12.0);
+            let error = (midi_to_freq(note) as f64 / expected - 1.0).abs();
+            assert!(error < 1e-6, "note {}: {}", note, midi_to_freq(note));
+        }
+    }
+
+    #[test]
+    fn reference_points() {
+        assert_eq!(midi_to_freq(69), 440.0);
+        assert_eq!(midi_to_freq(57), 220.0);
+        assert_eq!(midi_to_freq(81), 880.0);
+        assert!((midi_to_freq(60) - 261.6256).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fractional_semitones() {
+        for i in -480..=480 {
+            let semitones = i as f32 / 20.0;
+            let expected = 2f64.powf(semitones as f64 / 12.0);
+            let error = (semitone_ratio(semitones) as f64 / expected - 1.0).abs();
+            assert!(error < 1e-6, "{} semitones: {}", semitones, semitone_ratio(semitones));
+        }
+        assert_eq!(semitone_ratio(0.0), 1.0);
+        assert_eq!(semitone_ratio(-12.0), 0.5);
+    }
+
+    #[test]
+    fn log2_accuracy() {
+        for x in [1e-6, 0.001, 0.3, 1.0, 1.5, 2.0, 3.0, 1_000.0, 1e9] {
+            assert!((log2(x) as f64 - (x as f64).log2()).abs() < 1e-5, "{}", x);
+        }
+    }
+
+    #[test]
+    fn notes_from_frequencies() {
+        for note in 0..=127 {
+            let midi = freq_to_midi(midi_to_freq(note));
+            assert!((midi - note as f32).abs() < 1e-4, "note {}: {}", note, midi);
+        }
+        // A quarter tone up.
+        assert!((freq_to_midi(452.893) - 69.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn note_names() {
+        assert_eq!(note_name(60), "C");
+        assert_eq!(note_name(69), "A");
+        assert_eq!(note_name(70), "A#");
+        assert_eq!(note_name(11), "B");
+    }
+
+    #[test]
+    fn quantize_to_scales() {
+        assert_eq!(Scale::Chromatic.quantize(0, 60.4), 60);
+        assert_eq!(Scale::Chromatic.quantize(0, 60.6), 61);
+        // C# is not in C major: the nearer of C and D.
+        assert_eq!(Scale::Major.quantize(0, 60.9), 60);
+        assert_eq!(Scale::Major.quantize(0, 61.1), 62);
+        // B rounds up to the next octave's tonic.
+        assert_eq!(Scale::Pentatonic.quantize(0, 70.8), 72);
+        assert_eq!(Scale::Pentatonic.quantize(0, 69.4), 69);
+        // A minor, below the tonic.
+        assert_eq!(Scale::Minor.quantize(9, 55.4), 55);
+        assert_eq!(Scale::Minor.quantize(69, 56.6), 57);
+        // Every note found is in the scale.
+        for i in 10..1_260 {
+            let note = Scale::Major.quantize(2, i as f32 / 10.0);
+            assert!(Scale::Major.degrees().contains(&((note + 10) % 12)), "{}", note);
+        }
+        assert_eq!(Scale::Chromatic.quantize(0, -3.0), 0);
+        assert_eq!(Scale::Chromatic.quantize(0, 200.0), 127);
+    }
+
+    #[test]
+    fn equal_temperament_with_other_a4() {
+        let standard = Tuning::default();
+        for note in 0..=127 {
+            let error = (standard.freq(note) / midi_to_freq(note) - 1.0).abs();
+            assert!(error < 1e-6, "note {}", note);
+        }
+        let baroque = Tuning::equal(415.0);
+        assert_eq!(baroque.freq(69), 415.0);
+        assert_eq!(baroque.freq(57), 207.5);
+        assert!((baroque.freq(70) - 439.67).abs() < 0.01);
+        assert_eq!(baroque.degrees(), 12);
+    }
+
+    #[test]
+    fn just_intonation() {
+        let c = Tuning::just(60, 440.0);
+        assert!((c.freq(60) - 261.6256).abs() < 1e-3);
+        for (note, &(num, den)) in (60..).zip(&JUST_RATIOS) {
+            let ratio = c.freq(note) / c.freq(60);
+            assert!((ratio / (num as f32 / den as f32) - 1.0).abs() < 1e-5, "note {}", note);
+        }
+        // A pure major third is 14 cents flat of equal
+        // temperament, and the octaves are still octaves.
+        let third = ratio_cents(c.freq(64) / midi_to_freq(64));
+        assert!((third + 13.69).abs() < 0.01, "{}", third);
+        assert!((c.freq(48) * 2.0 - c.freq(60)).abs() < 1e-3);
+        assert!((c.freq(79) / c.freq(60) - 3.0).abs() < 1e-5);
+        // Moved to A: the A is where it was.
+        let a = Tuning::just(57, 440.0);
+        assert!((a.freq(69) - 440.0).abs() < 1e-3);
+        assert!((a.freq(76) / a.freq(69) - 1.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scala_files() {
+        let twelve = Tuning::from_scala(
+            "! 12-TET.scl\n\
+             !\n\
+             12-tone equal temperament\n\
+             12\n\
+             !\n\
+             100.0\n200.\n300.0\n400.0\n500.0\n600.0\n\
+             700.0\n800.0\n900.0\n1000.0\n1100.0\n\
+             2/1\n",
+        )
+        .unwrap();
+        for note in 0..=127 {
+            let error = (twelve.freq(note) / midi_to_freq(note) - 1.0).abs();
+            assert!(error < 1e-5, "note {}", note);
+        }
+        // Just intonation as ratios, with \r\n endings and
+        // trailing comments.
+        let just: String = JUST_RATIOS[1..]
+            .iter()
+            .map(|(num, den)| format!("{}/{} ! comment\r\n", num, den))
+            .collect();
+        let just = Tuning::from_scala(&format!("Just\r\n 12\r\n{}2\r\n", just)).unwrap();
+        let expected = Tuning::just(60, 440.0);
+        for note in 0..=127 {
+            let error = (just.freq(note) / expected.freq(note) - 1.0).abs();
+            assert!(error < 1e-5, "note {}", note);
+        }
+        // Five notes to the octave: each MIDI note is the next
+        // degree.
+        let pentatonic = Tuning::from_scala(
+            "! pythagorean.scl\nPythagorean pentatonic\n 5\n9/8\n81/64\n3/2\n27/16\n2/1\n",
+        )
+        .unwrap()
+        .with_tonic(60, 256.0);
+        assert_eq!(pentatonic.degrees(), 5);
+        assert_eq!(pentatonic.freq(60), 256.0);
+        assert!((pentatonic.freq(61) - 288.0).abs() < 1e-3);
+        assert!((pentatonic.freq(63) - 384.0).abs() < 1e-3);
+        assert!((pentatonic.freq(65) - 512.0).abs() < 1e-3);
+        assert!((pentatonic.freq(59) - 216.0).abs() < 1e-3);
+        // A tritave instead of an octave, and an empty
+        // description.
+        let bohlen_pierce = Tuning::from_scala(&format!(
+            "\n13\n{}3/1\n",
+            (1..13)
+                .map(|i| format!("{:.3}\n", i as f64 * 1901.955 / 13.0))
+                .collect::<String>()
+        ))
+        .unwrap();
+        assert!((bohlen_pierce.freq(73) / bohlen_pierce.freq(60) - 3.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn scala_errors() {
+        use ScalaErrorKind::*;
+        let error = |src: &str| {
+            let e = Tuning::from_scala(src).unwrap_err();
+            (e.line, e.kind)
+        };
+        assert_eq!(error(""), (1, Truncated));
+        assert_eq!(error("! only a comment"), (1, Truncated));
+        assert_eq!(error("Scale\n"), (2, Truncated));
+        assert_eq!(error("Scale\ntwelve\n"), (2, ExpectedNumber));
+        assert_eq!(error("Scale\n0\n"), (2, NoNotes));
+        assert_eq!(error("Scale\n129\n"), (2, TooManyNotes));
+        assert_eq!(error("Scale\n3\n9/8\n5/4\n"), (5, Truncated));
+        assert_eq!(error("Scale\n3\n9/8\n! comment\n5:4\n2/1\n"), (5, BadPitch));
+        assert_eq!(error("Scale\n2\n3/0\n2/1\n"), (3, BadPitch));
+        assert_eq!(error("Scale\n2\n-3/2\n2/1\n"), (3, BadPitch));
+        assert_eq!(error("Scale\n2\n1.2.3\n2/1\n"), (3, BadPitch));
+        assert_eq!(error("Scale\n2\n3/2\n1/2\n"), (4, BadPeriod));
+        assert_eq!(error("Scale\n1\n0.0\n"), (3, BadPeriod));
+    }
+
+    #[test]
+    fn nearest_notes() {
+        let standard = Tuning::default();
+        assert_eq!(standard.nearest(440.0), 69);
+        assert_eq!(standard.nearest(450.0), 69);
+        assert_eq!(standard.nearest(455.0), 70);
+        assert_eq!(standard.nearest(1.0), 0);
+        assert_eq!(standard.nearest(20_000.0), 127);
+        for note in 0..=127 {
+            assert_eq!(standard.nearest(midi_to_freq(note) * 1.02), note);
+            assert_eq!(standard.nearest(midi_to_freq(note) / 1.02), note);
+        }
+        assert_eq!(standard.quantize(445.0), 440.0);
+        let baroque = Tuning::equal(415.0);
+        assert_eq!(baroque.nearest(440.0), 70);
+        // The next period's tonic is nearest the top of a
+        // period.
+        let pentatonic = Tuning::from_scala("P\n5\n9/8\n81/64\n3/2\n27/16\n2/1\n")
+            .unwrap()
+            .with_reference(60, 256.0);
+        assert_eq!(pentatonic.nearest(500.0), 65);
+        assert_eq!(pentatonic.nearest(440.0), 64);
+        assert_eq!(pentatonic.nearest(260.0), 60);
+    }
+
+    #[test]
+    fn quantize_frequencies_to_scales() {
+        let standard = Tuning::default();
+        // A little sharp of C#: C or D in C major, C# in the
+        // chromatic scale.
+        let freq = midi_to_freq(61) * 1.01;
+        assert_eq!(Scale::Chromatic.quantize_freq(&standard, 0, freq), midi_to_freq(61));
+        assert_eq!(Scale::Major.quantize_freq(&standard, 0, freq), midi_to_freq(62));
+        assert_eq!(Scale::Major.quantize_freq(&standard, 0, freq / 1.03), midi_to_freq(60));
+        // In just intonation, E is lower.
+        let just = Tuning::just(60, 440.0);
+        let e = Scale::Pentatonic.quantize_freq(&just, 60, 340.0);
+        assert_eq!(e, just.freq(64));
+        assert!(e < midi_to_freq(64));
+    }
+
+    #[test]
+    fn scales_by_name() {
+        assert_eq!(Scale::from_name("Major"), Some(Scale::Major));
+        assert_eq!(Scale::from_name("PENTATONIC"), Some(Scale::Pentatonic));
+        assert_eq!(Scale::from_name("dorian"), None);
+    }
+}
@@ -0,0 +1,547 @@
+//! WAV (RIFF/WAVE) sound clips.
+//!
+//! A WAV file is a `RIFF` chunk of form type `WAVE` holding
+//! other chunks: an `fmt ` chunk describing the samples, and
+//! a `data` chunk with the samples themselves. Any other
+//! chunks, such as `LIST`, are skipped. Chunk
+//! bodies of odd length are followed by a pad byte.
+//!
+//! 8-bit (unsigned) and 16-bit (signed, little-endian) PCM
+//! are supported, as are 8-bit [G.711](crate::g711) mu-law
+//! and A-law and 4-bit [IMA ADPCM](crate::adpcm), mono or
+//! stereo, at any sample rate. Compressed files give
+//! their exact length in samples in a `fact` chunk.
+//! [Wav::parse] checks the whole chunk structure up front,
+//! without allocation, and [Wav::samples] then reads the
+//! samples straight from the data, mixing stereo down to
+//! mono. A [Resampler](crate::resample::Resampler) brings
+//! them to the output rate.
+
+use crate::adpcm::{self, FORMAT_IMA_ADPCM};
+use crate::g711::{self, FORMAT_ALAW, FORMAT_MULAW};
+use crate::Sample;
+
+/// `fmt ` format tag for PCM.
+pub const FORMAT_PCM: u16 = 1;
+
+/// What went wrong parsing a WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavErrorKind {
+    /// No `RIFF` header of form type `WAVE` at the start.
+    BadHeader,
+    /// A chunk, or the `RIFF` chunk itself, runs past the
+    /// end of the data.
+    Truncated,
+    /// An `fmt ` chunk that is too short or inconsistent.
+    BadFormat,
+    /// A format tag other than PCM, G.711 or IMA ADPCM.
+    UnsupportedFormat(u16),
+    /// Other than one or two channels.
+    UnsupportedChannels(u16),
+    /// Other than 8 or 16 bits per sample for PCM, 8 for
+    /// G.711, or 4 for ADPCM.
+    UnsupportedBits(u16),
+    /// No `fmt ` chunk before the `data` chunk.
+    MissingFormat,
+    /// No `data` chunk.
+    MissingData,
+}
+
+/// A WAV parse error at byte offset `pos`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavError {
+    pub pos: usize,
+    pub kind: WavErrorKind,
+}
+
+fn error(pos: usize, kind: WavErrorKind) -> WavError {
+    WavError { pos, kind }
+}
+
+fn le_u16(b: &[u8]) -> u16 {
+    u16::from_le_bytes([b[0], b[1]])
+}
+
+fn le_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+/// How each sample is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Unsigned 8-bit, centred on 128.
+    Pcm8,
+    /// Signed 16-bit little-endian.
+    Pcm16,
+    /// 8-bit G.711 mu-law.
+    MuLaw,
+    /// 8-bit G.711 A-law.
+    ALaw,
+    /// 4-bit IMA ADPCM, in blocks.
+    ImaAdpcm,
+}
+
+/// The contents of an `fmt ` chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Format {
+    encoding: Encoding,
+    channels: u16,
+    sample_rate: u32,
+    /// Bytes per block: a frame, for PCM.
+    block_align: u16,
+    /// Frames per block: one, for PCM.
+    samples_per_block: u16,
+}
+
+impl Format {
+    /// Parse the `fmt ` chunk body `body`, which starts at
+    /// byte offset `pos`.
+    fn parse(body: &[u8], pos: usize) -> Result<Self, WavError> {
+        if body.len() < 16 {
+            return Err(error(pos, WavErrorKind::BadFormat));
+        }
+        let tag = le_u16(body);
+        let channels = le_u16(&body[2..]);
+        let sample_rate = le_u32(&body[4..]);
+        let block_align = le_u16(&body[12..]);
+        let bits = le_u16(&body[14..]);
+        if ![FORMAT_PCM, FORMAT_ALAW, FORMAT_MULAW, FORMAT_IMA_ADPCM].contains(&tag) {
+            return Err(error(pos, WavErrorKind::UnsupportedFormat(tag)));
+        }
+        if !(1..=2).contains(&channels) {
+            return Err(error(pos + 2, WavErrorKind::UnsupportedChannels(channels)));
+        }
+        let encoding = match (tag, bits) {
+            (FORMAT_PCM, 8) => Encoding::Pcm8,
+            (FORMAT_PCM, 16) => Encoding::Pcm16,
+            (FORMAT_MULAW, 8) => Encoding::MuLaw,
+            (FORMAT_ALAW, 8) => Encoding::ALaw,
+            (FORMAT_IMA_ADPCM, 4) => Encoding::ImaAdpcm,
+            _ => return Err(error(pos + 14, WavErrorKind::UnsupportedBits(bits))),
+        };
+        if sample_rate == 0 {
+            return Err(error(pos + 4, WavErrorKind::BadFormat));
+        }
+        let mut samples_per_block = 1;
+        if encoding == Encoding::ImaAdpcm {
+            // Whole rounds of four bytes per channel after the
+            // headers.
+            let round = 4 * channels as usize;
+            if block_align as usize <= round || !(block_align as usize).is_multiple_of(round) {
+                return Err(error(pos + 12, WavErrorKind::BadFormat));
+            }
+            let most = adpcm::block_samples(block_align as usize, channels as usize);
+            // Given in the extra format bytes, if there are
+            // any; otherwise the blocks are full.
+            samples_per_block = if body.len() >= 20 && le_u16(&body[16..]) >= 2 {
+                le_u16(&body[18..])
+            } else {
+                most as u16
+            };
+            if samples_per_block == 0 || samples_per_block as usize > most {
+                return Err(error(pos + 18, WavErrorKind::BadFormat));
+            }
+        } else if block_align != channels * bits / 8 {
+            return Err(error(pos + 12, WavErrorKind::BadFormat));
+        }
+        Ok(Self {
+            encoding,
+            channels,
+            sample_rate,
+            block_align,
+            samples_per_block,
+        })
+    }
+
+    /// Frames in `len` bytes of data.
+    fn frames(&self, len: usize) -> usize {
+        adpcm::frames(
+            len,
+            self.block_align as usize,
+            self.channels as usize,
+            self.samples_per_block as usize,
+        )
+    }
+}
+
+/// A parsed WAV file.
+#[derive(Debug, Clone, Copy)]
+pub struct Wav<'a> {
+    format: Format,
+    data: &'a [u8],
+    frames: usize,
+}
+
+impl<'a> Wav<'a> {
+    /// Check the chunks of `data` and find its samples. A
+    /// trailing partial frame in the `data` chunk is ignored,
+    /// as are any samples of a compressed file beyond the
+    /// length given in its `fact` chunk.
+    pub fn parse(data: &'a [u8]) -> Result<Self, WavError> {
+        if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err(error(0, WavErrorKind::BadHeader));
+        }
+        let riff_end = 8usize.saturating_add(le_u32(&data[4..]) as usize);
+        if riff_end > data.len() {
+            return Err(error(4, WavErrorKind::Truncated));
+        }
+        let mut format = None;
+        let mut fact = None;
+        let mut pos = 12;
+        while pos < riff_end {
+            if riff_end - pos < 8 {
+                return Err(error(pos, WavErrorKind::Truncated));
+            }
+            let len = le_u32(&data[pos + 4..]) as usize;
+            let start = pos + 8;
+            let end = start.saturating_add(len);
+            if end > riff_end {
+                return Err(error(pos + 4, WavErrorKind::Truncated));
+            }
+            let body = &data[start..end];
+            match &data[pos..pos + 4] {
+                b"fmt " => format = Some(Format::parse(body, start)?),
+                b"fact" if body.len() >= 4 => fact = Some(le_u32(body) as usize),
+                b"data" => {
+                    let format = format.ok_or(error(pos, WavErrorKind::MissingFormat))?;
+                    let mut frames = format.frames(body.len());
+                    if format.encoding == Encoding::ImaAdpcm {
+                        frames = frames.min(fact.unwrap_or(usize::MAX));
+                    }
+                    return Ok(Self {
+                        format,
+                        data: body,
+                        frames,
+                    });
+                }
+                _ => (),
+            }
+            // The pad byte after an odd-length body may be
+            // missing at the very end.
+            pos = end + len % 2;
+        }
+        Err(error(riff_end, WavErrorKind::MissingData))
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.format.encoding
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.format.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.format.sample_rate
+    }
+
+    /// Number of frames: samples per channel.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn duration_ms(&self) -> u32 {
+        (self.frames() as u64 * 1_000 / self.sample_rate() as u64) as u32
+    }
+
+    /// Iterator over the samples, mixed down to mono.
+    pub fn samples(&self) -> Samples<'a> {
+        let format = self.format;
+        if format.encoding == Encoding::ImaAdpcm {
+            return Samples(Inner::ImaAdpcm(adpcm::WavSamples::new(
+                self.data,
+                format.block_align as usize,
+                format.channels as usize,
+                format.samples_per_block as usize,
+                self.frames,
+            )));
+        }
+        let frames = self.data[..self.frames * format.block_align as usize]
+            .chunks_exact(format.block_align as usize);
+        Samples(Inner::Pcm { format, frames })
+    }
+}
+
+/// Iterator over the samples of a [Wav], mixed down to mono.
+#[derive(Debug, Clone)]
+pub struct Samples<'a>(Inner<'a>);
+
+#[derive(Debug, Clone)]
+enum Inner<'a> {
+    Pcm {
+        format: Format,
+        frames: core::slice::ChunksExact<'a, u8>,
+    },
+    ImaAdpcm(adpcm::WavSamples<'a>),
+}
+
+impl Iterator for Samples<'_> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let (format, frames) = match &mut self.0 {
+            Inner::Pcm { format, frames } => (format, frames),
+            Inner::ImaAdpcm(samples) => return samples.next(),
+        };
+        let frame = frames.next()?;
+        let byte = |b: &u8| -> i32 {
+            match format.encoding {
+                Encoding::MuLaw => g711::decode_mulaw(*b) as i32,
+                Encoding::ALaw => g711::decode_alaw(*b) as i32,
+                _ => (*b as i32 - 128) << 8,
+            }
+        };
+        let sum: i32 = match format.encoding {
+            Encoding::Pcm16 => frame
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as i32)
+                .sum(),
+            _ => frame.iter().map(byte).sum(),
+        };
+        Some((sum / format.channels as i32) as Sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.0 {
+            Inner::Pcm { frames, .. } => frames.size_hint(),
+            Inner::ImaAdpcm(samples) => samples.size_hint(),
+        }
+    }
+}
+
+impl ExactSizeIterator for Samples<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use WavErrorKind::*;
+
+    /// A `fmt ` chunk body.
+    fn fmt(tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut body = vec![];
+        body.extend(tag.to_le_bytes());
+        body.extend(channels.to_le_bytes());
+        body.extend(sample_rate.to_le_bytes());
+        body.extend((sample_rate * block_align as u32).to_le_bytes());
+        body.extend(block_align.to_le_bytes());
+        body.extend(bits.to_le_bytes());
+        body
+    }
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((body.len() as u32).to_le_bytes());
+        chunk.extend(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut file = b"RIFF".to_vec();
+        file.extend((body.len() as u32 + 4).to_le_bytes());
+        file.extend(b"WAVE");
+        file.extend(body);
+        file
+    }
+
+    fn error(data: &[u8]) -> (usize, WavErrorKind) {
+        let e = Wav::parse(data).unwrap_err();
+        (e.pos, e.kind)
+    }
+
+    #[test]
+    fn eight_bit_mono() {
+        let file = riff(&[chunk(b"fmt ", &fmt(1, 1, 8_000, 8)), chunk(b"data", &[128, 255, 0, 64])]);
+        let wav = Wav::parse(&file).unwrap();
+        assert_eq!(wav.encoding(), Encoding::Pcm8);
+        assert_eq!((wav.channels(), wav.sample_rate(), wav.frames()), (1, 8_000, 4));
+        assert_eq!(wav.samples().collect::<Vec<_>>(), [0, 32_512, -32_768, -16_384]);
+    }
+
+    #[test]
+    fn sixteen_bit_stereo_mixed_down() {
+        let frames: Vec<u8> = [(1_000i16, 3_000i16), (-32_768, -32_768), (32_767, -32_767)]
+            .iter()
+            .flat_map(|(l, r)| [l.to_le_bytes(), r.to_le_bytes()].concat())
+            .collect();
+        let file = riff(&[chunk(b"fmt ", &fmt(1, 2, 44_100, 16)), chunk(b"data", &frames)]);
+        let wav = Wav::parse(&file).unwrap();
+        assert_eq!(wav.encoding(), Encoding::Pcm16);
+        assert_eq!((wav.channels(), wav.sample_rate(), wav.frames()), (2, 44_100, 3));
+        assert_eq!(wav.samples().collect::<Vec<_>>(), [2_000, -32_768, 0]);
+        assert_eq!(wav.samples().len(), 3);
+    }
+
+    #[test]
+    fn companded() {
+        let data = [0xff, 0x80, 0x00];
+        let mulaw = riff(&[chunk(b"fmt ", &fmt(7, 1, 8_000, 8)), chunk(b"data", &data)]);
+        let wav = Wav::parse(&mulaw).unwrap();
+        assert_eq!(wav.encoding(), Encoding::MuLaw);
+        assert_eq!(wav.samples().collect::<Vec<_>>(), [0, 32_124, -32_124]);
+        let data = [0xd5, 0xaa, 0x2a, 0x2a];
+        let alaw = riff(&[chunk(b"fmt ", &fmt(6, 2, 8_000, 8)), chunk(b"data", &data)]);
+        let wav = Wav::parse(&alaw).unwrap();
+        assert_eq!((wav.encoding(), wav.frames()), (Encoding::ALaw, 2));
+        assert_eq!(wav.samples().collect::<Vec<_>>(), [(8 + 32_256) / 2, -32_256]);
+    }
+
+    #[test]
+    fn other_chunks_skipped() {
+        // An odd-length chunk with its pad byte, an extended
+        // `fmt ` chunk, and a trailing partial frame.
+        let mut format = fmt(1, 1, 11_025, 16);
+        format.extend([0, 0]);
+        let file = riff(&[
+            chunk(b"LIST", b"INFOx"),
+            chunk(b"fmt ", &format),
+            chunk(b"fact", &[1, 0, 0, 0]),
+            chunk(b"data", &[1, 0, 2, 0, 3]),
+        ]);
+        let wav = Wav::parse(&file).unwrap();
+        assert_eq!(wav.samples().collect::<Vec<_>>(), [1, 2]);
+        // Bytes after the RIFF chunk are not part of it.
+        let mut padded = riff(&[chunk(b"fmt ", &fmt(1, 1, 8_000, 8)), chunk(b"data", &[1])]);
+        padded.extend(b"junk");
+        assert_eq!(Wav::parse(&padded).unwrap().frames(), 1);
+    }
+
+    #[test]
+    fn built_in_clips() {
+        let chime = Wav::parse(crate::songs::CLIPS[0].1).unwrap();
+        assert_eq!(chime.encoding(), Encoding::Pcm8);
+        assert_eq!((chime.channels(), chime.sample_rate()), (1, 8_000));
+        assert_eq!(chime.duration_ms(), 600);
+        let zap = Wav::parse(crate::songs::CLIPS[1].1).unwrap();
+        assert_eq!(zap.encoding(), Encoding::Pcm16);
+        assert_eq!((zap.channels(), zap.sample_rate()), (2, 11_025));
+        assert_eq!(zap.duration_ms(), 299);
+        let gong = Wav::parse(crate::songs::CLIPS[2].1).unwrap();
+        assert_eq!(gong.encoding(), Encoding::ImaAdpcm);
+        assert_eq!((gong.channels(), gong.sample_rate()), (1, 16_000));
+        assert_eq!(gong.duration_ms(), 1_200);
+        assert_eq!(gong.samples().len(), 19_200);
+    }
+
+    #[test]
+    fn durations() {
+        let data = vec![0; 2 * 4_000];
+        let file = riff(&[chunk(b"fmt ", &fmt(1, 2, 8_000, 8)), chunk(b"data", &data)]);
+        assert_eq!(Wav::parse(&file).unwrap().duration_ms(), 500);
+    }
+
+    #[test]
+    fn truncated_files() {
+        let file = riff(&[
+            chunk(b"fmt ", &fmt(1, 1, 8_000, 16)),
+            chunk(b"data", &[0; 100]),
+        ]);
+        assert!(Wav::parse(&file).is_ok());
+        // Every shorter prefix fails, without panicking.
+        for len in 0..file.len() {
+            let (_, kind) = error(&file[..len]);
+            let expected = if len < 12 { BadHeader } else { Truncated };
+            assert_eq!(kind, expected, "{} bytes", len);
+        }
+        // A RIFF size that is right, but a chunk too long
+        // for it.
+        let mut long = file.clone();
+        long[40..44].copy_from_slice(&200u32.to_le_bytes());
+        assert_eq!(error(&long), (40, Truncated));
+        // A chunk header cut short by the RIFF size.
+        let mut short = riff(&[chunk(b"fmt ", &fmt(1, 1, 8_000, 16))]);
+        short.extend(b"dat");
+        let len = short.len() as u32 - 8;
+        short[4..8].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(error(&short), (36, Truncated));
+    }
+
+    #[test]
+    fn malformed_headers() {
+        let good = riff(&[chunk(b"fmt ", &fmt(1, 1, 8_000, 8)), chunk(b"data", &[0; 4])]);
+        let mut bad = good.clone();
+        bad[0] = b'r';
+        assert_eq!(error(&bad), (0, BadHeader));
+        let mut bad = good.clone();
+        bad[8..12].copy_from_slice(b"AVI ");
+        assert_eq!(error(&bad), (0, BadHeader));
+        assert_eq!(error(b"RIFF\x04\x00\x00\x00WAVE"), (12, MissingData));
+        let data_first = riff(&[chunk(b"data", &[0; 4]), chunk(b"fmt ", &fmt(1, 1, 8_000, 8))]);
+        assert_eq!(error(&data_first), (12, MissingFormat));
+        let no_data = riff(&[chunk(b"fmt ", &fmt(1, 1, 8_000, 8))]);
+        assert_eq!(error(&no_data), (36, MissingData));
+    }
+
+    #[test]
+    fn malformed_formats() {
+        let wav = |format: &[u8]| riff(&[chunk(b"fmt ", format), chunk(b"data", &[0; 4])]);
+        assert_eq!(error(&wav(&fmt(3, 1, 8_000, 32))), (20, UnsupportedFormat(3)));
+        assert_eq!(error(&wav(&fmt(0xfffe, 1, 8_000, 16))), (20, UnsupportedFormat(0xfffe)));
+        assert_eq!(error(&wav(&fmt(1, 0, 8_000, 16))), (22, UnsupportedChannels(0)));
+        assert_eq!(error(&wav(&fmt(1, 6, 8_000, 16))), (22, UnsupportedChannels(6)));
+        assert_eq!(error(&wav(&fmt(1, 1, 8_000, 24))), (34, UnsupportedBits(24)));
+        assert_eq!(error(&wav(&fmt(1, 1, 0, 16))), (24, BadFormat));
+        assert_eq!(error(&wav(&fmt(1, 1, 8_000, 16)[..14])), (20, BadFormat));
+        let mut misaligned = fmt(1, 2, 8_000, 16);
+        misaligned[12] = 2;
+        assert_eq!(error(&wav(&misaligned)), (32, BadFormat));
+        assert_eq!(error(&wav(&fmt(0x11, 1, 8_000, 8))), (34, UnsupportedBits(8)));
+        assert_eq!(error(&wav(&fmt(7, 1, 8_000, 16))), (34, UnsupportedBits(16)));
+        assert_eq!(error(&wav(&adpcm_fmt(2, 12, None))), (32, BadFormat));
+        assert_eq!(error(&wav(&adpcm_fmt(1, 4, None))), (32, BadFormat));
+        assert_eq!(error(&wav(&adpcm_fmt(1, 256, Some(506)))), (38, BadFormat));
+        assert_eq!(error(&wav(&adpcm_fmt(1, 256, Some(0)))), (38, BadFormat));
+    }
+
+    /// An IMA ADPCM `fmt ` chunk body, with the samples per
+    /// block if given.
+    fn adpcm_fmt(channels: u16, block_align: u16, samples_per_block: Option<u16>) -> Vec<u8> {
+        let mut body = fmt(0x11, channels, 8_000, 4);
+        body[12..14].copy_from_slice(&block_align.to_le_bytes());
+        if let Some(n) = samples_per_block {
+            body.extend(2u16.to_le_bytes());
+            body.extend(n.to_le_bytes());
+        }
+        body
+    }
+
+    #[test]
+    fn adpcm_blocks() {
+        // Two mono blocks of 8 bytes: 9 samples each, with
+        // the length cut to 12 by the `fact` chunk.
+        let mut data = vec![];
+        for first in [1_000i16, -1_000] {
+            data.extend(first.to_le_bytes());
+            data.extend([0, 0, 0x70, 0x70, 0x70, 0x70]);
+        }
+        let blocks = |fact: Option<u32>, format: Vec<u8>| {
+            let mut chunks = vec![chunk(b"fmt ", &format)];
+            chunks.extend(fact.map(|n| chunk(b"fact", &n.to_le_bytes())));
+            chunks.push(chunk(b"data", &data));
+            riff(&chunks)
+        };
+        let file = blocks(Some(12), adpcm_fmt(1, 8, Some(9)));
+        let wav = Wav::parse(&file).unwrap();
+        assert_eq!((wav.encoding(), wav.frames()), (Encoding::ImaAdpcm, 12));
+        let samples: Vec<Sample> = wav.samples().collect();
+        // Codes 0 and 7 alternately: up an eighth of a step,
+        // then up a step and three quarters, the step growing.
+        assert_eq!(
+            samples,
+            [1_000, 1_000, 1_011, 1_013, 1_038, 1_041, 1_093, 1_100, 1_201, -1_000, -1_000, -989]
+        );
+        // Without a `fact` chunk, every sample in the blocks;
+        // without a count per block, full blocks.
+        let file = blocks(None, adpcm_fmt(1, 8, None));
+        assert_eq!(Wav::parse(&file).unwrap().frames(), 18);
+        // Fewer samples per block than would fit.
+        let file = blocks(None, adpcm_fmt(1, 8, Some(5)));
+        let wav = Wav::parse(&file).unwrap();
+        assert_eq!(wav.samples().len(), 10);
+        assert_eq!(wav.samples().nth(5), Some(-1_000));
+    }
+}
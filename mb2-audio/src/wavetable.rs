@@ -0,0 +1,171 @@
+//! Single-period waveform lookup tables.
+
+use crate::Sample;
+
+/// Number of entries in each table: one full period.
+pub const TABLE_LEN: usize = 256;
+
+/// `log2(TABLE_LEN)`: the number of top phase bits used to
+/// index a table.
+pub const TABLE_BITS: u32 = 8;
+
+pub type Table = [Sample; TABLE_LEN];
+
+/// One period of a full-scale sine wave.
+pub static SINE: Table = sine_table();
+
+/// One period of a full-scale square wave, high for the
+/// first half.
+pub static SQUARE: Table = square_table();
+
+/// One period of a full-scale triangle wave, starting at
+/// zero and rising.
+pub static TRIANGLE: Table = triangle_table();
+
+/// One period of a full-scale sawtooth wave, starting at
+/// zero and rising.
+pub static SAW: Table = saw_table();
+
+/// `sin(x)` by Taylor series, good to better than 1e-8 for
+/// `x` in `-π..=π`. (There is no `sin()` in `core`, let
+/// alone a `const` one.)
+pub(crate) const fn sin(x: f64) -> f64 {
+    let x2 = x * x;
+    let mut term = x;
+    let mut sum = x;
+    let mut n = 1;
+    while n < 10 {
+        term = -term * x2 / ((2 * n) as f64 * (2 * n + 1) as f64);
+        sum += term;
+        n += 1;
+    }
+    sum
+}
+
+/// Round `y` to the nearest sample value, with halves
+/// rounding away from zero.
+const fn round(y: f64) -> Sample {
+    if y < 0.0 {
+        (y - 0.5) as Sample
+    } else {
+        (y + 0.5) as Sample
+    }
+}
+
+const fn sine_table() -> Table {
+    let mut table = [0; TABLE_LEN];
+    let mut i = 0;
+    while i < TABLE_LEN {
+        // Angle in -π..π, so that the series converges well.
+        let turns = i as f64 / TABLE_LEN as f64;
+        let turns = if turns < 0.5 { turns } else { turns - 1.0 };
+        table[i] = round(sin(turns * 2.0 * core::f64::consts::PI) * Sample::MAX as f64);
+        i += 1;
+    }
+    table
+}
+
+const fn square_table() -> Table {
+    let mut table = [0; TABLE_LEN];
+    let mut i = 0;
+    while i < TABLE_LEN {
+        table[i] = if i < TABLE_LEN / 2 {
+            Sample::MAX
+        } else {
+            -Sample::MAX
+        };
+        i += 1;
+    }
+    table
+}
+
+const fn triangle_table() -> Table {
+    let mut table = [0; TABLE_LEN];
+    let mut i = 0;
+    while i < TABLE_LEN {
+        let turns = i as f64 / TABLE_LEN as f64;
+        let y = if turns < 0.25 {
+            4.0 * turns
+        } else if turns < 0.75 {
+            2.0 - 4.0 * turns
+        } else {
+            4.0 * turns - 4.0
+        };
+        table[i] = round(y * Sample::MAX as f64);
+        i += 1;
+    }
+    table
+}
+
+const fn saw_table() -> Table {
+    let mut table = [0; TABLE_LEN];
+    let mut i = 0;
+    while i < TABLE_LEN {
+        let turns = i as f64 / TABLE_LEN as f64;
+        let y = if turns < 0.5 { 2.0 * turns } else { 2.0 * turns - 2.0 };
+        table[i] = round(y * Sample::MAX as f64);
+        i += 1;
+    }
+    table
+}
+
+/// Look up 32-bit `phase` (a full period is `2**32`) in
+/// `table`, interpolating linearly between entries.
+pub fn lookup(table: &Table, phase: u32) -> Sample {
+    let index = (phase >> (32 - TABLE_BITS)) as usize;
+    let next = (index + 1) % TABLE_LEN;
+    // Top 16 bits of the remaining phase.
+    let frac = (phase << TABLE_BITS) >> 16;
+    let a = table[index] as i64;
+    let b = table[next] as i64;
+    // Widened: across the edge of a square or saw, the step
+    // times the fraction overflows 32 bits.
+    (a + (((b - a) * frac as i64) >> 16)) as Sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_matches_sin() {
+        for (i, &s) in SINE.iter().enumerate() {
+            let x = i as f64 / TABLE_LEN as f64 * 2.0 * std::f64::consts::PI;
+            let expected = (x.sin() * Sample::MAX as f64).round();
+            assert_eq!(s as f64, expected, "entry {}", i);
+        }
+    }
+
+    #[test]
+    fn quarter_points() {
+        assert_eq!((SINE[0], SINE[64], SINE[128], SINE[192]), (0, 32767, 0, -32767));
+        assert_eq!(
+            (TRIANGLE[0], TRIANGLE[64], TRIANGLE[128], TRIANGLE[192]),
+            (0, 32767, 0, -32767),
+        );
+        assert_eq!((SAW[0], SAW[64], SAW[128], SAW[192]), (0, 16384, -32767, -16384));
+        assert_eq!((SQUARE[127], SQUARE[128]), (32767, -32767));
+    }
+
+    #[test]
+    fn lookup_interpolates() {
+        assert_eq!(lookup(&SINE, 0), 0);
+        assert_eq!(lookup(&SINE, 1 << 30), Sample::MAX);
+        // Halfway between entries 0 and 1.
+        assert_eq!(lookup(&SAW, 1 << 23), 128);
+        // Wraps from the last entry back to the first.
+        let last = lookup(&SAW, u32::MAX);
+        assert!(last < 0 && last > -256, "{}", last);
+    }
+
+    #[test]
+    fn lookup_across_edges() {
+        // Halfway down the edge of the square, and of the
+        // saw.
+        let half = (TABLE_LEN as u32 / 2 - 1) << (32 - TABLE_BITS) | 1 << 23;
+        assert_eq!(lookup(&SQUARE, half), 0);
+        assert!(lookup(&SAW, half).abs() < 256);
+        let last = (TABLE_LEN as u32 - 1) << (32 - TABLE_BITS) | 1 << 23;
+        assert_eq!(lookup(&SQUARE, last), 0);
+    }
+}
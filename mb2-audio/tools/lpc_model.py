@@ -0,0 +1,349 @@
+#!/usr/bin/env python3
+"""Model of the LPC speech synthesizer in src/lpc.rs.
+
+A second, plain implementation of the same TMS5220-style
+arithmetic: integer tables, Talkie's bit order, the
+interpolation schedule, the lattice filter and its limits.
+It shares no code with the Rust and is written to be read
+rather than run fast.
+
+    python3 tools/lpc_model.py references
+
+prints the reference sequences checked by the tests in
+src/lpc.rs, and
+
+    python3 tools/lpc_model.py words
+
+prints the "Vowels" and "Hi" bitstreams of src/songs.rs,
+which are made from textbook formant frequencies: each
+vowel's formants become a ten-pole filter, stepped down to
+reflection coefficients and quantized to the chip's tables.
+
+The model follows the same reading of the chip as the Rust,
+so agreement between the two catches slips in either, not
+misreadings of the chip. No output of a chip or of another
+decoder is checked here: the test lattice_matches_direct_form
+in src/lpc.rs checks the filter against a direct form
+all-pole filter instead.
+"""
+
+import functools
+import math
+import sys
+
+SAMPLE_RATE = 8000
+FRAME_SAMPLES = 200
+PERIOD_SAMPLES = FRAME_SAMPLES // 8
+
+ENERGY = [0, 1, 2, 3, 4, 6, 8, 11, 16, 23, 33, 47, 63, 85, 114, 0]
+
+PITCH = [
+    0, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37,
+    38, 39, 40, 41, 42, 44, 46, 48, 50, 52, 53, 56, 58, 60, 62, 65, 68, 70, 72, 76, 78, 80, 84, 86,
+    91, 94, 98, 101, 105, 109, 114, 118, 122, 127, 132, 137, 142, 148, 153, 159,
+]
+
+K = [
+    [-501, -498, -497, -495, -493, -491, -488, -482, -478, -474, -469, -464, -459, -452, -445, -437,
+     -412, -380, -339, -288, -227, -158, -81, -1, 80, 157, 226, 287, 337, 379, 411, 436],
+    [-328, -303, -274, -244, -211, -175, -138, -99, -59, -18, 24, 64, 105, 143, 180, 215, 248, 278,
+     306, 331, 354, 374, 392, 408, 422, 435, 445, 455, 463, 470, 476, 506],
+    [-441, -387, -333, -279, -225, -171, -117, -63, -9, 45, 98, 152, 206, 260, 314, 368],
+    [-328, -273, -217, -161, -106, -50, 5, 61, 116, 172, 228, 283, 339, 394, 450, 506],
+    [-328, -282, -235, -189, -142, -96, -50, -3, 43, 90, 136, 182, 229, 275, 322, 368],
+    [-256, -212, -168, -123, -79, -35, 10, 54, 98, 143, 187, 232, 276, 320, 365, 409],
+    [-308, -260, -212, -164, -117, -69, -21, 27, 75, 122, 170, 218, 266, 314, 361, 409],
+    [-256, -161, -66, 29, 124, 219, 314, 409],
+    [-256, -176, -96, -15, 65, 146, 226, 307],
+    [-205, -132, -59, 14, 87, 160, 234, 307],
+]
+
+K_BITS = [5, 5, 4, 4, 4, 4, 4, 3, 3, 3]
+
+CHIRP = [
+    0x00, 0x03, 0x0f, 0x28, 0x4c, 0x6c, 0x71, 0x50, 0x25, 0x26, 0x4c, 0x44, 0x1a, 0x32, 0x3b,
+    0x13, 0x37, 0x1a, 0x25, 0x1f, 0x1d,
+] + [0] * 31
+
+SHIFTS = [0, 3, 3, 3, 2, 2, 1, 1]
+
+NOISE = 64
+LATTICE_MAX = (1 << 14) - 1
+OUTPUT_MAX = (1 << 11) - 1
+
+
+def pack(fields):
+    """Pack (bits, value) fields the way Talkie reads them:
+    each field first bit most significant, each byte filled
+    from its lowest bit."""
+    data = []
+    pos = 0
+    for bits, value in fields:
+        for bit in reversed(range(bits)):
+            if pos % 8 == 0:
+                data.append(0)
+            data[-1] |= ((value >> bit) & 1) << (pos % 8)
+            pos += 1
+    return bytes(data)
+
+
+def voiced(energy, pitch, k):
+    return [(4, energy), (1, 0), (6, pitch)] + list(zip(K_BITS, k))
+
+
+def frames(data):
+    """The frames of `data`, up to a stop frame or one cut
+    short by the end of the data."""
+    pos = 0
+
+    def read(bits):
+        nonlocal pos
+        value = 0
+        for _ in range(bits):
+            if pos // 8 >= len(data):
+                raise EOFError
+            value = value << 1 | (data[pos // 8] >> (pos % 8)) & 1
+            pos += 1
+        return value
+
+    try:
+        while True:
+            energy = read(4)
+            if energy == 0:
+                yield ("silence",)
+                continue
+            if energy == 15:
+                return
+            repeat = read(1)
+            pitch = read(6)
+            if repeat:
+                yield ("repeat", energy, pitch)
+                continue
+            count = 10 if pitch else 4
+            yield ("new", energy, pitch, [read(K_BITS[i]) for i in range(count)])
+    except EOFError:
+        return
+
+
+def clamp(value, limit):
+    return max(-limit - 1, min(limit, value))
+
+
+def render(data):
+    """The samples of `data`, as 16-bit values."""
+    out = []
+    target = dict(energy=0, pitch=0, k=[0] * 10)
+    x = [0] * 10
+    pitch_count = 0
+    lfsr = 1
+    stream = frames(data)
+    stopping = False
+    while not stopping:
+        current = dict(target, k=list(target["k"]))
+        frame = next(stream, None)
+        target = dict(current, k=list(current["k"]))
+        if frame is None:
+            # Fade out over one more frame.
+            stopping = True
+            target["energy"] = 0
+        elif frame[0] == "silence":
+            target["energy"] = 0
+        elif frame[0] == "repeat":
+            target["energy"] = ENERGY[frame[1]]
+            target["pitch"] = PITCH[frame[2]]
+        else:
+            _, energy, pitch, k = frame
+            target["energy"] = ENERGY[energy]
+            target["pitch"] = PITCH[pitch]
+            if pitch == 0:
+                target["k"] = [0] * 10
+            for i, index in enumerate(k):
+                target["k"][i] = K[i][index]
+        # Jump straight to the new values when speech starts
+        # from silence, or changes voicing.
+        starting = current["energy"] == 0
+        voicing = (current["pitch"] == 0) != (target["pitch"] == 0)
+        if target["energy"] != 0 and (starting or voicing):
+            current = dict(target, k=list(target["k"]))
+        for t in range(FRAME_SAMPLES):
+            if t > 0 and t % PERIOD_SAMPLES == 0:
+                shift = SHIFTS[t // PERIOD_SAMPLES]
+                current["energy"] += (target["energy"] - current["energy"]) >> shift
+                current["pitch"] += (target["pitch"] - current["pitch"]) >> shift
+                current["k"] = [
+                    c + ((g - c) >> shift) for c, g in zip(current["k"], target["k"])
+                ]
+            if current["pitch"] == 0:
+                bit = lfsr & 1
+                lfsr >>= 1
+                if bit:
+                    lfsr ^= 0xB800
+                excitation = NOISE if bit else -NOISE
+            else:
+                excitation = CHIRP[min(pitch_count, len(CHIRP) - 1)]
+                pitch_count += 1
+                if pitch_count >= current["pitch"]:
+                    pitch_count = 0
+            k = current["k"]
+            u = [0] * 11
+            u[10] = (excitation * current["energy"]) >> 3
+            for i in reversed(range(10)):
+                u[i] = clamp(u[i + 1] - ((k[i] * x[i]) >> 9), LATTICE_MAX)
+            for i in reversed(range(1, 10)):
+                x[i] = clamp(x[i - 1] + ((k[i - 1] * u[i - 1]) >> 9), LATTICE_MAX)
+            x[0] = u[0]
+            out.append(clamp(u[0], OUTPUT_MAX) << 4)
+    return out
+
+
+# Making words from formants.
+
+# F1 to F5 in Hz, for an adult male voice.
+VOWELS = {
+    "a": [730, 1090, 2440, 3300, 3750],
+    "e": [530, 1840, 2480, 3300, 3750],
+    "i": [270, 2290, 3010, 3400, 3800],
+    "o": [570, 840, 2410, 3300, 3750],
+    "u": [300, 870, 2240, 3300, 3750],
+}
+
+BANDWIDTHS = [60, 90, 120, 160, 220]
+
+
+def poly_mul(a, b):
+    product = [0] * (len(a) + len(b) - 1)
+    for i, x in enumerate(a):
+        for j, y in enumerate(b):
+            product[i + j] += x * y
+    return product
+
+
+def formant_poly(formants):
+    """Denominator of an all-pole filter with resonances at
+    `formants`."""
+    a = [1.0]
+    for freq, bandwidth in zip(formants, BANDWIDTHS):
+        r = math.exp(-math.pi * bandwidth / SAMPLE_RATE)
+        theta = 2 * math.pi * freq / SAMPLE_RATE
+        a = poly_mul(a, [1, -2 * r * math.cos(theta), r * r])
+    return a
+
+
+def step_down(a):
+    """Reflection coefficients of the filter `a`."""
+    a = list(a)
+    k = [0] * (len(a) - 1)
+    for m in reversed(range(1, len(a))):
+        k[m - 1] = a[m]
+        a = [(a[j] - a[m] * a[m - j]) / (1 - a[m] * a[m]) for j in range(m)]
+    return k
+
+
+def k_indices(formants):
+    k = step_down(formant_poly(formants))
+    return [
+        min(range(len(K[i])), key=lambda j: abs(K[i][j] / 512 - k[i])) for i in range(10)
+    ]
+
+
+def pitch_index(hz):
+    period = SAMPLE_RATE / hz
+    return min(range(1, 64), key=lambda i: abs(PITCH[i] - period))
+
+
+@functools.lru_cache(None)
+def loudest(formants, pitch):
+    """Highest energy index that keeps a steady vowel well
+    clear of clipping."""
+    k = k_indices(list(formants))
+    for energy in range(14, 0, -1):
+        out = render(pack(voiced(energy, pitch, k) + [(4, energy), (1, 1), (6, pitch)] * 3))
+        if max(map(abs, out)) < 20000:
+            return energy
+    return 1
+
+
+def vowel_frames(seq):
+    """Fields for `seq` of (formants, energy, pitch in Hz,
+    repeat)."""
+    fields = []
+    for formants, energy, hz, repeat in seq:
+        pitch = pitch_index(hz)
+        energy = min(energy, loudest(tuple(formants), pitch))
+        if repeat:
+            fields += [(4, energy), (1, 1), (6, pitch)]
+        else:
+            fields += voiced(energy, pitch, k_indices(formants))
+    return fields
+
+
+def mix(a, b, t):
+    return [x + (y - x) * t for x, y in zip(a, b)]
+
+
+def vowels():
+    fields = [(4, 0)]
+    hz = 120
+    for vowel in "aeiou":
+        formants = VOWELS[vowel]
+        seq = [(formants, 10, hz, False)]
+        seq += [(formants, 12, hz - 2 * n, True) for n in range(1, 5)]
+        seq += [(formants, 11, hz - 10, True), (formants, 9, hz - 12, True)]
+        seq += [(formants, 6, hz - 14, True)]
+        fields += vowel_frames(seq) + [(4, 0), (4, 0)]
+    return pack(fields + [(4, 15)])
+
+
+def hi():
+    a = VOWELS["a"]
+    i = VOWELS["i"]
+    # "h": breathy noise through the vowel's first
+    # coefficients.
+    fields = [(4, 0), (4, 6), (1, 0), (6, 0)] + list(zip(K_BITS[:4], k_indices(a)[:4]))
+    fields += [(4, 9), (1, 1), (6, 0)]
+    seq = [(a, 11, 125, False), (a, 12, 124, True), (a, 12, 122, True)]
+    seq += [(mix(a, i, n / 5), 12, 122 - 2 * n, False) for n in range(1, 6)]
+    seq += [(i, 12, 110, True), (i, 11, 106, True), (i, 9, 102, True)]
+    seq += [(i, 7, 100, True), (i, 4, 98, True)]
+    return pack(fields + vowel_frames(seq) + [(4, 0), (4, 15)])
+
+
+def rust_samples(name, samples):
+    lines = []
+    line = "       "
+    for s in samples:
+        item = f" {s:_},"
+        if len(line) + len(item) > 99:
+            lines.append(line)
+            line = "       "
+        line += item
+    lines.append(line)
+    return f"    const {name}: [Sample; {len(samples)}] = [\n" + "\n".join(lines) + "\n    ];\n"
+
+
+def rust_bytes(name, data):
+    out = f'    ("{name}", &[\n'
+    for row in range(0, len(data), 12):
+        out += "        " + ", ".join(f"0x{b:02x}" for b in data[row:row + 12]) + ",\n"
+    return out + "    ]),\n"
+
+
+def references():
+    k = [20, 20, 8, 8, 8, 8, 8, 3, 3, 3]
+    voiced_samples = render(pack(voiced(12, 45, k)))[:40]
+    unvoiced_samples = render(pack([(4, 12), (1, 0), (6, 0), (5, 20), (5, 10), (4, 8), (4, 6)]))[:40]
+    repeat_samples = render(pack(voiced(8, 45, k) + [(4, 13), (1, 1), (6, 40)]))[215:255]
+    return (
+        rust_samples("VOICED_REFERENCE", voiced_samples)
+        + rust_samples("UNVOICED_REFERENCE", unvoiced_samples)
+        + rust_samples("REPEAT_REFERENCE", repeat_samples)
+    )
+
+
+if __name__ == "__main__":
+    if sys.argv[1:] == ["references"]:
+        print(references(), end="")
+    elif sys.argv[1:] == ["words"]:
+        print(rust_bytes("Vowels", vowels()) + rust_bytes("Hi", hi()), end="")
+    else:
+        sys.exit(f"usage: {sys.argv[0]} references|words")
@@ -0,0 +1,38 @@
+//! Time between passes of the main loop, counted by TIMER2.
+//!
+//! The loops poll the controls, the display and the
+//! theremin with the time since the last pass, which is not
+//! just the delay between passes: listening for the tuner
+//! alone takes over 100ms. The timer free-runs at 1MHz and
+//! each reading takes whole milliseconds off it, so the
+//! remainder carries into the next.
+
+use microbit::hal::{prelude::*, timer::Periodic, Timer};
+use microbit::pac::TIMER2;
+
+const TICKS_PER_MS: u32 = Timer::<TIMER2>::TICKS_PER_SECOND / 1_000;
+
+pub struct Clock {
+    timer: Timer<TIMER2, Periodic>,
+    /// Counter value that has been accounted for.
+    last: u32,
+}
+
+impl Clock {
+    pub fn new(timer: TIMER2) -> Self {
+        let mut timer = Timer::periodic(timer);
+        // Wraps after 71 minutes, which the arithmetic below
+        // handles.
+        timer.start(u32::MAX);
+        let last = timer.read();
+        Self { timer, last }
+    }
+
+    /// Whole milliseconds since the last call, or since
+    /// [Self::new].
+    pub fn elapsed_ms(&mut self) -> u32 {
+        let ms = self.timer.read().wrapping_sub(self.last) / TICKS_PER_MS;
+        self.last = self.last.wrapping_add(ms * TICKS_PER_MS);
+        ms
+    }
+}
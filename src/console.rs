@@ -0,0 +1,213 @@
+//! Command console on the USB serial port.
+//!
+//! UARTE0 talks to the interface MCU, which presents it to
+//! the host as a USB CDC serial port. The UARTE0 interrupt
+//! queues bytes as they arrive; [Console::poll], called from
+//! the main loop, echoes them, parses each line as it is
+//! finished, and carries out the command on the
+//! [pwm_audio] synth.
+
+use core::cell::RefCell;
+use core::fmt::Write as _;
+
+use cortex_m::interrupt::Mutex;
+use embedded_hal::serial::{Read, Write};
+use mb2_audio::{
+    console::{Command, CommandError, CommandErrorKind, LineBuffer, HELP, LINE_LEN},
+    envelope::UNITY,
+    stream::SampleSource,
+};
+use microbit::board::UartPins;
+use microbit::hal::uarte::{Baudrate, Parity, Uarte, UarteRx, UarteTx};
+use microbit::pac::{self, interrupt, UARTE0};
+
+use crate::{controls, display, pwm_audio, theremin};
+
+/// How long the `mic` command listens for.
+const MIC_LEVEL_MS: u32 = 100;
+
+/// Bytes received but not yet handled by [Console::poll].
+const QUEUE_LEN: usize = 64;
+
+struct Receiver {
+    rx: UarteRx<UARTE0>,
+    queue: [u8; QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+static RECEIVER: Mutex<RefCell<Option<Receiver>>> = Mutex::new(RefCell::new(None));
+
+pub struct Console {
+    tx: UarteTx<UARTE0>,
+    line: LineBuffer<LINE_LEN>,
+}
+
+impl Console {
+    /// Run the console on `uarte` at 115200 baud.
+    pub fn new(uarte: UARTE0, pins: UartPins) -> Self {
+        let uarte = Uarte::new(uarte, pins.into(), Parity::EXCLUDED, Baudrate::BAUD115200);
+        let tx_buf = cortex_m::singleton!(: [u8; 64] = [0; 64]).unwrap();
+        let rx_buf = cortex_m::singleton!(: [u8; 1] = [0]).unwrap();
+        let (tx, mut rx) = uarte.split(tx_buf, rx_buf).unwrap();
+        // Start the first read: ENDRX fires when it completes.
+        let _ = rx.read();
+        unsafe { (*UARTE0::ptr()).intenset.write(|w| w.endrx().set()) };
+        let receiver = Receiver {
+            rx,
+            queue: [0; QUEUE_LEN],
+            head: 0,
+            len: 0,
+        };
+        cortex_m::interrupt::free(|cs| RECEIVER.borrow(cs).replace(Some(receiver)));
+        unsafe { pac::NVIC::unmask(pac::Interrupt::UARTE0_UART0) };
+
+        let mut console = Self {
+            tx,
+            line: LineBuffer::new(),
+        };
+        console.print(format_args!("\r\nmb2-audio: type help for commands\r\n> "));
+        console
+    }
+
+    /// Peak microphone level in percent of full scale over
+    /// [MIC_LEVEL_MS]. The microphone is borrowed from the
+    /// [pwm_audio] source, so this fails during loopback.
+    fn mic_level(&mut self) -> Option<u32> {
+        let mut mic = pwm_audio::with_source(|source| source.take_mic())?;
+        mic.start();
+        let mut left = mic.sample_rate() * MIC_LEVEL_MS / 1_000;
+        let mut peak = 0;
+        while left > 0 {
+            if let Some(sample) = mic.next_sample() {
+                peak = peak.max((sample as i32).unsigned_abs());
+                left -= 1;
+            }
+        }
+        mic.stop();
+        pwm_audio::with_source(|source| source.put_mic(mic));
+        Some(peak * 100 / 32_768)
+    }
+
+    fn print(&mut self, args: core::fmt::Arguments) {
+        let _ = self.tx.write_fmt(args);
+        let _ = nb::block!(self.tx.flush());
+    }
+
+    /// Handle whatever has been typed since the last call.
+    pub fn poll(&mut self) {
+        while let Some(byte) = next_byte() {
+            match byte {
+                b'\r' | b'\n' => self.print(format_args!("\r\n")),
+                0x08 | 0x7f => self.print(format_args!("\x08 \x08")),
+                b' '..=b'~' => self.print(format_args!("{}", byte as char)),
+                _ => (),
+            }
+            let Some(line) = self.line.push(byte) else {
+                continue;
+            };
+            match line.and_then(Command::parse) {
+                Ok(Command::Help) => {
+                    for line in HELP.lines() {
+                        self.print(format_args!("{}\r\n", line));
+                    }
+                }
+                Ok(Command::Mic) => match self.mic_level() {
+                    Some(percent) => self.print(format_args!("peak {}%\r\n", percent)),
+                    None => self.print(format_args!("error: microphone busy\r\n")),
+                },
+                Ok(Command::Theremin(mode)) => {
+                    if theremin::set_mode(mode) {
+                        self.print(format_args!("ok\r\n"));
+                    } else {
+                        self.print(format_args!("error: no accelerometer\r\n"));
+                    }
+                }
+                Ok(command) => {
+                    run(command);
+                    self.print(format_args!("ok\r\n"));
+                }
+                Err(CommandError {
+                    kind: CommandErrorKind::Empty,
+                    ..
+                }) => (),
+                Err(e) => self.print(format_args!("error: {}\r\n", e)),
+            }
+            self.print(format_args!("> "));
+        }
+    }
+}
+
+fn next_byte() -> Option<u8> {
+    cortex_m::interrupt::free(|cs| {
+        let mut receiver = RECEIVER.borrow(cs).borrow_mut();
+        let receiver = receiver.as_mut().unwrap();
+        if receiver.len == 0 {
+            return None;
+        }
+        let byte = receiver.queue[receiver.head];
+        receiver.head = (receiver.head + 1) % QUEUE_LEN;
+        receiver.len -= 1;
+        Some(byte)
+    })
+}
+
+fn run(command: Command) {
+    match command {
+        Command::Tone {
+            freq_hz,
+            duration_ms,
+        } => {
+            pwm_audio::with_synth(|synth| synth.play_tone(freq_hz, duration_ms));
+            pwm_audio::wake();
+        }
+        Command::Volume(percent) => {
+            let master = (percent as u32 * UNITY as u32 / 100) as u16;
+            pwm_audio::with_synth(|synth| synth.mixer.set_master(master));
+        }
+        Command::Wave(waveform) => {
+            pwm_audio::with_synth(|synth| synth.mixer.set_waveform(waveform));
+        }
+        Command::Play(song) => {
+            pwm_audio::with_synth(|synth| synth.play_song(song));
+            pwm_audio::wake();
+        }
+        Command::Stop => pwm_audio::with_synth(|synth| {
+            synth.stop();
+            synth.mixer.all_notes_off();
+        }),
+        Command::Gain(percent) => {
+            pwm_audio::with_source(|source| source.set_loop_gain(percent));
+        }
+        Command::Codec(codec) => {
+            pwm_audio::with_source(|source| source.set_loop_codec(codec));
+        }
+        Command::Meter(view) => display::set_view(view),
+        Command::Logo(mode) => controls::set_logo_mode(mode),
+        Command::Mic | Command::Theremin(_) | Command::Help => (),
+    }
+}
+
+#[interrupt]
+fn UARTE0_UART0() {
+    cortex_m::interrupt::free(|cs| {
+        let mut receiver = RECEIVER.borrow(cs).borrow_mut();
+        let receiver = receiver.as_mut().unwrap();
+        // Each read collects the finished byte; the next one
+        // starts another transfer and would block.
+        loop {
+            match receiver.rx.read() {
+                Ok(byte) => {
+                    // Drop bytes once the queue is full.
+                    if receiver.len < QUEUE_LEN {
+                        let tail = (receiver.head + receiver.len) % QUEUE_LEN;
+                        receiver.queue[tail] = byte;
+                        receiver.len += 1;
+                    }
+                }
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(_)) => (),
+            }
+        }
+    });
+}
@@ -0,0 +1,55 @@
+//! Buttons and touch logo.
+//!
+//! [Inputs::poll], called from the main loop, reads the
+//! buttons and the [TouchLogo] and hands them to the
+//! [Controls] state machine. The console sets what the logo
+//! does with [set_logo_mode].
+
+use core::cell::Cell;
+
+use cortex_m::interrupt::Mutex;
+use mb2_audio::controls::{Controls, Events, LogoMode};
+use microbit::gpio::{BTN_A, BTN_B};
+use microbit::hal::prelude::*;
+
+use crate::touch::TouchLogo;
+
+static LOGO_MODE: Mutex<Cell<LogoMode>> = Mutex::new(Cell::new(LogoMode::Gate));
+
+/// Choose what the touch logo does.
+pub fn set_logo_mode(mode: LogoMode) {
+    cortex_m::interrupt::free(|cs| LOGO_MODE.borrow(cs).set(mode));
+}
+
+pub struct Inputs {
+    button_a: BTN_A,
+    button_b: BTN_B,
+    logo: TouchLogo,
+    controls: Controls,
+}
+
+impl Inputs {
+    pub fn new(button_a: BTN_A, button_b: BTN_B, logo: TouchLogo) -> Self {
+        Self {
+            button_a,
+            button_b,
+            logo,
+            controls: Controls::new(),
+        }
+    }
+
+    /// Read the inputs, `elapsed_ms` after the last call.
+    pub fn poll(&mut self, elapsed_ms: u32) -> Events {
+        let mode = cortex_m::interrupt::free(|cs| LOGO_MODE.borrow(cs).get());
+        self.controls.set_logo_mode(mode);
+        let a = self.button_a.is_low().unwrap();
+        let b = self.button_b.is_low().unwrap();
+        let logo = self.logo.is_touched();
+        self.controls.update(elapsed_ms, a, b, logo)
+    }
+
+    /// The octave shift chosen with A and B together.
+    pub fn octave(&self) -> i8 {
+        self.controls.octave()
+    }
+}
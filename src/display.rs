@@ -0,0 +1,115 @@
+//! Level meter and tuner on the LED display.
+//!
+//! The non-blocking display driver refreshes the LEDs from
+//! the TIMER1 interrupt. The [pwm_audio] interrupt [push]es
+//! each sample it plays into a [Tap], and [Meter::poll],
+//! called from the main loop, draws a new frame from it
+//! [FRAME_HZ] times a second. The FFT for the spectrum runs
+//! in the main loop: only copying the tap and handing over
+//! the frame are done with interrupts off, so audio timing
+//! is not disturbed. The tuner view instead shows what the
+//! [Tuner] hears, as often as it can listen.
+//!
+//! [pwm_audio]: crate::pwm_audio
+
+use core::cell::{Cell, RefCell};
+
+use cortex_m::interrupt::Mutex;
+use mb2_audio::{
+    meter::{LevelMeter, Spectrum, Tap, View, FRAME_HZ, SPECTRUM_LEN},
+    tuner, Sample,
+};
+use microbit::display::nonblocking::{Display, GreyscaleImage};
+use microbit::gpio::DisplayPins;
+use microbit::pac::{self, interrupt, TIMER1};
+
+use crate::tuner::Tuner;
+
+/// Time between frames.
+const FRAME_MS: u32 = 1_000 / FRAME_HZ;
+
+static DISPLAY: Mutex<RefCell<Option<Display<TIMER1>>>> = Mutex::new(RefCell::new(None));
+
+static TAP: Mutex<RefCell<Tap<SPECTRUM_LEN>>> = Mutex::new(RefCell::new(Tap::new()));
+
+static VIEW: Mutex<Cell<View>> = Mutex::new(Cell::new(View::Level));
+
+/// Record a sample being played.
+pub fn push(sample: Sample) {
+    cortex_m::interrupt::free(|cs| TAP.borrow(cs).borrow_mut().push(sample));
+}
+
+/// Choose what the meter shows.
+pub fn set_view(view: View) {
+    cortex_m::interrupt::free(|cs| VIEW.borrow(cs).set(view));
+}
+
+/// Draws the played audio, or the note heard, on the
+/// display.
+pub struct Meter {
+    level: LevelMeter,
+    spectrum: Spectrum,
+    tuner: Tuner,
+    since_frame_ms: u32,
+}
+
+impl Meter {
+    /// Take over the display, refreshing it with `timer`.
+    pub fn new(timer: TIMER1, pins: DisplayPins) -> Self {
+        let display = Display::new(timer, pins);
+        cortex_m::interrupt::free(|cs| DISPLAY.borrow(cs).replace(Some(display)));
+        unsafe { pac::NVIC::unmask(pac::Interrupt::TIMER1) };
+        Self {
+            level: LevelMeter::new(),
+            spectrum: Spectrum::new(),
+            tuner: Tuner::new(),
+            since_frame_ms: 0,
+        }
+    }
+
+    /// Draw a frame if one is due, `elapsed_ms` after the
+    /// last call.
+    pub fn poll(&mut self, elapsed_ms: u32) {
+        self.since_frame_ms += elapsed_ms;
+        if self.since_frame_ms < FRAME_MS {
+            return;
+        }
+        self.since_frame_ms %= FRAME_MS;
+        let (view, peak, samples) = cortex_m::interrupt::free(|cs| {
+            let mut tap = TAP.borrow(cs).borrow_mut();
+            (VIEW.borrow(cs).get(), tap.take_peak(), tap.recent())
+        });
+        let frame = match view {
+            View::Off => [[0; 5]; 5],
+            View::Level => self.level.update(peak),
+            // Nothing played since the last frame: the tap
+            // holds old samples.
+            View::Spectrum if peak == 0 => self.spectrum.update(&[0; SPECTRUM_LEN]),
+            View::Spectrum => self.spectrum.update(&samples),
+            View::Tuner => tuner::frame(self.tuner.listen()),
+        };
+        let image = GreyscaleImage::new(&frame);
+        cortex_m::interrupt::free(|cs| {
+            if let Some(display) = DISPLAY.borrow(cs).borrow_mut().as_mut() {
+                display.show(&image);
+            }
+        });
+    }
+
+    /// Frequency of the note last heard by the tuner, if it
+    /// is showing.
+    pub fn reference_hz(&self) -> Option<f32> {
+        let view = cortex_m::interrupt::free(|cs| VIEW.borrow(cs).get());
+        let reading = self.tuner.last_reading().filter(|_| view == View::Tuner)?;
+        Some(reading.reference_hz())
+    }
+}
+
+#[interrupt]
+fn TIMER1() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(display) = DISPLAY.borrow(cs).borrow_mut().as_mut() {
+            display.handle_display_event();
+        }
+    });
+}
@@ -1,24 +1,281 @@
 #![no_main]
 #![no_std]
 
+mod clock;
+mod console;
+mod controls;
+mod display;
+mod mic;
+mod midi_in;
+mod pwm_audio;
+mod theremin;
+mod timer_tone;
+mod touch;
+mod tuner;
+
 use panic_halt as _;
 
 use cortex_m_rt::entry;
+use mb2_audio::{
+    controls::Event,
+    envelope::{Adsr, UNITY},
+    osc::Waveform,
+    songs,
+    square::SquareWave,
+    synth::Synth,
+    tuning,
+};
 use microbit::Board;
-use microbit::hal::{prelude::*, delay::Delay, gpio::Level};
+use microbit::pac::PWM0;
+use microbit::gpio::{BTN_A, BTN_B};
+use microbit::hal::{
+    prelude::*,
+    delay::Delay,
+    gpio::{Level, Output, Pin, PushPull},
+};
+use clock::Clock;
+use console::Console;
+use controls::Inputs;
+use display::Meter;
+use mic::MicInput;
+use pwm_audio::PwmAudioOut;
+use theremin::Accelerometer;
+use touch::TouchLogo;
+
+/// Frequency of the tone played while button A is held.
+const TONE_HZ: f32 = 1_000.0;
+
+/// Duty cycle of the tone played while button A is held.
+const TONE_DUTY: f32 = 0.5;
+
+/// Chord played on the PWM while button A is held: A
+/// major, shifted by the octave chosen with the buttons.
+const CHORD_HZ: [f32; 3] = [440.0, 554.37, 659.26];
+
+/// Mixer keys of the notes of the chord: above the MIDI
+/// note range, so that [midi_in] notes can't release them,
+/// and clear of the synth's tone and the theremin.
+const CHORD_KEYS: [u8; 3] = [130, 131, 132];
+
+/// Gain of each note of the chord.
+const CHORD_GAIN: u16 = UNITY / 3;
+
+/// Envelope of PWM tones.
+const TONE_ADSR: Adsr = Adsr {
+    attack_ms: 10,
+    decay_ms: 100,
+    sustain: UNITY / 10 * 7,
+    release_ms: 300,
+};
+
+/// Length of the tuner's reference tone.
+const REFERENCE_MS: u32 = 2_000;
+
+/// Sample rate of PWM output.
+const PWM_SAMPLE_RATE: u32 = 16_000;
+
+/// Sample rate of microphone input.
+const MIC_SAMPLE_RATE: u32 = 16_000;
+
+/// Delay between checks of the buttons when the tone is
+/// generated in the background. The PWM modes time their
+/// controls with a [Clock] instead, as a pass can take far
+/// longer.
+const POLL_MS: u8 = 10;
 
 #[entry]
 fn main() -> ! {
     let board = Board::take().unwrap();
-    let mut delay = Delay::new(board.SYST);
-    let mut speaker = board.speaker_pin.into_push_pull_output(Level::Low);
-    let button = board.buttons.button_a;
+    let delay = Delay::new(board.SYST);
+    let speaker = board.speaker_pin.into_push_pull_output(Level::Low).degrade();
+    let button_a = board.buttons.button_a;
+    let button_b = board.buttons.button_b;
+    let wave = SquareWave::new(TONE_HZ, TONE_DUTY).unwrap();
+
+    // The buttons held during reset select the output:
+    // button B for the timer interrupt, button A for PWM,
+    // and both for the PWM jukebox. The PWM modes also take
+    // commands from the USB serial console, and show the
+    // output level on the display.
+    let a_held = button_a.is_low().unwrap();
+    let b_held = button_b.is_low().unwrap();
+    if a_held && b_held {
+        let mic = MicInput::new(board.SAADC, board.microphone_pins, MIC_SAMPLE_RATE).unwrap();
+        init_pwm(board.PWM0, speaker, mic);
+        let console = Console::new(board.UARTE0, board.uart);
+        let meter = Meter::new(board.TIMER1, board.display_pins);
+        let clock = Clock::new(board.TIMER2);
+        run_jukebox(button_a, button_b, delay, clock, console, meter)
+    } else if b_held {
+        timer_tone::init(board.TIMER0, speaker, &wave);
+        run_timer(button_a, delay)
+    } else if a_held {
+        let mic = MicInput::new(board.SAADC, board.microphone_pins, MIC_SAMPLE_RATE).unwrap();
+        init_pwm(board.PWM0, speaker, mic);
+        let midi_rx = board.pins.p0_03.into_floating_input().degrade();
+        let midi_tx = board.pins.p0_02.into_push_pull_output(Level::High).degrade();
+        midi_in::init(board.UARTE1, midi_rx, midi_tx);
+        let console = Console::new(board.UARTE0, board.uart);
+        let meter = Meter::new(board.TIMER1, board.display_pins);
+        let inputs = Inputs::new(button_a, button_b, TouchLogo::new(board.pins.p1_04));
+        // Without the accelerometer, only the theremin is
+        // lost.
+        let accel = Accelerometer::new(board.TWIM0, board.i2c_internal).ok();
+        let theremin = theremin::Player::new(accel);
+        let clock = Clock::new(board.TIMER2);
+        run_pwm(inputs, delay, clock, console, meter, theremin)
+    } else {
+        run_bitbang(button_a, delay, speaker, &wave)
+    }
+}
+
+/// Set up [pwm_audio] to stream a [Synth] on the speaker,
+/// with `mic` for loopback.
+fn init_pwm(pwm0: PWM0, speaker: Pin<Output<PushPull>>, mic: MicInput) {
+    let out = PwmAudioOut::new(pwm0, speaker, PWM_SAMPLE_RATE).unwrap();
+    let synth = Synth::new(out.sample_rate(), Waveform::Square, TONE_ADSR);
+    pwm_audio::init(out, pwm_audio::Source::new(synth, Some(mic)));
+}
+
+/// Toggle the speaker by hand, busy-waiting between edges.
+fn run_bitbang(
+    button: BTN_A,
+    mut delay: Delay,
+    mut speaker: Pin<Output<PushPull>>,
+    wave: &SquareWave,
+) -> ! {
+    let (high_us, low_us) = wave.periods_us();
     loop {
         if button.is_low().unwrap() {
             speaker.set_high().unwrap();
-            delay.delay_us(500u16);
+            delay.delay_us(high_us);
             speaker.set_low().unwrap();
-            delay.delay_us(500u16);
+            delay.delay_us(low_us);
+        }
+    }
+}
+
+/// Let the [timer_tone] interrupt toggle the speaker, and
+/// just start and stop it as button A changes.
+fn run_timer(button: BTN_A, mut delay: Delay) -> ! {
+    let mut playing = false;
+    loop {
+        let pressed = button.is_low().unwrap();
+        if pressed != playing {
+            if pressed {
+                timer_tone::start();
+            } else {
+                timer_tone::stop();
+            }
+            playing = pressed;
+        }
+        delay.delay_ms(POLL_MS);
+    }
+}
+
+/// Stream a chord through the PWM while button A or the
+/// touch logo is held, fading in and out with the envelope,
+/// and play whatever arrives from [midi_in]. See
+/// [mb2_audio::controls] for what the other controls do.
+/// When the display is showing the tuner, the gate plays
+/// the note it last heard instead. The console can also
+/// switch on the [theremin].
+fn run_pwm(
+    mut inputs: Inputs,
+    mut delay: Delay,
+    mut clock: Clock,
+    mut console: Console,
+    mut meter: Meter,
+    mut theremin: theremin::Player,
+) -> ! {
+    let mut chord = false;
+    loop {
+        let elapsed_ms = clock.elapsed_ms();
+        for event in inputs.poll(elapsed_ms) {
+            match event {
+                Event::Gate(on) => {
+                    if on {
+                        pwm_audio::wake();
+                    }
+                    let reference = meter.reference_hz().filter(|_| on);
+                    let shift = tuning::semitone_ratio(12.0 * inputs.octave() as f32);
+                    pwm_audio::with_synth(|synth| {
+                        for (&key, &freq) in CHORD_KEYS.iter().zip(&CHORD_HZ) {
+                            if on && reference.is_none() {
+                                // Struck again if the pedal
+                                // was still holding it.
+                                synth.mixer.note_on(key, freq * shift, CHORD_GAIN);
+                                synth.mixer.retrigger(key);
+                            } else if chord {
+                                // Released only if it was started:
+                                // the tuner may have heard a note
+                                // since.
+                                synth.mixer.note_off(key);
+                            }
+                        }
+                        if let Some(freq) = reference {
+                            synth.play_tone(freq, REFERENCE_MS);
+                        }
+                    });
+                    chord = on && reference.is_none();
+                }
+                Event::NextWaveform => pwm_audio::with_synth(|synth| {
+                    let waveform = synth.mixer.voice(0).tone.waveform().next();
+                    synth.mixer.set_waveform(waveform);
+                }),
+                Event::ToggleLoopback => {
+                    let looping = pwm_audio::with_source(|source| {
+                        let looping = !source.is_looping();
+                        source.set_looping(looping)
+                    });
+                    if looping {
+                        pwm_audio::wake();
+                    }
+                }
+                // Picked up by the next note.
+                Event::Octave(_) => (),
+            }
+        }
+        theremin.poll(elapsed_ms);
+        // Only stops once the release has finished.
+        pwm_audio::sleep_if_idle();
+        console.poll();
+        meter.poll(elapsed_ms);
+        delay.delay_ms(POLL_MS);
+    }
+}
+
+/// Play the built-in ringtones, MIDI files and clips
+/// through the PWM: button A starts the next one, button B
+/// stops.
+fn run_jukebox(
+    button_a: BTN_A,
+    button_b: BTN_B,
+    mut delay: Delay,
+    mut clock: Clock,
+    mut console: Console,
+    mut meter: Meter,
+) -> ! {
+    let mut song = 0;
+    let mut a_was_pressed = false;
+    let mut b_was_pressed = false;
+    loop {
+        let a_pressed = button_a.is_low().unwrap();
+        if a_pressed && !a_was_pressed {
+            let next = songs::all().nth(song).unwrap();
+            pwm_audio::with_synth(|synth| synth.play_song(next));
+            pwm_audio::wake();
+            song = (song + 1) % songs::all().count();
+        }
+        a_was_pressed = a_pressed;
+        let b_pressed = button_b.is_low().unwrap();
+        if b_pressed && !b_was_pressed {
+            pwm_audio::with_synth(|synth| synth.stop());
         }
+        b_was_pressed = b_pressed;
+        console.poll();
+        meter.poll(clock.elapsed_ms());
+        pwm_audio::sleep_if_idle();
+        delay.delay_ms(POLL_MS);
     }
 }
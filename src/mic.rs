@@ -0,0 +1,169 @@
+//! Microphone capture through the SAADC.
+//!
+//! The microphone's output is on AIN3, and it is powered
+//! from its run pin. The SAADC samples it on its internal
+//! timer into two DMA buffers, and PPI channel 0 restarts
+//! the SAADC into the next buffer each time one fills, so
+//! no samples are missed as long as [MicInput] is read at
+//! least once per buffer.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use mb2_audio::{
+    capture::{Capture, Sampler},
+    filter::DcBlocker,
+    saadc::{self, SaadcError},
+    stream::SampleSource,
+    Sample,
+};
+use microbit::gpio::MicrophonePins;
+use microbit::hal::{
+    gpio::{p0::P0_20, OpenDrain, Output},
+    prelude::*,
+};
+use microbit::pac::{self, SAADC};
+
+/// Length in samples of each DMA buffer.
+pub const BUF_LEN: usize = 256;
+
+/// Cutoff of the DC-blocking filter.
+const DC_CUTOFF_HZ: f32 = 20.0;
+
+/// PPI channel restarting the SAADC.
+const PPI_CHANNEL: usize = 0;
+
+type Buffer = &'static mut [Sample; BUF_LEN];
+
+/// The SAADC and its buffers.
+pub struct Saadc {
+    saadc: SAADC,
+    bufs: [Buffer; 2],
+}
+
+impl Sampler for Saadc {
+    fn set_buffer(&mut self, index: usize) {
+        let ptr = self.bufs[index].as_ptr() as u32;
+        self.saadc.result.ptr.write(|w| unsafe { w.ptr().bits(ptr) });
+    }
+
+    fn buffer(&self, index: usize) -> &[Sample] {
+        compiler_fence(Ordering::SeqCst);
+        &self.bufs[index][..]
+    }
+
+    fn start(&mut self) {
+        self.saadc.tasks_start.write(|w| unsafe { w.bits(1) });
+        // The sample timer only runs once a transfer has
+        // started. The event is left for the capture to see.
+        while self.saadc.events_started.read().bits() == 0 {}
+        self.saadc.tasks_sample.write(|w| unsafe { w.bits(1) });
+    }
+
+    fn stop(&mut self) {
+        self.saadc.tasks_stop.write(|w| unsafe { w.bits(1) });
+        while self.saadc.events_stopped.read().bits() == 0 {}
+        self.saadc.events_stopped.reset();
+    }
+
+    fn take_started(&mut self) -> bool {
+        let started = self.saadc.events_started.read().bits() != 0;
+        if started {
+            self.saadc.events_started.reset();
+        }
+        started
+    }
+
+    fn take_end(&mut self) -> bool {
+        let end = self.saadc.events_end.read().bits() != 0;
+        if end {
+            self.saadc.events_end.reset();
+        }
+        end
+    }
+}
+
+/// The microphone as a [SampleSource], with its DC offset
+/// removed.
+pub struct MicInput {
+    capture: Capture<Saadc>,
+    dc: DcBlocker,
+    run: P0_20<Output<OpenDrain>>,
+    sample_rate: u32,
+}
+
+impl MicInput {
+    /// Set up capture at `sample_rate`, with the microphone
+    /// powered down until [Self::start].
+    pub fn new(saadc: SAADC, pins: MicrophonePins, sample_rate: u32) -> Result<Self, SaadcError> {
+        let cc = saadc::sample_cc(sample_rate)?;
+        // The 12-bit result range covers 0V to VDD.
+        saadc.resolution.write(|w| w.val()._12bit());
+        saadc.oversample.write(|w| w.oversample().bypass());
+        saadc.ch[0].pselp.write(|w| w.pselp().analog_input3());
+        saadc.ch[0].pseln.write(|w| w.pseln().nc());
+        saadc.ch[0].config.write(|w| {
+            w.gain().gain1_4();
+            w.refsel().vdd1_4();
+            w.tacq()._3us();
+            w.mode().se();
+            w.resp().bypass();
+            w.resn().bypass();
+            w.burst().disabled()
+        });
+        saadc
+            .samplerate
+            .write(|w| unsafe { w.cc().bits(cc).mode().timers() });
+        saadc
+            .result
+            .maxcnt
+            .write(|w| unsafe { w.maxcnt().bits(BUF_LEN as u16) });
+        saadc.enable.write(|w| w.enable().enabled());
+
+        // The board support crate does not hand out the PPI,
+        // and nothing else uses it.
+        let ppi = unsafe { &*pac::PPI::ptr() };
+        let ch = &ppi.ch[PPI_CHANNEL];
+        ch.eep
+            .write(|w| unsafe { w.bits(&saadc.events_end as *const _ as u32) });
+        ch.tep
+            .write(|w| unsafe { w.bits(&saadc.tasks_start as *const _ as u32) });
+        ppi.chenset.write(|w| unsafe { w.bits(1 << PPI_CHANNEL) });
+
+        let buf0 = cortex_m::singleton!(: [Sample; BUF_LEN] = [0; BUF_LEN]).unwrap();
+        let buf1 = cortex_m::singleton!(: [Sample; BUF_LEN] = [0; BUF_LEN]).unwrap();
+        let sampler = Saadc {
+            saadc,
+            bufs: [buf0, buf1],
+        };
+        Ok(Self {
+            capture: Capture::new(sampler),
+            dc: DcBlocker::new(sample_rate, DC_CUTOFF_HZ),
+            run: pins.mic_run,
+            sample_rate,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Power up the microphone and start capturing.
+    pub fn start(&mut self) {
+        self.run.set_high().unwrap();
+        self.dc.reset();
+        self.capture.start();
+    }
+
+    /// Stop capturing and power down the microphone.
+    pub fn stop(&mut self) {
+        self.capture.stop();
+        self.run.set_low().unwrap();
+    }
+}
+
+impl SampleSource for MicInput {
+    fn next_sample(&mut self) -> Option<Sample> {
+        let raw = self.capture.next_sample()?;
+        Some(self.dc.process(saadc::to_sample(raw)))
+    }
+}
@@ -0,0 +1,84 @@
+//! MIDI input on the edge connector, played on the
+//! [pwm_audio] synth.
+//!
+//! UARTE1 receives on ring 1 (P0.03) at the MIDI baud rate,
+//! one byte per EasyDMA transfer. Ring 0 (P0.02) is claimed
+//! as the unused transmit pin. The UARTE1 interrupt feeds
+//! each byte to the parser as it arrives, so messages are
+//! played as soon as they are complete.
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use embedded_hal::serial::Read;
+use mb2_audio::midi::{Instrument, Message, Parser};
+use microbit::hal::{
+    gpio::{Floating, Input, Output, Pin, PushPull},
+    uarte::{self, Baudrate, Parity, Uarte, UarteRx},
+};
+use microbit::pac::{self, interrupt, UARTE1};
+
+use crate::pwm_audio;
+
+struct MidiIn {
+    rx: UarteRx<UARTE1>,
+    parser: Parser,
+    instrument: Instrument,
+}
+
+static MIDI_IN: Mutex<RefCell<Option<MidiIn>>> = Mutex::new(RefCell::new(None));
+
+/// Receive MIDI on `rxd` and play it. [pwm_audio] must
+/// already be set up.
+pub fn init(uarte: UARTE1, rxd: Pin<Input<Floating>>, txd: Pin<Output<PushPull>>) {
+    let pins = uarte::Pins {
+        rxd,
+        txd,
+        cts: None,
+        rts: None,
+    };
+    let uarte = Uarte::new(uarte, pins, Parity::EXCLUDED, Baudrate::BAUD31250);
+    let tx_buf = cortex_m::singleton!(: [u8; 1] = [0]).unwrap();
+    let rx_buf = cortex_m::singleton!(: [u8; 1] = [0]).unwrap();
+    let (_, mut rx) = uarte.split(tx_buf, rx_buf).unwrap();
+    // Start the first read: ENDRX fires when it completes.
+    let _ = rx.read();
+    unsafe { (*UARTE1::ptr()).intenset.write(|w| w.endrx().set()) };
+    let midi_in = MidiIn {
+        rx,
+        parser: Parser::new(),
+        instrument: Instrument::new(),
+    };
+    cortex_m::interrupt::free(|cs| MIDI_IN.borrow(cs).replace(Some(midi_in)));
+    unsafe { pac::NVIC::unmask(pac::Interrupt::UARTE1) };
+}
+
+#[interrupt]
+fn UARTE1() {
+    cortex_m::interrupt::free(|cs| {
+        let mut midi_in = MIDI_IN.borrow(cs).borrow_mut();
+        let MidiIn {
+            rx,
+            parser,
+            instrument,
+        } = midi_in.as_mut().unwrap();
+        // Each read collects the finished byte; the next one
+        // starts another transfer and would block.
+        loop {
+            match rx.read() {
+                Ok(byte) => {
+                    let Some(message) = parser.feed(byte) else {
+                        continue;
+                    };
+                    pwm_audio::with_synth(|synth| instrument.handle(message, &mut synth.mixer));
+                    if let Message::NoteOn { .. } = message {
+                        pwm_audio::wake();
+                    }
+                }
+                Err(nb::Error::WouldBlock) => break,
+                // Framing error or the like: drop the byte.
+                Err(nb::Error::Other(_)) => (),
+            }
+        }
+    });
+}
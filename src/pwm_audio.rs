@@ -0,0 +1,320 @@
+//! Sample playback on the speaker through the PWM0
+//! peripheral.
+//!
+//! PWM0 runs at the sample rate, so each PWM period plays
+//! one sample. EasyDMA feeds the duty values from the SEQ0
+//! and SEQ1 sequence buffers without any CPU involvement,
+//! and the PWM0 interrupt refills whichever buffer has just
+//! finished playing.
+//!
+//! What gets played is the synth, plus the microphone while
+//! loopback is on. Each sample also goes to the [display]
+//! meter.
+
+use core::cell::RefCell;
+use core::sync::atomic::AtomicU32;
+
+use cortex_m::interrupt::Mutex;
+use mb2_audio::{
+    g711::Law,
+    loopback::Loopback,
+    pwm::{self, PwmError, PWM_CLOCK_HZ},
+    stream::{self, SampleSource, Sequencer, Streamer},
+    synth::Synth,
+    Sample, ToneGenerator,
+};
+use microbit::hal::{
+    gpio::{Output, Pin, PushPull},
+    pwm::{Channel, LoadMode, Pwm, PwmEvent, PwmSeq, Seq},
+};
+use microbit::pac::{self, interrupt, PWM0};
+
+use crate::display;
+use crate::mic::MicInput;
+
+/// Length in samples of each of the SEQ0 and SEQ1 buffers.
+pub const SEQ_LEN: usize = 256;
+
+type SeqBuffer = &'static mut [u16; SEQ_LEN];
+
+enum State {
+    Stopped {
+        pwm: Pwm<PWM0>,
+        seq0: SeqBuffer,
+        seq1: SeqBuffer,
+    },
+    Playing(PwmSeq<PWM0, SeqBuffer, SeqBuffer>),
+}
+
+/// PWM audio output on the speaker pin.
+pub struct PwmAudioOut {
+    // Only `None` transiently, while changing state.
+    state: Option<State>,
+    top: u16,
+}
+
+impl PwmAudioOut {
+    /// Take over `pwm` and `speaker` for playback at the
+    /// sample rate nearest `sample_rate`. Must only be
+    /// called once, since it claims static sequence buffers.
+    pub fn new(
+        pwm: PWM0,
+        speaker: Pin<Output<PushPull>>,
+        sample_rate: u32,
+    ) -> Result<Self, PwmError> {
+        let top = pwm::countertop(sample_rate)?;
+        let pwm = Pwm::new(pwm);
+        // `Pwm::new()` has already set up an undivided
+        // up-counter with each sequence value used once.
+        pwm.set_output_pin(Channel::C0, speaker)
+            .set_max_duty(top)
+            .set_load_mode(LoadMode::Common)
+            .loop_inf()
+            .enable_interrupt(PwmEvent::SeqEnd(Seq::Seq0))
+            .enable_interrupt(PwmEvent::SeqEnd(Seq::Seq1));
+        let seq0 = cortex_m::singleton!(: [u16; SEQ_LEN] = [0; SEQ_LEN]).unwrap();
+        let seq1 = cortex_m::singleton!(: [u16; SEQ_LEN] = [0; SEQ_LEN]).unwrap();
+        Ok(Self {
+            state: Some(State::Stopped { pwm, seq0, seq1 }),
+            top,
+        })
+    }
+
+    /// Actual sample rate of the output.
+    pub fn sample_rate(&self) -> u32 {
+        PWM_CLOCK_HZ / self.top as u32
+    }
+
+    fn is_event_triggered(&self, event: PwmEvent) -> bool {
+        match self.state.as_ref().unwrap() {
+            State::Stopped { pwm, .. } => pwm.is_event_triggered(event),
+            State::Playing(seq) => seq.is_event_triggered(event),
+        }
+    }
+
+    fn reset_event(&self, event: PwmEvent) {
+        match self.state.as_ref().unwrap() {
+            State::Stopped { pwm, .. } => pwm.reset_event(event),
+            State::Playing(seq) => seq.reset_event(event),
+        }
+    }
+}
+
+fn hal_seq(seq: stream::Seq) -> Seq {
+    match seq {
+        stream::Seq::Seq0 => Seq::Seq0,
+        stream::Seq::Seq1 => Seq::Seq1,
+    }
+}
+
+impl Sequencer for PwmAudioOut {
+    fn countertop(&self) -> u16 {
+        self.top
+    }
+
+    fn with_buffer<F: FnOnce(&mut [u16])>(&mut self, seq: stream::Seq, f: F) {
+        let pick = |seq0: &mut SeqBuffer, seq1: &mut SeqBuffer| match seq {
+            stream::Seq::Seq0 => f(&mut seq0[..]),
+            stream::Seq::Seq1 => f(&mut seq1[..]),
+        };
+        self.state = Some(match self.state.take().unwrap() {
+            State::Stopped {
+                pwm,
+                mut seq0,
+                mut seq1,
+            } => {
+                pick(&mut seq0, &mut seq1);
+                State::Stopped { pwm, seq0, seq1 }
+            }
+            State::Playing(playing) => {
+                // The HAL owns the buffers while they play, so
+                // take them back and hand them over again. This
+                // only rewrites the sequence pointers, with the
+                // same values: playback is not disturbed.
+                let (seq0, seq1, pwm) = playing.split();
+                let (mut seq0, mut seq1) = (seq0.unwrap(), seq1.unwrap());
+                pick(&mut seq0, &mut seq1);
+                State::Playing(pwm.load(Some(seq0), Some(seq1), false).ok().unwrap())
+            }
+        });
+    }
+
+    fn start(&mut self) {
+        self.state = Some(match self.state.take().unwrap() {
+            State::Stopped { pwm, seq0, seq1 } => {
+                // Don't ask `load()` to start playback: it would
+                // start SEQ0 and then immediately switch to SEQ1.
+                let playing = pwm.load(Some(seq0), Some(seq1), false).ok().unwrap();
+                playing.start_seq(Seq::Seq0);
+                State::Playing(playing)
+            }
+            State::Playing(playing) => {
+                playing.start_seq(Seq::Seq0);
+                State::Playing(playing)
+            }
+        });
+    }
+
+    fn stop(&mut self) {
+        match self.state.take().unwrap() {
+            State::Playing(playing) => {
+                playing.stop();
+                let (seq0, seq1, pwm) = playing.split();
+                self.state = Some(State::Stopped {
+                    pwm,
+                    seq0: seq0.unwrap(),
+                    seq1: seq1.unwrap(),
+                });
+            }
+            stopped => self.state = Some(stopped),
+        }
+    }
+
+    fn take_seq_end(&mut self, seq: stream::Seq) -> bool {
+        let event = PwmEvent::SeqEnd(hal_seq(seq));
+        let triggered = self.is_event_triggered(event);
+        if triggered {
+            self.reset_event(event);
+        }
+        triggered
+    }
+}
+
+/// Number of voices that can sound at once.
+pub const VOICES: usize = 4;
+
+/// What the PWM streams: the synth, mixed with the
+/// microphone when looping it back.
+pub struct Source {
+    pub synth: Synth<'static, VOICES>,
+    mic: Option<MicInput>,
+    loopback: Loopback,
+    looping: bool,
+}
+
+impl Source {
+    pub fn new(synth: Synth<'static, VOICES>, mic: Option<MicInput>) -> Self {
+        let loopback = Loopback::new(synth.sample_rate());
+        Self {
+            synth,
+            mic,
+            loopback,
+            looping: false,
+        }
+    }
+
+    /// Start or stop playing the microphone. Returns whether
+    /// loopback is now on: it can't be without a microphone.
+    pub fn set_looping(&mut self, looping: bool) -> bool {
+        let Some(mic) = self.mic.as_mut() else {
+            return false;
+        };
+        if looping && !self.looping {
+            self.loopback.reset();
+            mic.start();
+        } else if !looping && self.looping {
+            mic.stop();
+        }
+        self.looping = looping;
+        looping
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Set the loopback gain in percent.
+    pub fn set_loop_gain(&mut self, percent: u16) {
+        self.loopback.set_gain(percent);
+    }
+
+    /// Put the loopback through a G.711 codec, or not.
+    pub fn set_loop_codec(&mut self, codec: Option<Law>) {
+        self.loopback.set_codec(codec);
+    }
+
+    /// Borrow the microphone, unless it is being played.
+    /// Give it back with [Self::put_mic].
+    pub fn take_mic(&mut self) -> Option<MicInput> {
+        if self.looping {
+            return None;
+        }
+        self.mic.take()
+    }
+
+    pub fn put_mic(&mut self, mic: MicInput) {
+        self.mic = Some(mic);
+    }
+
+    /// True while there is anything to play.
+    pub fn is_active(&self) -> bool {
+        self.looping || self.synth.is_active()
+    }
+}
+
+impl SampleSource for Source {
+    fn next_sample(&mut self) -> Option<Sample> {
+        let mut sample = ToneGenerator::next_sample(&mut self.synth);
+        if self.looping {
+            // Until the microphone has a buffer ready, only
+            // the synth plays.
+            if let Some(mic) = self.mic.as_mut().and_then(|mic| mic.next_sample()) {
+                sample = sample.saturating_add(self.loopback.process(mic));
+            }
+        }
+        display::push(sample);
+        Some(sample)
+    }
+}
+
+static STREAM: Mutex<RefCell<Option<Streamer<'static, PwmAudioOut, Source>>>> =
+    Mutex::new(RefCell::new(None));
+
+static UNDERRUNS: AtomicU32 = AtomicU32::new(0);
+
+/// Stream `source` through `out`, refilling from the PWM0
+/// interrupt. Playback is silent until [wake] is called.
+pub fn init(out: PwmAudioOut, source: Source) {
+    let streamer = Streamer::new(out, source, &UNDERRUNS);
+    cortex_m::interrupt::free(|cs| STREAM.borrow(cs).replace(Some(streamer)));
+    unsafe { pac::NVIC::unmask(pac::Interrupt::PWM0) };
+}
+
+/// Start streaming if not already doing so.
+pub fn wake() {
+    with_streamer(|streamer| {
+        if !streamer.is_running() {
+            streamer.start();
+        }
+    });
+}
+
+/// Stop streaming once the source has fallen silent.
+pub fn sleep_if_idle() {
+    with_streamer(|streamer| {
+        if streamer.is_running() && !streamer.source().is_active() {
+            streamer.stop();
+        }
+    });
+}
+
+/// Run `f` on the source being streamed.
+pub fn with_source<R>(f: impl FnOnce(&mut Source) -> R) -> R {
+    with_streamer(|streamer| f(streamer.source_mut()))
+}
+
+/// Run `f` on the synth being streamed.
+pub fn with_synth<R>(f: impl FnOnce(&mut Synth<'static, VOICES>) -> R) -> R {
+    with_source(|source| f(&mut source.synth))
+}
+
+/// Run `f` on the streamer, which must have been set up by
+/// [init].
+fn with_streamer<R>(f: impl FnOnce(&mut Streamer<'static, PwmAudioOut, Source>) -> R) -> R {
+    cortex_m::interrupt::free(|cs| f(STREAM.borrow(cs).borrow_mut().as_mut().unwrap()))
+}
+
+#[interrupt]
+fn PWM0() {
+    with_streamer(|streamer| streamer.on_seq_end());
+}
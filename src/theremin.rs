@@ -0,0 +1,136 @@
+//! Theremin on the accelerometer.
+//!
+//! The LSM303AGR on the internal I2C bus measures the tilt
+//! of the board. While the console has the theremin on,
+//! [Player::poll], called from the main loop, reads it and
+//! plays a [Theremin] note on the [pwm_audio] synth. If the
+//! accelerometer didn't start, the theremin stays off and
+//! the rest of the firmware carries on without it.
+//!
+//! [pwm_audio]: crate::pwm_audio
+
+use core::cell::Cell;
+
+use cortex_m::interrupt::Mutex;
+use mb2_audio::theremin::{Mode, Theremin, THEREMIN_KEY};
+use microbit::board::I2CInternalPins;
+use microbit::hal::twim::{self, Twim};
+use microbit::pac::TWIM0;
+
+use crate::pwm_audio;
+
+/// I2C address of the LSM303AGR accelerometer.
+const ADDRESS: u8 = 0x19;
+
+const CTRL_REG1_A: u8 = 0x20;
+const CTRL_REG4_A: u8 = 0x23;
+const OUT_X_L_A: u8 = 0x28;
+
+/// Set on a register address to read on through the
+/// following registers.
+const AUTO_INCREMENT: u8 = 0x80;
+
+/// 100Hz output, with all three axes on.
+const CTRL_REG1_100HZ_XYZ: u8 = 0x57;
+
+/// Whole readings only, ±2g full scale, and 12-bit high
+/// resolution: 1mg per count.
+const CTRL_REG4_BDU_2G_HR: u8 = 0x88;
+
+static MODE: Mutex<Cell<Mode>> = Mutex::new(Cell::new(Mode::Off));
+
+/// Set by a [Player] with an accelerometer.
+static AVAILABLE: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+/// Switch the theremin on or off. Returns false, leaving it
+/// off, if there is no accelerometer to play it with.
+pub fn set_mode(mode: Mode) -> bool {
+    cortex_m::interrupt::free(|cs| {
+        if mode != Mode::Off && !AVAILABLE.borrow(cs).get() {
+            return false;
+        }
+        MODE.borrow(cs).set(mode);
+        true
+    })
+}
+
+pub struct Accelerometer {
+    twim: Twim<TWIM0>,
+}
+
+impl Accelerometer {
+    pub fn new(twim: TWIM0, pins: I2CInternalPins) -> Result<Self, twim::Error> {
+        let twim = Twim::new(twim, pins.into(), twim::Frequency::K100);
+        let mut accel = Self { twim };
+        accel.write(CTRL_REG1_A, CTRL_REG1_100HZ_XYZ)?;
+        accel.write(CTRL_REG4_A, CTRL_REG4_BDU_2G_HR)?;
+        Ok(accel)
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result<(), twim::Error> {
+        // Built on the stack: EasyDMA can't read from flash.
+        let buf = [register, value];
+        self.twim.write(ADDRESS, &buf)
+    }
+
+    /// Acceleration along X, Y and Z, in thousandths of
+    /// gravity.
+    pub fn read_mg(&mut self) -> Result<[i32; 3], twim::Error> {
+        let register = [OUT_X_L_A | AUTO_INCREMENT];
+        let mut buf = [0; 6];
+        self.twim.write_then_read(ADDRESS, &register, &mut buf)?;
+        // Left-justified 12-bit readings.
+        Ok(core::array::from_fn(|i| {
+            (i16::from_le_bytes([buf[2 * i], buf[2 * i + 1]]) >> 4) as i32
+        }))
+    }
+}
+
+/// Plays the theremin while it is switched on.
+pub struct Player {
+    accel: Option<Accelerometer>,
+    theremin: Theremin,
+    playing: bool,
+}
+
+impl Player {
+    /// A player on `accel`, or one that never plays if
+    /// there is none.
+    pub fn new(accel: Option<Accelerometer>) -> Self {
+        cortex_m::interrupt::free(|cs| AVAILABLE.borrow(cs).set(accel.is_some()));
+        Self {
+            accel,
+            theremin: Theremin::new(),
+            playing: false,
+        }
+    }
+
+    /// Follow the tilt, `elapsed_ms` after the last call.
+    pub fn poll(&mut self, elapsed_ms: u32) {
+        let mode = cortex_m::interrupt::free(|cs| MODE.borrow(cs).get());
+        match mode {
+            Mode::Off => {
+                if self.playing {
+                    pwm_audio::with_synth(|synth| synth.mixer.note_off(THEREMIN_KEY));
+                    self.playing = false;
+                }
+                return;
+            }
+            Mode::Free => self.theremin.set_scale(None),
+            Mode::Scale(scale) => self.theremin.set_scale(Some(scale)),
+        }
+        // Hold the note as it is until the next good reading.
+        let Some(Ok([x, y, _])) = self.accel.as_mut().map(Accelerometer::read_mg) else {
+            return;
+        };
+        if !self.playing {
+            self.theremin.reset();
+            pwm_audio::wake();
+            self.playing = true;
+        }
+        let (freq, gain) = self.theremin.update(x, y, elapsed_ms);
+        // A note already sounding just takes the new pitch
+        // and gain.
+        pwm_audio::with_synth(|synth| synth.mixer.note_on(THEREMIN_KEY, freq, gain));
+    }
+}
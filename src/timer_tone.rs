@@ -0,0 +1,101 @@
+//! Square wave on the speaker driven by the TIMER0
+//! interrupt.
+//!
+//! The timer runs periodically with its counter cleared on
+//! each compare, and the interrupt handler just toggles the
+//! speaker and moves the compare point for the next
+//! half-period. Since the counter restarts in hardware, the
+//! handler's latency does not accumulate into the pitch.
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use embedded_hal::timer::Cancel;
+use mb2_audio::square::SquareWave;
+use microbit::hal::{
+    gpio::{Output, Pin, PushPull},
+    prelude::*,
+    timer::Periodic,
+    Timer,
+};
+use microbit::pac::{self, interrupt, TIMER0};
+
+struct TimerTone {
+    timer: Timer<TIMER0, Periodic>,
+    speaker: Pin<Output<PushPull>>,
+    high_ticks: u32,
+    low_ticks: u32,
+    high: bool,
+}
+
+static TONE: Mutex<RefCell<Option<TimerTone>>> = Mutex::new(RefCell::new(None));
+
+/// Take over `timer` and `speaker` for playing `wave`. The
+/// tone is silent until [start] is called.
+pub fn init(timer: TIMER0, speaker: Pin<Output<PushPull>>, wave: &SquareWave) {
+    let (high_ticks, low_ticks) = wave.periods(Timer::<TIMER0>::TICKS_PER_SECOND);
+    let mut timer = Timer::periodic(timer);
+    timer.enable_interrupt();
+    let tone = TimerTone {
+        timer,
+        speaker,
+        high_ticks,
+        low_ticks,
+        high: false,
+    };
+    cortex_m::interrupt::free(|cs| TONE.borrow(cs).replace(Some(tone)));
+    unsafe { pac::NVIC::unmask(pac::Interrupt::TIMER0) };
+}
+
+/// Start the tone from the beginning of a period.
+pub fn start() {
+    with_tone(|tone| {
+        // A zero-length half-period (0% or 100% duty) would
+        // never toggle: there's nothing to play.
+        if tone.high_ticks == 0 || tone.low_ticks == 0 {
+            return;
+        }
+        tone.high = true;
+        tone.speaker.set_high().unwrap();
+        tone.timer.start(tone.high_ticks);
+    });
+}
+
+/// Stop the tone, leaving the speaker low.
+pub fn stop() {
+    with_tone(|tone| {
+        tone.timer.cancel().unwrap();
+        tone.high = false;
+        tone.speaker.set_low().unwrap();
+    });
+}
+
+fn with_tone(f: impl FnOnce(&mut TimerTone)) {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(tone) = TONE.borrow(cs).borrow_mut().as_mut() {
+            f(tone);
+        }
+    });
+}
+
+#[interrupt]
+fn TIMER0() {
+    with_tone(|tone| {
+        // Clear the compare event.
+        tone.timer.wait().ok();
+        tone.high = !tone.high;
+        let ticks = if tone.high {
+            tone.speaker.set_high().unwrap();
+            tone.high_ticks
+        } else {
+            tone.speaker.set_low().unwrap();
+            tone.low_ticks
+        };
+        // The COMPARE0_CLEAR short has already restarted the
+        // counter, so only the compare value needs updating.
+        // The HAL has no way to do this without also clearing
+        // the counter.
+        let timer0 = unsafe { &*TIMER0::ptr() };
+        timer0.cc[0].write(|w| unsafe { w.cc().bits(ticks) });
+    });
+}
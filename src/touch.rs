@@ -0,0 +1,67 @@
+//! The touch logo.
+//!
+//! The logo on the front of the board is a capacitive pad on
+//! P1.04, pulled up to the supply through 10MΩ on the board.
+//! [TouchLogo::is_touched] discharges it and counts how long
+//! it takes to read high again: a finger on the pad adds to
+//! its capacitance, so it charges more slowly. The counts
+//! are compared with a baseline taken when the logo is set
+//! up, which must be while nobody is touching it.
+
+use microbit::hal::{
+    gpio::{p1::P1_04, Disconnected, Floating, Input, Level, Pin},
+    prelude::*,
+};
+
+/// Most loops waited for the pad to charge.
+const MAX_COUNT: u32 = 20_000;
+
+/// Charge times taken for each reading. Interrupts can only
+/// make a charge look slower, so the quickest is used.
+const READS: usize = 3;
+
+/// Readings averaged for the baseline.
+const BASELINE_READS: u32 = 16;
+
+/// How much slower than the baseline counts as a touch, in
+/// percent.
+const TOUCH_PERCENT: u32 = 150;
+
+pub struct TouchLogo {
+    /// Only `None` while it is being discharged.
+    pin: Option<Pin<Input<Floating>>>,
+    threshold: u32,
+}
+
+impl TouchLogo {
+    pub fn new(pin: P1_04<Disconnected>) -> Self {
+        let mut logo = Self {
+            pin: Some(pin.into_floating_input().degrade()),
+            threshold: MAX_COUNT,
+        };
+        let total: u32 = (0..BASELINE_READS).map(|_| logo.charge_time()).sum();
+        let baseline = total / BASELINE_READS;
+        logo.threshold = (baseline * TOUCH_PERCENT / 100).max(baseline + 1);
+        logo
+    }
+
+    /// Loops taken for the pad to charge from empty.
+    fn charge_time(&mut self) -> u32 {
+        let mut quickest = MAX_COUNT;
+        for _ in 0..READS {
+            let pin = self.pin.take().unwrap();
+            let pin = pin.into_push_pull_output(Level::Low).into_floating_input();
+            let mut count = 0;
+            while count < MAX_COUNT && pin.is_low().unwrap() {
+                count += 1;
+            }
+            self.pin = Some(pin);
+            quickest = quickest.min(count);
+        }
+        quickest
+    }
+
+    pub fn is_touched(&mut self) -> bool {
+        self.charge_time() > self.threshold
+    }
+}
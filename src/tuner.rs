@@ -0,0 +1,66 @@
+//! Chromatic tuner on the microphone.
+//!
+//! Each [Tuner::listen] borrows the microphone from the
+//! [pwm_audio] source, records a block and finds its pitch.
+//! Recording blocks the main loop for the length of the
+//! block, but the speaker carries on from its interrupt.
+//!
+//! [pwm_audio]: crate::pwm_audio
+
+use mb2_audio::{
+    stream::SampleSource,
+    tuner::{Reading, Yin, MAX_FREQ_HZ, MIN_FREQ_HZ},
+    Sample,
+};
+
+use crate::pwm_audio;
+
+/// Samples listened to for each reading: 64ms at 16kHz,
+/// more than two periods of the lowest note.
+const BLOCK_LEN: usize = 1_024;
+
+/// Samples dropped while the microphone powers up and its
+/// DC blocker settles.
+const SETTLE_LEN: usize = 800;
+
+pub struct Tuner {
+    samples: [Sample; BLOCK_LEN],
+    last: Option<Reading>,
+}
+
+impl Tuner {
+    pub const fn new() -> Self {
+        Self {
+            samples: [0; BLOCK_LEN],
+            last: None,
+        }
+    }
+
+    /// Listen for a block and find the nearest note, if
+    /// there is one. There is none while loopback has the
+    /// microphone.
+    pub fn listen(&mut self) -> Option<Reading> {
+        let mut mic = pwm_audio::with_source(|source| source.take_mic())?;
+        mic.start();
+        let mut filled = 0;
+        while filled < SETTLE_LEN + BLOCK_LEN {
+            if let Some(sample) = mic.next_sample() {
+                if let Some(slot) = filled.checked_sub(SETTLE_LEN) {
+                    self.samples[slot] = sample;
+                }
+                filled += 1;
+            }
+        }
+        mic.stop();
+        let yin = Yin::new(mic.sample_rate(), MIN_FREQ_HZ, MAX_FREQ_HZ);
+        pwm_audio::with_source(|source| source.put_mic(mic));
+        let reading = yin.detect(&self.samples).and_then(Reading::from_freq);
+        self.last = reading.or(self.last);
+        reading
+    }
+
+    /// The last note heard, even if it has since died away.
+    pub fn last_reading(&self) -> Option<Reading> {
+        self.last
+    }
+}